@@ -1,19 +0,0 @@
-use crate::task_library;
-
-pub async fn create_task() {
-    println!("Creating a new automation task...");
-    // Placeholder logic
-    task_library::save_task("Test Task".to_string(), "Click Button X".to_string());
-    println!("Task created and saved!");
-}
-
-pub async fn run_task() {
-    println!("Running automation task...");
-    // Placeholder: load and execute a task
-    if let Some(task) = task_library::load_task("Test Task".to_string()) {
-        println!("Executing: {}", task);
-        // In the future, execute via the web_interaction module
-    } else {
-        println!("No task found!");
-    }
-}
@@ -1,11 +0,0 @@
-use webdriver::WebDriver; // Hypothetical WebDriver trait
-
-pub async fn find_element(driver: &WebDriver, selector: &str) -> Result<Element, String> {
-    match driver.find(selector).await {
-        Ok(element) => Ok(element),
-        Err(_) => {
-            println!("Fallback: Searching for element similar to '{}'", selector);
-            driver.find_near_text("Login").await // Hypothetical fallback mechanism
-        }
-    }
-}
@@ -1,5 +0,0 @@
-pub mod interaction_log;
-pub mod log_repository;
-pub mod dynamic_detection;
-pub mod event_recorder;
-pub mod event_replayer;
\ No newline at end of file
@@ -1,19 +1,59 @@
-use crate::task_library;
+use crate::browser_config::BrowserConfig;
+use crate::cancellation::RunControl;
+use crate::executor::execute_task;
+use crate::task_library::{library_dir, TaskLibrary};
+use crate::workflow::load_workflow;
+use std::collections::HashMap;
+use std::io::{self, Write};
 
+/// Add a workflow file to the task library (as a new version if a task with
+/// its name is already there)
 pub async fn create_task() {
     println!("Creating a new automation task...");
-    // Placeholder logic
-    task_library::save_task("Test Task".to_string(), "Click Button X".to_string());
-    println!("Task created and saved!");
+    let Some(path) = prompt("Workflow file: ") else {
+        return;
+    };
+    let result = load_workflow(&path)
+        .map_err(|err| err.to_string())
+        .and_then(|task| {
+            let library = TaskLibrary::open(library_dir()).map_err(|err| err.to_string())?;
+            library.save(&task, None, None).map_err(|err| err.to_string())
+        });
+    match result {
+        Ok(entry) => println!("Task '{}' saved as version {}!", entry.name, entry.latest().version),
+        Err(err) => eprintln!("❌ {}", err),
+    }
 }
 
+/// Run the latest version of a library task with its default inputs
 pub async fn run_task() {
     println!("Running automation task...");
-    // Placeholder: load and execute a task
-    if let Some(task) = task_library::load_task("Test Task".to_string()) {
-        println!("Executing: {}", task);
-        // In the future, execute via the web_interaction module
-    } else {
-        println!("No task found!");
-    }
+    let Some(name) = prompt("Task name or id: ") else {
+        return;
+    };
+    let task = match TaskLibrary::open(library_dir()).and_then(|library| library.load_task(&name, None)) {
+        Ok(task) => task,
+        Err(err) => {
+            eprintln!("❌ {}", err);
+            return;
+        }
+    };
+    let browser = match BrowserConfig::from_env() {
+        Ok(browser) => browser,
+        Err(err) => {
+            eprintln!("❌ {}", err);
+            return;
+        }
+    };
+    let report = execute_task(task, &HashMap::new(), &browser, &RunControl::new()).await;
+    println!("Task '{}' finished: {:?}", report.task, report.status);
+}
+
+fn prompt(label: &str) -> Option<String> {
+    print!("{}", label);
+    io::stdout().flush().ok()?;
+    let mut input = String::new();
+    io::stdin().read_line(&mut input).ok()?;
+    let input = input.trim();
+    (!input.is_empty()).then(|| input.to_string())
 }
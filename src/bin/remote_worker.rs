@@ -0,0 +1,97 @@
+use systematic_automation::browser_config::BrowserConfig;
+use systematic_automation::capabilities::Capabilities;
+use systematic_automation::remote::client::run_remote_worker;
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+const USAGE: &str = "Usage: remote_worker <coordinator host:port> [--name <name>] [--label <label>]... \
+[--plugin <plugin>]... [--concurrency <n>] [--no-browser]";
+
+/// `remote_worker <coordinator host:port> [flags]`; the browser and the
+/// advertised capabilities are configured with the same `SAE_*` variables as
+/// the engine, and the flags add to them
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let Some((addr, flags)) = args.split_first() else {
+        eprintln!("{}", USAGE);
+        return ExitCode::FAILURE;
+    };
+    let browser = match BrowserConfig::from_env() {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("❌ {}", err);
+            return ExitCode::FAILURE;
+        }
+    };
+    let mut capabilities = match Capabilities::from_env(&browser) {
+        Ok(capabilities) => capabilities,
+        Err(err) => {
+            eprintln!("❌ {}", err);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut name = default_name();
+    let mut flags = flags.iter();
+    while let Some(flag) = flags.next() {
+        if flag == "--no-browser" {
+            // Only runs subtasks that require `browser: none`
+            capabilities.browsers.clear();
+            continue;
+        }
+        let Some(value) = flags.next() else {
+            eprintln!("{}", USAGE);
+            return ExitCode::FAILURE;
+        };
+        match flag.as_str() {
+            "--name" => name = value.clone(),
+            "--label" => {
+                capabilities.labels.insert(value.clone());
+            }
+            "--plugin" => {
+                capabilities.plugins.insert(value.clone());
+            }
+            "--concurrency" => match value.parse() {
+                Ok(n) if n > 0 => capabilities.max_concurrency = n,
+                _ => {
+                    eprintln!("❌ --concurrency must be a positive number, got '{}'", value);
+                    return ExitCode::FAILURE;
+                }
+            },
+            _ => {
+                eprintln!("{}", USAGE);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    // Ctrl-C finishes the current jobs, then closes the browsers and leaves
+    let drain = Arc::new(AtomicBool::new(false));
+    {
+        let drain = Arc::clone(&drain);
+        tokio::spawn(async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                println!("Finishing the current job(s) before leaving (Ctrl-C again to abort)");
+                drain.store(true, Ordering::Relaxed);
+                if tokio::signal::ctrl_c().await.is_ok() {
+                    std::process::exit(130);
+                }
+            }
+        });
+    }
+
+    match run_remote_worker(addr, &name, &browser, capabilities, drain).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("❌ {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn default_name() -> String {
+    let host = std::env::var("HOSTNAME").unwrap_or_else(|_| "worker".to_string());
+    format!("{}-{}", host, std::process::id())
+}
@@ -0,0 +1,161 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::env;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BrowserKind {
+    #[default]
+    Firefox,
+    Chrome,
+}
+
+impl FromStr for BrowserKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "firefox" | "gecko" | "geckodriver" => Ok(BrowserKind::Firefox),
+            "chrome" | "chromium" | "chromedriver" => Ok(BrowserKind::Chrome),
+            other => Err(format!("unknown browser '{}' (expected firefox or chrome)", other)),
+        }
+    }
+}
+
+impl fmt::Display for BrowserKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BrowserKind::Firefox => write!(f, "firefox"),
+            BrowserKind::Chrome => write!(f, "chrome"),
+        }
+    }
+}
+
+/// How to reach the WebDriver server and which browser session to ask it for
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BrowserConfig {
+    pub webdriver_url: String,
+    pub browser: BrowserKind,
+    pub headless: bool,
+    pub window_size: Option<(u32, u32)>,
+    pub user_agent: Option<String>,
+    pub proxy: Option<String>, // host:port, used for both HTTP and HTTPS
+    pub download_dir: Option<String>,
+    pub extra_args: Vec<String>, // Passed verbatim to the browser binary
+}
+
+impl Default for BrowserConfig {
+    fn default() -> Self {
+        BrowserConfig {
+            webdriver_url: "http://localhost:4444".to_string(),
+            browser: BrowserKind::Firefox,
+            headless: false,
+            window_size: None,
+            user_agent: None,
+            proxy: None,
+            download_dir: None,
+            extra_args: Vec::new(),
+        }
+    }
+}
+
+impl BrowserConfig {
+    /// Defaults overridden by `SAE_*` environment variables, for CI:
+    /// `SAE_WEBDRIVER_URL`, `SAE_BROWSER`, `SAE_HEADLESS`, `SAE_WINDOW_SIZE`
+    /// (`1280x720`), `SAE_USER_AGENT`, `SAE_PROXY`, `SAE_DOWNLOAD_DIR` and
+    /// `SAE_BROWSER_ARGS` (space separated)
+    pub fn from_env() -> Result<Self, String> {
+        let mut config = BrowserConfig::default();
+        let var = |name: &str| env::var(name).ok().filter(|v| !v.trim().is_empty());
+
+        if let Some(url) = var("SAE_WEBDRIVER_URL") {
+            config.webdriver_url = url;
+        }
+        if let Some(browser) = var("SAE_BROWSER") {
+            config.browser = browser.parse()?;
+        }
+        if let Some(headless) = var("SAE_HEADLESS") {
+            config.headless = matches!(headless.trim(), "1" | "true" | "yes");
+        }
+        if let Some(size) = var("SAE_WINDOW_SIZE") {
+            config.window_size = Some(parse_window_size(&size)?);
+        }
+        config.user_agent = var("SAE_USER_AGENT");
+        config.proxy = var("SAE_PROXY");
+        config.download_dir = var("SAE_DOWNLOAD_DIR");
+        if let Some(args) = var("SAE_BROWSER_ARGS") {
+            config.extra_args = args.split_whitespace().map(str::to_string).collect();
+        }
+        Ok(config)
+    }
+
+    /// WebDriver capabilities for a new session
+    pub fn capabilities(&self) -> Map<String, Value> {
+        let mut caps = Map::new();
+        caps.insert("browserName".to_string(), json!(self.browser.to_string()));
+
+        if let Some(proxy) = &self.proxy {
+            caps.insert(
+                "proxy".to_string(),
+                json!({ "proxyType": "manual", "httpProxy": proxy, "sslProxy": proxy }),
+            );
+        }
+
+        let mut args = Vec::new();
+        let mut prefs = Map::new();
+        match self.browser {
+            BrowserKind::Firefox => {
+                if self.headless {
+                    args.push("-headless".to_string());
+                }
+                if let Some((width, height)) = self.window_size {
+                    args.push(format!("--width={}", width));
+                    args.push(format!("--height={}", height));
+                }
+                if let Some(user_agent) = &self.user_agent {
+                    prefs.insert("general.useragent.override".to_string(), json!(user_agent));
+                }
+                if let Some(dir) = &self.download_dir {
+                    prefs.insert("browser.download.folderList".to_string(), json!(2));
+                    prefs.insert("browser.download.dir".to_string(), json!(dir));
+                    prefs.insert("browser.download.useDownloadDir".to_string(), json!(true));
+                }
+            }
+            BrowserKind::Chrome => {
+                if self.headless {
+                    args.push("--headless=new".to_string());
+                }
+                if let Some((width, height)) = self.window_size {
+                    args.push(format!("--window-size={},{}", width, height));
+                }
+                if let Some(user_agent) = &self.user_agent {
+                    args.push(format!("--user-agent={}", user_agent));
+                }
+                if let Some(dir) = &self.download_dir {
+                    prefs.insert("download.default_directory".to_string(), json!(dir));
+                    prefs.insert("download.prompt_for_download".to_string(), json!(false));
+                }
+            }
+        }
+        args.extend(self.extra_args.iter().cloned());
+
+        let options_key = match self.browser {
+            BrowserKind::Firefox => "moz:firefoxOptions",
+            BrowserKind::Chrome => "goog:chromeOptions",
+        };
+        caps.insert(options_key.to_string(), json!({ "args": args, "prefs": prefs }));
+        caps
+    }
+}
+
+fn parse_window_size(size: &str) -> Result<(u32, u32), String> {
+    let invalid = || format!("invalid window size '{}' (expected WIDTHxHEIGHT)", size);
+    let (width, height) = size.trim().split_once(['x', 'X']).ok_or_else(invalid)?;
+    Ok((
+        width.trim().parse().map_err(|_| invalid())?,
+        height.trim().parse().map_err(|_| invalid())?,
+    ))
+}
@@ -0,0 +1,771 @@
+use crate::task_library::{resolve_workflow, LibraryError, TaskLibrary};
+use crate::task_model::{AutomationTask, ErrorPolicy, LoopSource, Step, TaskStep};
+use crate::variables::placeholders;
+use crate::workflow::{WorkflowDocument, WorkflowError, WorkflowFormat};
+use chrono::{DateTime, Utc};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+use thiserror::Error;
+
+/// Bumped whenever the bundle layout or manifest changes incompatibly
+pub const BUNDLE_FORMAT_VERSION: u32 = 1;
+
+/// Version of this engine, recorded in bundles it exports as the minimum
+/// needed to run them
+pub const ENGINE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Most a bundle may unpack to, so a small archive can't fill memory
+pub const MAX_BUNDLE_BYTES: u64 = 256 * 1024 * 1024;
+
+const MANIFEST_PATH: &str = "manifest.json";
+
+#[derive(Error, Debug)]
+pub enum BundleError {
+    #[error("Failed to access {path}: {source}")]
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    #[error("Invalid bundle: {0}")]
+    Invalid(String),
+    #[error("Bundle format version {0} is not supported")]
+    UnsupportedFormat(u32),
+    #[error("Bundle needs engine {required} or newer, this is {current}")]
+    EngineTooOld {
+        required: String,
+        current: String,
+    },
+    #[error("Checksum mismatch for {0}")]
+    Checksum(String),
+    #[error("Workflow '{0}' calls itself through its sub-workflows")]
+    Cyclic(String),
+    #[error("Cannot load sub-workflow '{reference}': {message}")]
+    SubWorkflow {
+        reference: String,
+        message: String,
+    },
+    #[error(transparent)]
+    Workflow(#[from] WorkflowError),
+    #[error(transparent)]
+    Library(#[from] LibraryError),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AssetKind {
+    Workflow,
+    Upload,   // A file typed into a file input
+    Data,     // A `ForEach` CSV source
+    Baseline, // A reference screenshot
+}
+
+impl AssetKind {
+    fn dir(&self) -> &'static str {
+        match self {
+            AssetKind::Workflow => "workflows",
+            AssetKind::Upload => "uploads",
+            AssetKind::Data => "data",
+            AssetKind::Baseline => "baselines",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleFile {
+    pub path: String, // Inside the archive, e.g. `workflows/login.yaml`
+    pub kind: AssetKind,
+    pub sha256: String,
+    pub size: u64,
+}
+
+/// `manifest.json` at the root of every bundle
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleManifest {
+    pub format_version: u32,
+    pub engine_version: String, // Oldest engine that can run the bundle
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub root: String, // Path of the main workflow
+    pub created_at: DateTime<Utc>,
+    pub files: Vec<BundleFile>,
+}
+
+/// Files to ship besides the workflows and their CSV sources
+#[derive(Debug, Clone, Default)]
+pub struct ExportOptions {
+    pub uploads: Vec<PathBuf>, // `FillForm` values spelling one of these paths exactly are pointed at the bundled copy
+    pub baselines: Vec<PathBuf>,
+}
+
+/// What to do when an imported workflow's name is already in the library
+/// with different content; identical tasks are always reused
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictPolicy {
+    #[default]
+    Rename, // Import as `name-2`, `name-3`, ...
+    NewVersion, // Save as the next version of the existing task
+    Fail,
+}
+
+#[derive(Debug, Clone)]
+pub struct ImportOptions {
+    pub assets_dir: PathBuf, // Files are unpacked into `<assets_dir>/<bundle name>/`
+    pub on_conflict: ConflictPolicy,
+    pub author: Option<String>,
+}
+
+impl Default for ImportOptions {
+    fn default() -> Self {
+        ImportOptions {
+            assets_dir: PathBuf::from("assets"),
+            on_conflict: ConflictPolicy::Rename,
+            author: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ImportSummary {
+    pub root: String, // Library name of the main workflow
+    pub workflows: Vec<(String, String)>, // (name in the bundle, name in the library)
+    pub assets: Vec<PathBuf>,
+}
+
+/// Pack `task`, every workflow it calls and the files its steps read into a
+/// gzipped tar archive at `out`.
+///
+/// Only literal references are followed: a `CallWorkflow` or CSV path built
+/// from placeholders is left as is. `FillForm` values are never bundled on
+/// their own; only files listed in `options.uploads` are.
+pub fn export_bundle(
+    task: &AutomationTask,
+    out: impl AsRef<Path>,
+    options: &ExportOptions,
+) -> Result<BundleManifest, BundleError> {
+    let out = out.as_ref();
+    let mut exporter = Exporter::default();
+    for path in &options.uploads {
+        let bundle_path = exporter.add_file(path, AssetKind::Upload)?;
+        exporter.uploads.insert(path.to_string_lossy().into_owned(), bundle_path);
+    }
+    let root = exporter.add_workflow(task.clone())?;
+    for path in &options.baselines {
+        exporter.add_file(path, AssetKind::Baseline)?;
+    }
+
+    let manifest = BundleManifest {
+        format_version: BUNDLE_FORMAT_VERSION,
+        engine_version: ENGINE_VERSION.to_string(),
+        name: task.name.clone(),
+        description: task.description.clone(),
+        root,
+        created_at: Utc::now(),
+        files: exporter.files.iter().map(|(file, _)| file.clone()).collect(),
+    };
+    let manifest_json = serde_json::to_vec_pretty(&manifest).map_err(|err| BundleError::Invalid(err.to_string()))?;
+
+    let io_error = |source| BundleError::Io {
+        path: out.to_path_buf(),
+        source,
+    };
+    let file = File::create(out).map_err(io_error)?;
+    let mut archive = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+    append(&mut archive, MANIFEST_PATH, &manifest_json).map_err(io_error)?;
+    for (file, contents) in &exporter.files {
+        append(&mut archive, &file.path, contents).map_err(io_error)?;
+    }
+    archive.into_inner().and_then(|gz| gz.finish()).map_err(io_error)?;
+
+    println!(
+        "📦 Exported '{}' with {} file(s) to {}",
+        manifest.name,
+        manifest.files.len(),
+        out.display()
+    );
+    Ok(manifest)
+}
+
+/// Unpack a bundle: verify it, save its workflows into `library` and its
+/// other files under `options.assets_dir`, pointing the imported steps at
+/// the unpacked files
+pub fn import_bundle(
+    bundle: impl AsRef<Path>,
+    library: &TaskLibrary,
+    options: &ImportOptions,
+) -> Result<ImportSummary, BundleError> {
+    let bundle = bundle.as_ref();
+    let (manifest, mut contents) = read_bundle(bundle)?;
+
+    // Nothing is written until the whole bundle has been checked, so a bad
+    // bundle leaves no half-imported files behind
+    let assets_dir = options.assets_dir.join(slug(&manifest.name));
+    let mut local_paths = HashMap::new();
+    let mut assets = Vec::new();
+    for file in manifest.files.iter().filter(|file| file.kind != AssetKind::Workflow) {
+        let target = assets_dir.join(&file.path);
+        // File inputs need absolute paths
+        let target = std::path::absolute(&target).unwrap_or(target);
+        local_paths.insert(file.path.clone(), target.to_string_lossy().into_owned());
+        assets.push((target, contents.remove(&file.path).unwrap_or_default()));
+    }
+
+    let mut workflows = BTreeMap::new();
+    for file in manifest.files.iter().filter(|file| file.kind == AssetKind::Workflow) {
+        let bytes = contents.remove(&file.path).unwrap_or_default();
+        let text = String::from_utf8(bytes).map_err(|_| BundleError::Invalid(format!("{} is not UTF-8", file.path)))?;
+        workflows.insert(file.path.clone(), WorkflowDocument::parse(&text, WorkflowFormat::Yaml)?.into_task());
+    }
+    if !workflows.contains_key(&manifest.root) {
+        return Err(BundleError::Invalid(format!("root workflow {} is missing", manifest.root)));
+    }
+
+    // Called workflows are named first so callers can refer to their final
+    // names
+    let mut order = Vec::new();
+    visit_calls(&manifest.root, &workflows, &mut HashSet::new(), &mut order)?;
+    let mut summary = ImportSummary::default();
+    let mut claimed = HashSet::new();
+    let mut imported = Vec::new();
+    for path in order {
+        let mut task = workflows[&path].clone();
+        let bundle_name = task.name.clone();
+        rewrite_references(&mut task.steps, &mut |reference| local_paths.get(reference).cloned());
+        task.name = resolve_name(library, &task, options.on_conflict, &claimed)?;
+        claimed.insert(task.name.clone());
+        local_paths.insert(path.clone(), task.name.clone());
+        if path == manifest.root {
+            summary.root = task.name.clone();
+        }
+        summary.workflows.push((bundle_name, task.name.clone()));
+        imported.push(task);
+    }
+
+    for (target, bytes) in assets {
+        if let Some(dir) = target.parent() {
+            fs::create_dir_all(dir).map_err(|source| BundleError::Io {
+                path: dir.to_path_buf(),
+                source,
+            })?;
+        }
+        fs::write(&target, bytes).map_err(|source| BundleError::Io {
+            path: target.clone(),
+            source,
+        })?;
+        summary.assets.push(target);
+    }
+    let note = format!("Imported from {}", bundle.display());
+    for task in &imported {
+        library.save(task, options.author.as_deref(), Some(&note))?;
+    }
+
+    println!(
+        "📦 Imported '{}' as '{}' with {} workflow(s) and {} file(s)",
+        manifest.name,
+        summary.root,
+        summary.workflows.len(),
+        summary.assets.len()
+    );
+    Ok(summary)
+}
+
+/// Read and verify a bundle's manifest and files
+pub fn read_bundle(bundle: &Path) -> Result<(BundleManifest, HashMap<String, Vec<u8>>), BundleError> {
+    read_bundle_within(bundle, MAX_BUNDLE_BYTES)
+}
+
+fn read_bundle_within(
+    bundle: &Path,
+    max_bytes: u64,
+) -> Result<(BundleManifest, HashMap<String, Vec<u8>>), BundleError> {
+    let io_error = |source| BundleError::Io {
+        path: bundle.to_path_buf(),
+        source,
+    };
+    let mut archive = tar::Archive::new(GzDecoder::new(File::open(bundle).map_err(io_error)?));
+    let mut contents = HashMap::new();
+    let mut budget = max_bytes;
+    for entry in archive.entries().map_err(io_error)? {
+        let entry = entry.map_err(io_error)?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let path = entry.path().map_err(io_error)?.to_string_lossy().replace('\\', "/");
+        // Never let an archive write outside the assets directory
+        if !is_safe_path(&path) {
+            return Err(BundleError::Invalid(format!("unsafe path {}", path)));
+        }
+        // The header's size can't be trusted, so the read itself is capped
+        let mut bytes = Vec::new();
+        entry.take(budget + 1).read_to_end(&mut bytes).map_err(io_error)?;
+        if bytes.len() as u64 > budget {
+            return Err(BundleError::Invalid(format!("unpacks to more than {} bytes", max_bytes)));
+        }
+        budget -= bytes.len() as u64;
+        contents.insert(path, bytes);
+    }
+
+    let manifest_bytes = contents
+        .remove(MANIFEST_PATH)
+        .ok_or_else(|| BundleError::Invalid(format!("no {}", MANIFEST_PATH)))?;
+    let manifest: BundleManifest =
+        serde_json::from_slice(&manifest_bytes).map_err(|err| BundleError::Invalid(err.to_string()))?;
+    if manifest.format_version != BUNDLE_FORMAT_VERSION {
+        return Err(BundleError::UnsupportedFormat(manifest.format_version));
+    }
+    if version_parts(&manifest.engine_version) > version_parts(ENGINE_VERSION) {
+        return Err(BundleError::EngineTooOld {
+            required: manifest.engine_version.clone(),
+            current: ENGINE_VERSION.to_string(),
+        });
+    }
+    for file in &manifest.files {
+        if !is_safe_path(&file.path) {
+            return Err(BundleError::Invalid(format!("unsafe path {}", file.path)));
+        }
+        let bytes = contents
+            .get(&file.path)
+            .ok_or_else(|| BundleError::Invalid(format!("{} is listed but missing", file.path)))?;
+        if bytes.len() as u64 != file.size || sha256(bytes) != file.sha256 {
+            return Err(BundleError::Checksum(file.path.clone()));
+        }
+    }
+    Ok((manifest, contents))
+}
+
+#[derive(Default)]
+struct Exporter {
+    files: Vec<(BundleFile, Vec<u8>)>,
+    added: HashMap<String, String>, // Source path or workflow reference -> path in the bundle
+    uploads: HashMap<String, String>, // Declared upload path -> path in the bundle
+    in_progress: HashSet<String>,   // Workflows being added, to catch cycles
+}
+
+impl Exporter {
+    fn add_workflow(&mut self, mut task: AutomationTask) -> Result<String, BundleError> {
+        self.in_progress.insert(task.name.clone());
+        let mut failure = None;
+        walk_steps(&mut task.steps, &mut |step| {
+            if failure.is_some() {
+                return;
+            }
+            let result = match step {
+                TaskStep::CallWorkflow { workflow, .. } if is_literal(workflow) => self.add_called(workflow),
+                TaskStep::ForEach { items: LoopSource::Csv(path), .. } if is_literal(path) => {
+                    self.add_file(Path::new(path), AssetKind::Data)
+                }
+                TaskStep::FillForm(_, value) if self.uploads.contains_key(value.as_str()) => {
+                    Ok(self.uploads[value.as_str()].clone())
+                }
+                _ => return,
+            };
+            match result {
+                Ok(bundle_path) => match step {
+                    TaskStep::CallWorkflow { workflow: reference, .. }
+                    | TaskStep::ForEach { items: LoopSource::Csv(reference), .. }
+                    | TaskStep::FillForm(_, reference) => *reference = bundle_path,
+                    _ => {}
+                },
+                Err(err) => failure = Some(err),
+            }
+        });
+        self.in_progress.remove(&task.name);
+        if let Some(err) = failure {
+            return Err(err);
+        }
+
+        let text = WorkflowDocument::from_task(&task).render(WorkflowFormat::Yaml)?;
+        let path = self.unique_path(AssetKind::Workflow, &format!("{}.yaml", slug(&task.name)));
+        self.push(path.clone(), AssetKind::Workflow, text.into_bytes());
+        Ok(path)
+    }
+
+    fn add_called(&mut self, reference: &str) -> Result<String, BundleError> {
+        if let Some(path) = self.added.get(reference) {
+            return Ok(path.clone());
+        }
+        let task = resolve_workflow(reference).map_err(|message| BundleError::SubWorkflow {
+            reference: reference.to_string(),
+            message,
+        })?;
+        if self.in_progress.contains(&task.name) {
+            return Err(BundleError::Cyclic(task.name));
+        }
+        let path = self.add_workflow(task)?;
+        self.added.insert(reference.to_string(), path.clone());
+        Ok(path)
+    }
+
+    fn add_file(&mut self, source: &Path, kind: AssetKind) -> Result<String, BundleError> {
+        let key = source.to_string_lossy().into_owned();
+        if let Some(path) = self.added.get(&key) {
+            return Ok(path.clone());
+        }
+        let bytes = fs::read(source).map_err(|err| BundleError::Io {
+            path: source.to_path_buf(),
+            source: err,
+        })?;
+        let file_name = source
+            .file_name()
+            .map(|name| name.to_string_lossy().replace(['\\', ':'], "_"))
+            .unwrap_or_else(|| "file".to_string());
+        let path = self.unique_path(kind, &file_name);
+        self.push(path.clone(), kind, bytes);
+        self.added.insert(key, path.clone());
+        Ok(path)
+    }
+
+    fn unique_path(&self, kind: AssetKind, file_name: &str) -> String {
+        let (stem, extension) = match file_name.rsplit_once('.') {
+            Some((stem, extension)) if !stem.is_empty() => (stem, format!(".{}", extension)),
+            _ => (file_name, String::new()),
+        };
+        let taken = |path: &str| self.files.iter().any(|(file, _)| file.path == path);
+        let mut path = format!("{}/{}", kind.dir(), file_name);
+        let mut n = 2;
+        while taken(&path) {
+            path = format!("{}/{}-{}{}", kind.dir(), stem, n, extension);
+            n += 1;
+        }
+        path
+    }
+
+    fn push(&mut self, path: String, kind: AssetKind, contents: Vec<u8>) {
+        let file = BundleFile {
+            path,
+            kind,
+            sha256: sha256(&contents),
+            size: contents.len() as u64,
+        };
+        self.files.push((file, contents));
+    }
+}
+
+/// Call `f` on every step, including nested bodies and fallbacks
+fn walk_steps(steps: &mut [Step], f: &mut impl FnMut(&mut TaskStep)) {
+    for step in steps {
+        f(&mut step.action);
+        for body in step.action.bodies_mut() {
+            walk_steps(body, f);
+        }
+        if let ErrorPolicy::Fallback(fallback) = &mut step.on_error {
+            walk_steps(fallback, f);
+        }
+    }
+}
+
+/// Replace every file or workflow reference `lookup` knows a new value for
+fn rewrite_references(steps: &mut [Step], lookup: &mut impl FnMut(&str) -> Option<String>) {
+    walk_steps(steps, &mut |step| {
+        if let TaskStep::CallWorkflow { workflow: reference, .. }
+        | TaskStep::ForEach { items: LoopSource::Csv(reference), .. }
+        | TaskStep::FillForm(_, reference) = step
+        {
+            if let Some(replacement) = lookup(reference) {
+                *reference = replacement;
+            }
+        }
+    });
+}
+
+/// Depth-first over `CallWorkflow` references, callees before callers
+fn visit_calls(
+    path: &str,
+    workflows: &BTreeMap<String, AutomationTask>,
+    visiting: &mut HashSet<String>,
+    order: &mut Vec<String>,
+) -> Result<(), BundleError> {
+    if order.iter().any(|done| done == path) {
+        return Ok(());
+    }
+    let Some(task) = workflows.get(path) else {
+        return Err(BundleError::Invalid(format!("workflow {} is missing", path)));
+    };
+    if !visiting.insert(path.to_string()) {
+        return Err(BundleError::Cyclic(task.name.clone()));
+    }
+    let mut called = Vec::new();
+    let mut steps = task.steps.clone();
+    walk_steps(&mut steps, &mut |step| {
+        if let TaskStep::CallWorkflow { workflow, .. } = step {
+            if workflows.contains_key(workflow.as_str()) {
+                called.push(workflow.clone());
+            }
+        }
+    });
+    for callee in called {
+        visit_calls(&callee, workflows, visiting, order)?;
+    }
+    visiting.remove(path);
+    order.push(path.to_string());
+    Ok(())
+}
+
+/// The library name to import `task` under, avoiding names already
+/// `claimed` by other workflows of the same bundle
+fn resolve_name(
+    library: &TaskLibrary,
+    task: &AutomationTask,
+    policy: ConflictPolicy,
+    claimed: &HashSet<String>,
+) -> Result<String, BundleError> {
+    let claimed_here = claimed.contains(&task.name);
+    let existing = match library.get(&task.name) {
+        Ok(entry) => Some(entry),
+        Err(LibraryError::NotFound(_)) => None,
+        Err(err) => return Err(err.into()),
+    };
+    if !claimed_here && existing.is_none_or(|entry| entry.is_latest(task)) {
+        return Ok(task.name.clone());
+    }
+    match policy {
+        ConflictPolicy::NewVersion if !claimed_here => Ok(task.name.clone()),
+        ConflictPolicy::Fail => Err(LibraryError::NameTaken(task.name.clone()).into()),
+        ConflictPolicy::Rename | ConflictPolicy::NewVersion => {
+            let mut n = 2;
+            loop {
+                let name = format!("{}-{}", task.name, n);
+                if claimed.contains(&name) {
+                    n += 1;
+                    continue;
+                }
+                match library.get(&name) {
+                    Err(LibraryError::NotFound(_)) => return Ok(name),
+                    Ok(entry) if entry.is_latest(task) => return Ok(name),
+                    Ok(_) => n += 1,
+                    Err(err) => return Err(err.into()),
+                }
+            }
+        }
+    }
+}
+
+fn append(archive: &mut tar::Builder<GzEncoder<File>>, path: &str, contents: &[u8]) -> io::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(contents.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(Utc::now().timestamp().max(0) as u64);
+    header.set_cksum();
+    archive.append_data(&mut header, path, contents)
+}
+
+/// A relative, `/`-separated path that stays inside the directory it is
+/// joined to on any platform
+fn is_safe_path(path: &str) -> bool {
+    !path.is_empty()
+        && path.split('/').all(|part| {
+            !part.is_empty()
+                && part != "."
+                && part != ".."
+                && !part.contains(['\\', ':'])
+                && Path::new(part).components().all(|part| matches!(part, Component::Normal(_)))
+        })
+}
+
+fn sha256(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+fn is_literal(value: &str) -> bool {
+    placeholders(value).is_ok_and(|names| names.is_empty())
+}
+
+/// `1.2.3` as comparable numbers; anything unparsable counts as 0
+fn version_parts(version: &str) -> Vec<u64> {
+    version.split('.').map(|part| part.parse().unwrap_or(0)).collect()
+}
+
+/// A file-system friendly version of a task name
+fn slug(name: &str) -> String {
+    let mut slug = String::new();
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug = slug.trim_end_matches('-');
+    if slug.is_empty() {
+        "workflow".to_string()
+    } else {
+        slug.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::target::Target;
+
+    fn temp_bundle(name: &str, entries: &[(&str, &[u8])]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("sae-bundle-{}-{}.tar.gz", std::process::id(), name));
+        let mut archive = tar::Builder::new(GzEncoder::new(File::create(&path).unwrap(), Compression::default()));
+        for (entry, contents) in entries {
+            append(&mut archive, entry, contents).unwrap();
+        }
+        archive.into_inner().unwrap().finish().unwrap();
+        path
+    }
+
+    fn manifest(files: Vec<BundleFile>) -> Vec<u8> {
+        serde_json::to_vec(&BundleManifest {
+            format_version: BUNDLE_FORMAT_VERSION,
+            engine_version: ENGINE_VERSION.to_string(),
+            name: "test".to_string(),
+            description: None,
+            root: "workflows/main.yaml".to_string(),
+            created_at: Utc::now(),
+            files,
+        })
+        .unwrap()
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sae-bundle-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn bundle_file(path: &str, kind: AssetKind, contents: &[u8]) -> BundleFile {
+        BundleFile {
+            path: path.to_string(),
+            kind,
+            sha256: sha256(contents),
+            size: contents.len() as u64,
+        }
+    }
+
+    fn assert_unsafe(result: Result<(BundleManifest, HashMap<String, Vec<u8>>), BundleError>) {
+        match result {
+            Err(BundleError::Invalid(message)) => assert!(message.starts_with("unsafe path"), "{}", message),
+            other => panic!("expected an unsafe path, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn accepts_relative_paths() {
+        for path in ["manifest.json", "workflows/login.yaml", "uploads/a b/cv.pdf", "data/..csv"] {
+            assert!(is_safe_path(path), "{}", path);
+        }
+    }
+
+    #[test]
+    fn rejects_paths_that_leave_the_assets_directory() {
+        for path in [
+            "", "../evil", "a/../../evil", "..\\evil", "a\\..\\evil", "/etc/passwd", "C:evil", "C:/evil", "./a",
+            "a//b", "a/",
+        ] {
+            assert!(!is_safe_path(path), "{}", path);
+        }
+    }
+
+    #[test]
+    fn read_rejects_unsafe_archive_entries() {
+        let path = temp_bundle("backslash", &[("..\\evil", b"pwned")]);
+        assert_unsafe(read_bundle(&path));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn read_rejects_unsafe_manifest_paths() {
+        let file = BundleFile {
+            path: "../evil".to_string(),
+            kind: AssetKind::Upload,
+            sha256: sha256(b"pwned"),
+            size: 5,
+        };
+        let path = temp_bundle("manifest", &[(MANIFEST_PATH, &manifest(vec![file]))]);
+        assert_unsafe(read_bundle(&path));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn read_checks_checksums() {
+        let file = BundleFile {
+            path: "uploads/cv.pdf".to_string(),
+            kind: AssetKind::Upload,
+            sha256: sha256(b"original"),
+            size: 8,
+        };
+        let path = temp_bundle("checksum", &[(MANIFEST_PATH, &manifest(vec![file])), ("uploads/cv.pdf", b"tampered")]);
+        assert!(matches!(read_bundle(&path), Err(BundleError::Checksum(file)) if file == "uploads/cv.pdf"));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn read_refuses_bundles_that_unpack_too_large() {
+        let path = temp_bundle("large", &[("uploads/big.bin", b"0123456789")]);
+        match read_bundle_within(&path, 8) {
+            Err(BundleError::Invalid(message)) => assert_eq!(message, "unpacks to more than 8 bytes"),
+            other => panic!("expected the size limit, got {:?}", other.map(|_| ())),
+        }
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn export_bundles_declared_uploads_only() {
+        let dir = temp_dir("export");
+        let cv = dir.join("cv.pdf");
+        let notes = dir.join("notes.txt");
+        fs::write(&cv, b"%PDF").unwrap();
+        fs::write(&notes, b"typed, not uploaded").unwrap();
+        let mut task = AutomationTask::new();
+        task.name = "Apply".to_string();
+        task.add_step(TaskStep::FillForm(Target::css("#cv"), cv.to_string_lossy().into_owned()));
+        task.add_step(TaskStep::FillForm(Target::css("#notes"), notes.to_string_lossy().into_owned()));
+
+        let out = dir.join("apply.tar.gz");
+        let options = ExportOptions {
+            uploads: vec![cv.clone()],
+            ..ExportOptions::default()
+        };
+        let manifest = export_bundle(&task, &out, &options).unwrap();
+        let paths: Vec<_> = manifest.files.iter().map(|file| (file.path.as_str(), file.kind)).collect();
+        assert_eq!(
+            paths,
+            [("uploads/cv.pdf", AssetKind::Upload), ("workflows/apply.yaml", AssetKind::Workflow)]
+        );
+
+        let (_, contents) = read_bundle(&out).unwrap();
+        let workflow = String::from_utf8(contents["workflows/apply.yaml"].clone()).unwrap();
+        assert!(workflow.contains("uploads/cv.pdf"), "{}", workflow);
+        assert!(workflow.contains(&*notes.to_string_lossy()), "{}", workflow);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn import_writes_nothing_from_a_bad_bundle() {
+        let dir = temp_dir("import");
+        let upload: &[u8] = b"%PDF";
+        let workflow: &[u8] = b"steps: [";
+        let files = vec![
+            bundle_file("uploads/cv.pdf", AssetKind::Upload, upload),
+            bundle_file("workflows/main.yaml", AssetKind::Workflow, workflow),
+        ];
+        let path = temp_bundle(
+            "invalid-workflow",
+            &[(MANIFEST_PATH, &manifest(files)), ("uploads/cv.pdf", upload), ("workflows/main.yaml", workflow)],
+        );
+        let library = TaskLibrary::open(dir.join("library")).unwrap();
+        let options = ImportOptions {
+            assets_dir: dir.join("assets"),
+            ..ImportOptions::default()
+        };
+
+        assert!(matches!(import_bundle(&path, &library, &options), Err(BundleError::Workflow(_))));
+        assert!(!dir.join("assets").exists());
+        assert!(library.list().unwrap().is_empty());
+        fs::remove_file(path).unwrap();
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
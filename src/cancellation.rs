@@ -0,0 +1,219 @@
+use parking_lot::Mutex;
+use std::future::Future;
+use std::sync::{Arc, Weak};
+use thiserror::Error;
+use tokio::sync::watch;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunState {
+    Running,
+    Paused,    // Finish the current step, then wait for `resume`
+    Cancelled, // Stop as soon as possible; can't be undone
+}
+
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("Cancelled")]
+pub struct Cancelled;
+
+struct Inner {
+    state: watch::Sender<RunState>,
+    children: Mutex<Vec<Weak<Inner>>>,
+}
+
+impl Inner {
+    fn set(&self, state: RunState) {
+        self.state.send_if_modified(|current| {
+            let changed = *current != RunState::Cancelled && *current != state;
+            if changed {
+                *current = state;
+            }
+            changed
+        });
+        self.children.lock().retain(|child| match child.upgrade() {
+            Some(child) => {
+                child.set(state);
+                true
+            }
+            None => false,
+        });
+    }
+}
+
+/// Cancellation token that can also pause a run between steps. Clones share
+/// the same state; a `child` follows its parent but can be cancelled or
+/// paused on its own, so one job can be stopped without its worker.
+#[derive(Clone)]
+pub struct RunControl {
+    inner: Arc<Inner>,
+}
+
+impl Default for RunControl {
+    fn default() -> Self {
+        RunControl {
+            inner: Arc::new(Inner {
+                state: watch::channel(RunState::Running).0,
+                children: Mutex::new(Vec::new()),
+            }),
+        }
+    }
+}
+
+impl RunControl {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A control that starts in this one's state and follows its changes
+    pub fn child(&self) -> Self {
+        let child = RunControl::new();
+        child.inner.set(self.state());
+        self.inner.children.lock().push(Arc::downgrade(&child.inner));
+        child
+    }
+
+    pub fn cancel(&self) {
+        self.inner.set(RunState::Cancelled);
+    }
+
+    pub fn pause(&self) {
+        self.inner.set(RunState::Paused);
+    }
+
+    pub fn resume(&self) {
+        self.inner.set(RunState::Running);
+    }
+
+    pub fn state(&self) -> RunState {
+        *self.inner.state.borrow()
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.state() == RunState::Cancelled
+    }
+
+    /// Wait for the state to change from `seen`
+    pub async fn changed(&self, seen: RunState) -> RunState {
+        let mut state = self.inner.state.subscribe();
+        let _ = state.wait_for(|state| *state != seen).await;
+        let current = *state.borrow();
+        current
+    }
+
+    /// Resolves once cancelled
+    pub async fn cancelled(&self) {
+        let mut state = self.inner.state.subscribe();
+        let _ = state.wait_for(|state| *state == RunState::Cancelled).await;
+    }
+
+    /// Call between steps: waits while paused, and fails once cancelled
+    pub async fn checkpoint(&self) -> Result<(), Cancelled> {
+        let mut state = self.inner.state.subscribe();
+        if *state.borrow() == RunState::Paused {
+            println!("⏸️ Paused");
+            let _ = state.wait_for(|state| *state != RunState::Paused).await;
+            if *state.borrow() == RunState::Running {
+                println!("▶️ Resumed");
+            }
+        }
+        let current = *state.borrow();
+        match current {
+            RunState::Cancelled => Err(Cancelled),
+            _ => Ok(()),
+        }
+    }
+
+    /// Run `future` unless cancelled first. Cancelling drops it, which
+    /// abandons any WebDriver command it was waiting on. A future that
+    /// finishes once cancelled (e.g. by stopping at its own `checkpoint`)
+    /// counts as cancelled too.
+    pub async fn run<F: Future>(&self, future: F) -> Result<F::Output, Cancelled> {
+        tokio::select! {
+            output = future => match self.state() {
+                RunState::Cancelled => Err(Cancelled),
+                _ => Ok(output),
+            },
+            _ = self.cancelled() => Err(Cancelled),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::time::timeout;
+
+    const SHORT: Duration = Duration::from_millis(50);
+
+    #[test]
+    fn children_follow_their_parent() {
+        let run = RunControl::new();
+        let job = run.child();
+        let other = run.child();
+        run.pause();
+        assert_eq!((job.state(), other.state()), (RunState::Paused, RunState::Paused));
+        run.resume();
+        assert_eq!(job.state(), RunState::Running);
+
+        job.cancel();
+        assert!(job.is_cancelled());
+        assert_eq!(run.state(), RunState::Running, "cancelling a job leaves its run alone");
+        assert_eq!(other.state(), RunState::Running);
+
+        run.cancel();
+        assert!(other.is_cancelled());
+        assert!(run.child().is_cancelled(), "a child of a cancelled run starts cancelled");
+    }
+
+    #[test]
+    fn cancelling_is_final() {
+        let run = RunControl::new();
+        run.cancel();
+        run.resume();
+        run.pause();
+        assert!(run.is_cancelled());
+    }
+
+    #[test]
+    fn dropped_children_are_forgotten() {
+        let run = RunControl::new();
+        drop(run.child());
+        let _kept = run.child();
+        run.pause();
+        assert_eq!(run.inner.children.lock().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn checkpoint_waits_while_paused() {
+        let run = RunControl::new();
+        assert_eq!(run.checkpoint().await, Ok(()));
+
+        run.pause();
+        assert!(timeout(SHORT, run.checkpoint()).await.is_err(), "still paused");
+        let (resumed, ()) = tokio::join!(run.checkpoint(), async { run.resume() });
+        assert_eq!(resumed, Ok(()));
+
+        run.pause();
+        let (cancelled, ()) = tokio::join!(run.checkpoint(), async { run.cancel() });
+        assert_eq!(cancelled, Err(Cancelled));
+    }
+
+    #[tokio::test]
+    async fn run_abandons_the_future_when_cancelled() {
+        let run = RunControl::new();
+        let (result, ()) = tokio::join!(run.run(std::future::pending::<()>()), async { run.cancel() });
+        assert_eq!(result, Err(Cancelled));
+    }
+
+    #[tokio::test]
+    async fn run_counts_a_future_that_finished_after_cancelling_as_cancelled() {
+        let run = RunControl::new();
+        assert_eq!(run.run(async { 1 }).await, Ok(1));
+        let job = run.child();
+        let stopped = job.run(async {
+            job.cancel();
+            job.checkpoint().await
+        });
+        assert_eq!(stopped.await, Err(Cancelled));
+    }
+}
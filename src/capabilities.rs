@@ -0,0 +1,230 @@
+use crate::browser_config::{BrowserConfig, BrowserKind};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::env;
+use std::fmt;
+
+/// Which browser a subtask needs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BrowserNeed {
+    #[default]
+    Any, // Some browser, whichever
+    None, // Doesn't touch the browser, so any worker will do
+    Firefox,
+    Chrome,
+}
+
+impl BrowserNeed {
+    fn kind(self) -> Option<BrowserKind> {
+        match self {
+            BrowserNeed::Firefox => Some(BrowserKind::Firefox),
+            BrowserNeed::Chrome => Some(BrowserKind::Chrome),
+            BrowserNeed::Any | BrowserNeed::None => None,
+        }
+    }
+}
+
+/// What a subtask needs from the worker that runs it
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Requirements {
+    pub browser: BrowserNeed,
+    #[serde(skip_serializing_if = "BTreeSet::is_empty")]
+    pub plugins: BTreeSet<String>,
+    #[serde(skip_serializing_if = "BTreeSet::is_empty")]
+    pub labels: BTreeSet<String>,
+}
+
+impl Requirements {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn browser(mut self, browser: BrowserNeed) -> Self {
+        self.browser = browser;
+        self
+    }
+
+    pub fn plugin(mut self, plugin: impl Into<String>) -> Self {
+        self.plugins.insert(plugin.into());
+        self
+    }
+
+    pub fn label(mut self, label: impl Into<String>) -> Self {
+        self.labels.insert(label.into());
+        self
+    }
+
+    /// What a worker needs to run both; `None` if they ask for different
+    /// browsers
+    pub fn merge(&self, other: &Requirements) -> Option<Requirements> {
+        let browser = match (self.browser, other.browser) {
+            (a, b) if a == b => a,
+            (BrowserNeed::None, other) | (other, BrowserNeed::None) => other,
+            (BrowserNeed::Any, other) | (other, BrowserNeed::Any) => other,
+            _ => return None,
+        };
+        Some(Requirements {
+            browser,
+            plugins: self.plugins.union(&other.plugins).cloned().collect(),
+            labels: self.labels.union(&other.labels).cloned().collect(),
+        })
+    }
+}
+
+impl fmt::Display for Requirements {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        match self.browser {
+            BrowserNeed::Any => parts.push("a browser".to_string()),
+            BrowserNeed::None => {}
+            kind => parts.push(format!("{:?}", kind).to_lowercase()),
+        }
+        if !self.plugins.is_empty() {
+            parts.push(format!("plugins {:?}", self.plugins));
+        }
+        if !self.labels.is_empty() {
+            parts.push(format!("labels {:?}", self.labels));
+        }
+        if parts.is_empty() {
+            f.write_str("nothing")
+        } else {
+            f.write_str(&parts.join(", "))
+        }
+    }
+}
+
+/// What a worker offers, announced when it joins. A local worker always runs
+/// one job at a time, whatever `max_concurrency` says; the pool's size sets
+/// how many run locally.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Capabilities {
+    pub browsers: BTreeSet<BrowserKind>,
+    pub plugins: BTreeSet<String>,
+    pub labels: BTreeSet<String>,
+    pub max_concurrency: usize, // Jobs a remote worker runs at once, each in its own browser
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Capabilities {
+            browsers: BTreeSet::new(),
+            plugins: BTreeSet::new(),
+            labels: BTreeSet::new(),
+            max_concurrency: 1,
+        }
+    }
+}
+
+impl Capabilities {
+    /// A worker driving `browser`, with `SAE_WORKER_PLUGINS` and
+    /// `SAE_WORKER_LABELS` (comma separated) and `SAE_WORKER_CONCURRENCY`
+    /// (remote workers only)
+    pub fn from_env(browser: &BrowserConfig) -> Result<Self, String> {
+        let var = |name: &str| env::var(name).ok().filter(|v| !v.trim().is_empty());
+        let list = |value: String| -> BTreeSet<String> {
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(str::to_string)
+                .collect()
+        };
+
+        let mut capabilities = Capabilities {
+            browsers: BTreeSet::from([browser.browser]),
+            ..Capabilities::default()
+        };
+        if let Some(plugins) = var("SAE_WORKER_PLUGINS") {
+            capabilities.plugins = list(plugins);
+        }
+        if let Some(labels) = var("SAE_WORKER_LABELS") {
+            capabilities.labels = list(labels);
+        }
+        if let Some(concurrency) = var("SAE_WORKER_CONCURRENCY") {
+            capabilities.max_concurrency = concurrency
+                .trim()
+                .parse()
+                .ok()
+                .filter(|&n| n > 0)
+                .ok_or_else(|| format!("SAE_WORKER_CONCURRENCY must be a positive number, got '{}'", concurrency))?;
+        }
+        Ok(capabilities)
+    }
+
+    pub fn satisfies(&self, requirements: &Requirements) -> bool {
+        let browser = match requirements.browser {
+            BrowserNeed::None => true,
+            BrowserNeed::Any => !self.browsers.is_empty(),
+            need => need.kind().is_some_and(|kind| self.browsers.contains(&kind)),
+        };
+        browser && requirements.plugins.is_subset(&self.plugins) && requirements.labels.is_subset(&self.labels)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn worker(browsers: &[BrowserKind]) -> Capabilities {
+        Capabilities {
+            browsers: browsers.iter().copied().collect(),
+            plugins: BTreeSet::from(["pdf".to_string()]),
+            labels: BTreeSet::from(["eu".to_string(), "gpu".to_string()]),
+            ..Capabilities::default()
+        }
+    }
+
+    #[test]
+    fn merge_picks_the_stricter_browser() {
+        let need = |browser| Requirements::default().browser(browser);
+        let merged = |a, b| need(a).merge(&need(b)).map(|requirements| requirements.browser);
+        assert_eq!(merged(BrowserNeed::None, BrowserNeed::None), Some(BrowserNeed::None));
+        assert_eq!(merged(BrowserNeed::None, BrowserNeed::Any), Some(BrowserNeed::Any));
+        assert_eq!(merged(BrowserNeed::Any, BrowserNeed::Chrome), Some(BrowserNeed::Chrome));
+        assert_eq!(merged(BrowserNeed::Firefox, BrowserNeed::None), Some(BrowserNeed::Firefox));
+        assert_eq!(merged(BrowserNeed::Chrome, BrowserNeed::Chrome), Some(BrowserNeed::Chrome));
+        assert_eq!(merged(BrowserNeed::Firefox, BrowserNeed::Chrome), None);
+    }
+
+    #[test]
+    fn merge_needs_every_plugin_and_label() {
+        let a = Requirements::default().plugin("pdf").label("eu");
+        let b = Requirements::default().plugin("ocr").label("eu");
+        let merged = a.merge(&b).unwrap();
+        assert_eq!(merged.plugins, BTreeSet::from(["ocr".to_string(), "pdf".to_string()]));
+        assert_eq!(merged.labels, BTreeSet::from(["eu".to_string()]));
+    }
+
+    #[test]
+    fn satisfies_browser_needs() {
+        let chrome = worker(&[BrowserKind::Chrome]);
+        let browserless = worker(&[]);
+        let need = |browser| Requirements::default().browser(browser);
+        assert!(chrome.satisfies(&need(BrowserNeed::Any)));
+        assert!(chrome.satisfies(&need(BrowserNeed::Chrome)));
+        assert!(!chrome.satisfies(&need(BrowserNeed::Firefox)));
+        assert!(chrome.satisfies(&need(BrowserNeed::None)));
+        assert!(browserless.satisfies(&need(BrowserNeed::None)));
+        assert!(!browserless.satisfies(&need(BrowserNeed::Any)));
+        assert!(!browserless.satisfies(&need(BrowserNeed::Chrome)));
+    }
+
+    #[test]
+    fn satisfies_only_with_every_plugin_and_label() {
+        let chrome = worker(&[BrowserKind::Chrome]);
+        assert!(chrome.satisfies(&Requirements::default().plugin("pdf").label("gpu")));
+        assert!(!chrome.satisfies(&Requirements::default().plugin("ocr")));
+        assert!(!chrome.satisfies(&Requirements::default().label("us")));
+    }
+
+    #[test]
+    fn describes_requirements() {
+        assert_eq!(Requirements::default().to_string(), "a browser");
+        assert_eq!(Requirements::default().browser(BrowserNeed::None).to_string(), "nothing");
+        let requirements = Requirements::default().browser(BrowserNeed::Firefox).plugin("pdf");
+        assert_eq!(requirements.to_string(), r#"firefox, plugins {"pdf"}"#);
+    }
+}
@@ -1,42 +1,220 @@
 use tokio::sync::mpsc;
-use crate::worker::worker_node;
-use crate::task_model::{Subtask, TaskResult};
+use crate::browser_config::BrowserConfig;
+use crate::cancellation::{RunControl, RunState};
+use crate::capabilities::{Capabilities, Requirements};
+use crate::job_graph::{GraphError, GraphNode, GraphRun, JobGraph, NodeReport};
+use crate::job_queue::JobQueue;
+use crate::remote;
+use crate::task_model::{Job, Subtask, TaskStep};
+use crate::worker_pool::{PoolConfig, WorkerPool};
+use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::sync::Mutex;
-use tokio::sync::mpsc::{Receiver, Sender};
+use std::time::{Duration, Instant};
 
+/// How long a job waits for a worker that can run it to connect
+const UNMATCHED_GRACE: Duration = Duration::from_secs(60);
 
-pub async fn task_distributor() {
-    let (task_tx, task_rx) = mpsc::channel(100);
-    let (result_tx, mut result_rx) = mpsc::channel(100);
+/// Visited when no other sites are given
+pub const DEFAULT_SITES: [&str; 5] = [
+    "https://example.com",
+    "https://rust-lang.org",
+    "https://tokio.rs",
+    "https://crates.io",
+    "https://docs.rs",
+];
+
+/// Visit `sites`, one job each. Jobs left in `queue` by an earlier run are
+/// finished instead.
+pub async fn task_distributor(
+    browser: BrowserConfig,
+    capabilities: Capabilities,
+    queue: JobQueue,
+    sites: &[String],
+    pool: PoolConfig,
+    control: RunControl,
+) {
+    let mut graph = JobGraph::new();
 
-    // Share a single `Receiver` for all workers
-    let shared_task_rx = Arc::new(Mutex::new(task_rx));
+    if queue.is_empty() {
+        // One job per website: categorizing has to happen in the browser
+        // that opened the site, so both subtasks travel together
+        for (i, url) in sites.iter().enumerate() {
+            let open_website_task = Subtask {
+                id: 0, // Assigned when the graph is submitted
+                description: format!("Open website: {}", url),
+                step: TaskStep::OpenWebsite(url.to_string()).into(),
+                requires: Requirements::default(),
+            };
 
-    // Launch worker nodes
-    for i in 0..3 {
-        let worker_id = i;
-        let task_rx_clone = Arc::clone(&shared_task_rx); // Use the shared receiver
-        let result_tx_clone = result_tx.clone();
+            let categorize_task = Subtask {
+                id: 0,
+                description: format!("Categorize elements on: {}", url),
+                step: TaskStep::CategorizeElements.into(),
+                requires: Requirements::default(),
+            };
+
+            let job = Job::new(format!("Visit {}", url))
+                .then(open_website_task)
+                .then(categorize_task);
+            graph = graph.node(GraphNode::new(format!("site-{}", i + 1), job));
+        }
+    }
 
-        tokio::spawn(async move {
-            worker_node(worker_id, task_rx_clone, result_tx_clone).await;
-        });
+    if let Err(err) = run_graph(browser, capabilities, queue, graph, pool, control).await {
+        eprintln!("❌ {}", err);
     }
+}
+
+/// Run `graph` on a pool of workers, each with its own browser session, and
+/// report how every node ended. Jobs already in `queue` run alongside it.
+/// With `pool.listen` set, remote workers can join in as well. Local workers
+/// offer `capabilities`; a job that no worker can satisfy fails its node.
+/// Pausing `control` holds every worker between subtasks; cancelling it
+/// cancels every node that hasn't finished.
+pub async fn run_graph(
+    browser: BrowserConfig,
+    capabilities: Capabilities,
+    mut queue: JobQueue,
+    graph: JobGraph,
+    pool: PoolConfig,
+    control: RunControl,
+) -> Result<Vec<NodeReport>, GraphError> {
+    let (result_tx, mut result_rx) = mpsc::channel(100);
 
-    // Simulate task creation
-    for i in 0..10 {
-        let task = Subtask {
-            id: i,
-            description: format!("Task {}", i),
-        };
-        task_tx.send(task).await.unwrap();
+    let mut run = GraphRun::submit(graph, &mut queue)?;
+    if run.is_finished() {
+        queue.close(); // Workers exit once every job is acked
     }
 
-    drop(task_tx); // Close task sender after all tasks are sent
+    // Share the queue between all workers, who take jobs by priority and
+    // tenants' fair shares and quotas
+    let queue = Arc::new(parking_lot::Mutex::new(queue.scheduling(pool.scheduling.clone())));
+    let listen = pool.listen.clone();
+    let mut pool = WorkerPool::start(
+        pool,
+        browser,
+        capabilities,
+        Arc::clone(&queue),
+        result_tx.clone(),
+        control.clone(),
+    );
+    let server = match &listen {
+        Some(addr) => match remote::server::listen(
+            addr,
+            Arc::clone(&queue),
+            result_tx,
+            pool.registry(),
+            control.clone(),
+        )
+        .await
+        {
+            Ok(server) => Some(server),
+            Err(err) => {
+                eprintln!("❌ Failed to listen on {}: {}", addr, err);
+                None
+            }
+        },
+        None => {
+            drop(result_tx);
+            None
+        }
+    };
+    let mut scale = tokio::time::interval(pool.scale_interval());
 
-    // Collect results
-    while let Some(result) = result_rx.recv().await {
+    // A capable remote worker may still connect, so give it a while
+    let grace = if server.is_some() { UNMATCHED_GRACE } else { Duration::ZERO };
+    let mut unmatched_since: HashMap<u64, Instant> = HashMap::new();
+
+    let mut cancelling = false;
+    loop {
+        tokio::select! {
+            _ = control.cancelled(), if !cancelling => {
+                cancelling = true;
+                println!("🛑 Cancelling the run");
+                let mut queue = queue.lock();
+                run.cancel(&mut queue);
+                if run.is_finished() {
+                    queue.close();
+                }
+            }
+            Some(result) = result_rx.recv() => {
+                println!("Coordinator received result: {:?}", result);
+                pool.record(&result);
+                let mut queue = queue.lock();
+                run.on_result(&result, &mut queue);
+                if run.is_finished() {
+                    queue.close();
+                }
+            }
+            _ = scale.tick() => {
+                pool.reap().await;
+                {
+                    let mut queue = queue.lock();
+                    for (job, attempts) in queue.take_poisoned() {
+                        let reason = format!("Gave up after {} attempts", attempts);
+                        if !run.give_up(job.id, reason, &mut queue) {
+                            eprintln!("❌ Job {} ({}) was dropped after {} attempts", job.id, job.description, attempts);
+                        }
+                    }
+                    match queue.drop_cancelled() {
+                        Ok(jobs) => {
+                            for job in jobs {
+                                run.cancelled(job.id, &mut queue);
+                            }
+                        }
+                        Err(err) => eprintln!("❌ Failed to drop cancelled jobs: {}", err),
+                    }
+
+                    let unmatched = queue.unmatched(|requirements| pool.can_run(requirements));
+                    unmatched_since.retain(|id, _| unmatched.iter().any(|(unmatched, _)| unmatched == id));
+                    for (id, requirements) in unmatched {
+                        if unmatched_since.entry(id).or_insert_with(Instant::now).elapsed() < grace {
+                            continue;
+                        }
+                        let reason = match requirements {
+                            Some(requirements) => format!("No worker can run it (needs {})", requirements),
+                            None => "Its subtasks need different browsers".to_string(),
+                        };
+                        match queue.reject(id) {
+                            Ok(Some(job)) => {
+                                eprintln!("🚫 Rejecting job {} ({}): {}", id, job.description, reason);
+                                run.give_up(job.id, reason, &mut queue);
+                            }
+                            Ok(None) => {}
+                            Err(err) => eprintln!("❌ Failed to reject job {}: {}", id, err),
+                        }
+                        unmatched_since.remove(&id);
+                    }
+
+                    if run.is_finished() {
+                        queue.close();
+                    }
+                }
+                let finished = queue.lock().is_finished();
+                // Remote workers may still turn up while the server runs
+                if pool.is_empty() && (finished || server.is_none()) {
+                    if !finished {
+                        eprintln!("❌ Every worker stopped; unfinished jobs stay queued for the next run");
+                    }
+                    break;
+                }
+                // Paused jobs pile up, but more workers wouldn't help
+                if control.state() == RunState::Running {
+                    pool.autoscale();
+                }
+            }
+        }
+    }
+
+    // Results sent just before the last workers exited
+    while let Ok(result) = result_rx.try_recv() {
         println!("Coordinator received result: {:?}", result);
+        run.on_result(&result, &mut queue.lock());
+    }
+    pool.shutdown().await;
+    if let Some(server) = server {
+        server.abort();
     }
+
+    Ok(run.into_reports())
 }
@@ -0,0 +1,176 @@
+use crate::executor::browser;
+use crate::run_report::{ErrorKind, StepError};
+use crate::task_model::{CompareOp, Condition, LoopSource};
+use crate::variables::Variables;
+use crate::web_interaction::locate;
+use fantoccini::Client;
+use serde_json::{Map, Value};
+
+/// Evaluate an `If`/`While` condition against the current page and variables
+pub async fn evaluate(client: Option<&mut Client>, condition: &Condition, vars: &Variables) -> Result<bool, StepError> {
+    // Peel off any `Not`s so the rest stays a flat match
+    let mut negate = false;
+    let mut condition = condition;
+    while let Condition::Not(inner) = condition {
+        negate = !negate;
+        condition = inner;
+    }
+
+    let holds = match condition {
+        Condition::ElementExists(target) => {
+            let target = target.render(vars)?;
+            locate(browser(client)?, &target).await.is_ok()
+        }
+        Condition::TextContains { selector, text } => {
+            let target = selector.render(vars)?;
+            let text = vars.render(text)?;
+            match locate(browser(client)?, &target).await {
+                Ok(mut element) => element.text().await?.contains(&text),
+                Err(_) => false,
+            }
+        }
+        Condition::Compare { left, op, right } => compare(&vars.render(left)?, *op, &vars.render(right)?),
+        Condition::Not(_) => unreachable!("negations are unwrapped above"),
+    };
+    Ok(holds != negate)
+}
+
+/// Compare numerically when both sides are numbers, as strings otherwise
+pub fn compare(left: &str, op: CompareOp, right: &str) -> bool {
+    if op == CompareOp::Contains {
+        return left.contains(right);
+    }
+    let ordering = match (left.trim().parse::<f64>(), right.trim().parse::<f64>()) {
+        (Ok(l), Ok(r)) => l.partial_cmp(&r),
+        _ => Some(left.cmp(right)),
+    };
+    let Some(ordering) = ordering else {
+        return op == CompareOp::Ne; // NaN compares unequal to everything
+    };
+    match op {
+        CompareOp::Eq => ordering.is_eq(),
+        CompareOp::Ne => ordering.is_ne(),
+        CompareOp::Lt => ordering.is_lt(),
+        CompareOp::Le => ordering.is_le(),
+        CompareOp::Gt => ordering.is_gt(),
+        CompareOp::Ge => ordering.is_ge(),
+        CompareOp::Contains => unreachable!("handled above"),
+    }
+}
+
+/// Resolve the items a `ForEach` iterates over
+pub fn loop_items(source: &LoopSource, vars: &Variables) -> Result<Vec<Value>, StepError> {
+    match source {
+        LoopSource::Variable(name) => match vars.get(name) {
+            Some(Value::Array(items)) => Ok(items.clone()),
+            Some(other) => Err(StepError::new(
+                ErrorKind::ControlFlow,
+                format!("'{}' is not a list (got {})", name, other),
+            )),
+            None => Err(StepError::new(
+                ErrorKind::Variables,
+                format!("Variable '{}' is used but never bound", name),
+            )),
+        },
+        LoopSource::Csv(path) => read_csv(&vars.render(path)?),
+    }
+}
+
+/// Read a CSV file with a header row into one object per row
+pub fn read_csv(path: &str) -> Result<Vec<Value>, StepError> {
+    let csv_error = |err: csv::Error| StepError::new(ErrorKind::Io, format!("{}: {}", path, err));
+    let mut reader = csv::Reader::from_path(path).map_err(csv_error)?;
+    let headers = reader.headers().map_err(csv_error)?.clone();
+
+    let mut rows = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(csv_error)?;
+        let row: Map<String, Value> = headers
+            .iter()
+            .zip(record.iter())
+            .map(|(header, field)| (header.to_string(), Value::String(field.to_string())))
+            .collect();
+        rows.push(Value::Object(row));
+    }
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::target::Target;
+    use serde_json::json;
+
+    fn compared(left: &str, op: CompareOp, right: &str) -> Condition {
+        Condition::Compare {
+            left: left.to_string(),
+            op,
+            right: right.to_string(),
+        }
+    }
+
+    #[test]
+    fn compares_numbers_numerically() {
+        assert!(compare("10", CompareOp::Gt, "9"));
+        assert!(compare(" 2.50 ", CompareOp::Eq, "2.5"));
+        assert!(compare("-1", CompareOp::Lt, "0"));
+        assert!(compare("3", CompareOp::Le, "3.0"));
+        assert!(!compare("NaN", CompareOp::Eq, "NaN"));
+        assert!(compare("NaN", CompareOp::Ne, "1"));
+    }
+
+    #[test]
+    fn compares_anything_else_as_strings() {
+        assert!(compare("10", CompareOp::Lt, "9x"), "not both numbers, so compared as text");
+        assert!(compare("abc", CompareOp::Lt, "abd"));
+        assert!(compare("Paid", CompareOp::Ne, "paid"));
+        assert!(compare("", CompareOp::Eq, ""));
+        assert!(compare("Order 10 shipped", CompareOp::Contains, "10"));
+        assert!(!compare("10", CompareOp::Contains, "10.0"));
+    }
+
+    #[tokio::test]
+    async fn evaluates_comparisons_on_variables() {
+        let mut vars = Variables::new();
+        vars.set("total", json!(120));
+        let over = compared("{{total}}", CompareOp::Ge, "100");
+        assert!(evaluate(None, &over, &vars).await.unwrap());
+        let not_not = Condition::Not(Box::new(Condition::Not(Box::new(over))));
+        assert!(evaluate(None, &not_not, &vars).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn missing_variables_are_errors_not_false() {
+        let vars = Variables::new();
+        let condition = Condition::Not(Box::new(compared("{{total}}", CompareOp::Eq, "")));
+        let err = evaluate(None, &condition, &vars).await.unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Variables);
+        assert!(err.message.contains("total"), "{}", err.message);
+
+        let err = evaluate(None, &Condition::ElementExists(Target::css("#a")), &vars).await.unwrap_err();
+        assert_eq!(err.kind, ErrorKind::BrowserStart);
+    }
+
+    #[test]
+    fn loops_over_list_variables_only() {
+        let mut vars = Variables::new();
+        vars.set("rows", json!([1, 2]));
+        vars.set("name", json!("x"));
+        let items = |name: &str| loop_items(&LoopSource::Variable(name.to_string()), &vars);
+        assert_eq!(items("rows").unwrap(), [json!(1), json!(2)]);
+        assert_eq!(items("name").unwrap_err().kind, ErrorKind::ControlFlow);
+        assert_eq!(items("missing").unwrap_err().kind, ErrorKind::Variables);
+    }
+
+    #[test]
+    fn reads_csv_rows_as_objects() {
+        let path = std::env::temp_dir().join(format!("sae-control-flow-{}.csv", std::process::id()));
+        std::fs::write(&path, "email,plan\na@x.io,pro\nb@x.io,free\n").unwrap();
+        let mut vars = Variables::new();
+        vars.set("file", json!(path.to_string_lossy()));
+        let rows = loop_items(&LoopSource::Csv("{{file}}".to_string()), &vars).unwrap();
+        assert_eq!(rows, [json!({"email": "a@x.io", "plan": "pro"}), json!({"email": "b@x.io", "plan": "free"})]);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(read_csv(&path.to_string_lossy()).unwrap_err().kind, ErrorKind::Io);
+    }
+}
@@ -1,51 +1,602 @@
 use crate::web_interaction::*;
+use crate::browser_config::BrowserConfig;
+use crate::cancellation::{Cancelled, RunControl};
+use crate::control_flow;
+use crate::observation_layer::dynamic_detection::{heal, DEFAULT_CONFIDENCE};
+use crate::observation_layer::interaction_log::{LogContext, LOG_CONTEXT};
+use crate::run_report::{Artifact, ErrorKind, RunReport, StepError, StepReport};
+use crate::task_model::{AutomationTask, ErrorPolicy, Step, TaskStep};
+use crate::variables::{VariableError, Variables};
+use crate::task_library::resolve_workflow;
 use fantoccini::Client;
-use crate::task_model::{AutomationTask, TaskStep};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use tokio::time::{sleep, Duration};
 
-pub async fn execute_task(task: AutomationTask) {
+/// Whether the surrounding task should keep going after a step
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flow {
+    Continue,
+    Abort,
+    Cancelled, // The run was cancelled while the step (or one nested in it) ran
+}
+
+/// Run a task with the given input arguments (`name -> raw value`) in a
+/// browser started from `browser`. `control` can pause the task between
+/// steps or cancel it, abandoning the step in progress.
+pub async fn execute_task(
+    task: AutomationTask,
+    args: &HashMap<String, String>,
+    browser: &BrowserConfig,
+    control: &RunControl,
+) -> RunReport {
     println!("🚀 Starting task execution...");
+    let mut report = RunReport::start(&task.name);
 
-    let mut client = match start_browser().await {
+    // Refuse to start if any placeholder could end up unbound
+    let mut vars = match bind_variables(&task, args) {
+        Ok(vars) => vars,
+        Err(errors) => {
+            for err in &errors {
+                eprintln!("❌ {}", err);
+            }
+            let message = errors.iter().map(|e| e.to_string()).collect::<Vec<_>>().join("; ");
+            report.fail(StepError::new(ErrorKind::Variables, message));
+            return report.finish();
+        }
+    };
+
+    // Every browser action below is logged under this run
+    let context = LogContext {
+        run_id: Some(report.run_id.clone()),
+        task_id: Some(task.name.clone()),
+        ..LogContext::current()
+    };
+
+    let mut client = match LOG_CONTEXT.scope(context.clone(), start_browser(browser)).await {
         Ok(client) => client,
         Err(err) => {
             eprintln!("❌ Failed to start browser: {:?}", err);
-            return;
+            report.fail(StepError::new(ErrorKind::BrowserStart, err));
+            return report.finish();
         }
     };
 
-    for step in task.steps {
-        match step {
-            TaskStep::OpenWebsite(url) => {
-                if let Err(err) = open_website(&mut client, &url).await {
-                    eprintln!("❌ Failed to open website {}: {:?}", url, err);
+    for (index, step) in task.steps.iter().enumerate() {
+        if control.checkpoint().await.is_err() {
+            eprintln!("🛑 Cancelled before step: {:?}", step.action);
+            report.cancel();
+            break;
+        }
+        let path = format!("steps[{}]", index);
+        let interrupted = StepReport::start(&path, &step.action);
+        let first = report.steps.len();
+        let flow = control
+            .run(LOG_CONTEXT.scope(
+                context.step(index),
+                run_with_policy(Some(&mut client), step, &path, &mut vars, &mut report, control),
+            ))
+            .await;
+        match flow {
+            Ok(Flow::Continue) => {}
+            Ok(Flow::Cancelled) => {
+                eprintln!("🛑 Cancelled during step: {:?}", step.action);
+                report.cancel();
+                break;
+            }
+            Ok(Flow::Abort) => {
+                eprintln!("🛑 Aborting task after failed step: {:?}", step.action);
+                report.abort();
+                break;
+            }
+            Err(Cancelled) => {
+                eprintln!("🛑 Cancelled during step: {:?}", step.action);
+                // Unless it stopped at a checkpoint and recorded itself
+                if report.steps.get(first).is_none_or(|step| step.path != path) {
+                    report.insert_step(first, interrupted.cancelled());
                 }
+                report.cancel();
+                break;
             }
-            TaskStep::ClickElement(selector) => {
-                if let Err(err) = click_element(&mut client, &selector).await {
-                    eprintln!("❌ Failed to click element {}: {:?}", selector, err);
+        }
+    }
+
+    if let Err(err) = close_browser(client).await {
+        eprintln!("❌ Failed to close browser: {:?}", err);
+    } else {
+        println!("✅ Task execution completed!");
+    }
+    report.finish()
+}
+
+fn bind_variables(
+    task: &AutomationTask,
+    args: &HashMap<String, String>,
+) -> Result<Variables, Vec<VariableError>> {
+    let vars = Variables::from_inputs(&task.inputs, args)?;
+    task.check_variables(&vars)?;
+    Ok(vars)
+}
+
+/// Nesting limit for `CallWorkflow`, so a workflow calling itself fails
+/// instead of recursing forever
+const MAX_CALL_DEPTH: usize = 8;
+
+/// Run a step and apply its `on_error` policy, recording every step that ran
+/// (including fallback and nested steps) in `report`, in workflow order.
+/// `path` locates the step in its workflow, like `steps[2]`.
+///
+/// Both the executor and `worker::worker_node` go through here so a policy
+/// means the same thing wherever a step runs.
+pub fn run_with_policy<'a>(
+    client: Option<&'a mut Client>,
+    step: &'a Step,
+    path: &'a str,
+    vars: &'a mut Variables,
+    report: &'a mut RunReport,
+    control: &'a RunControl,
+) -> Pin<Box<dyn Future<Output = Flow> + Send + 'a>> {
+    run_with_policy_at(client, step, path, vars, report, control, 0)
+}
+
+fn run_with_policy_at<'a>(
+    mut client: Option<&'a mut Client>,
+    step: &'a Step,
+    path: &'a str,
+    vars: &'a mut Variables,
+    report: &'a mut RunReport,
+    control: &'a RunControl,
+    depth: usize,
+) -> Pin<Box<dyn Future<Output = Flow> + Send + 'a>> {
+    Box::pin(async move {
+        let step_report = StepReport::start(path, &step.action);
+        let nested = report.steps.len(); // Where this step's nested steps start, and where it goes
+        let mut attempts = 1;
+        let result = loop {
+            let attempt = run_step_at(client.as_deref_mut(), &step.action, path, vars, report, control, depth).await;
+            match (attempt, &step.on_error) {
+                (Err(err), ErrorPolicy::Retry { attempts: retries, backoff_ms })
+                    if attempts <= *retries && err.kind != ErrorKind::Cancelled =>
+                {
+                    let delay = backoff_ms.saturating_mul(1 << (attempts - 1).min(16));
+                    eprintln!(
+                        "🔁 {:?} failed ({}), retrying in {} ms ({}/{})",
+                        step.action, err, delay, attempts, retries
+                    );
+                    sleep(Duration::from_millis(delay)).await;
+                    report.discard_from(nested); // Only the last attempt's nested steps are reported
+                    attempts += 1;
                 }
+                (result, _) => break result,
             }
-            TaskStep::FillForm(selector, value) => {
-                if let Err(err) = fill_form_field(&mut client, &selector, &value).await {
-                    eprintln!("❌ Failed to fill form field {}: {:?}", selector, err);
+        };
+        let step_report = step_report.attempts(attempts);
+
+        let err = match result {
+            Ok(artifacts) => {
+                report.tolerate_from(nested);
+                report.insert_step(nested, step_report.passed(artifacts));
+                return Flow::Continue;
+            }
+            Err(err) => err,
+        };
+        if err.kind == ErrorKind::Cancelled {
+            // Not a failure, so no policy applies
+            report.insert_step(nested, step_report.cancelled());
+            return Flow::Cancelled;
+        }
+        eprintln!("❌ {:?} failed: {}", step.action, err);
+
+        match &step.on_error {
+            ErrorPolicy::Abort | ErrorPolicy::Retry { .. } => {
+                report.insert_step(nested, step_report.failed(err));
+                Flow::Abort
+            }
+            ErrorPolicy::Continue => {
+                report.tolerate_from(nested);
+                report.insert_step(nested, step_report.tolerated(err));
+                Flow::Continue
+            }
+            ErrorPolicy::Fallback(steps) => {
+                report.tolerate_from(nested);
+                report.insert_step(nested, step_report.tolerated(err));
+                println!("↪️ Running {} fallback step(s)", steps.len());
+                for (i, fallback) in steps.iter().enumerate() {
+                    let path = format!("{}.on_error.fallback[{}]", path, i);
+                    let flow = run_with_policy_at(client.as_deref_mut(), fallback, &path, vars, report, control, depth)
+                        .await;
+                    if flow != Flow::Continue {
+                        return flow;
+                    }
                 }
+                Flow::Continue
+            }
+        }
+    })
+}
+
+/// Fill in a step's placeholders and run it against an open browser session.
+/// Extraction steps store their value in `vars` and report it as an artifact;
+/// control-flow steps record their nested steps in `report`.
+pub async fn run_step(
+    client: Option<&mut Client>,
+    step: &TaskStep,
+    path: &str,
+    vars: &mut Variables,
+    report: &mut RunReport,
+    control: &RunControl,
+) -> Result<Vec<Artifact>, StepError> {
+    run_step_at(client, step, path, vars, report, control, 0).await
+}
+
+async fn run_step_at(
+    mut client: Option<&mut Client>,
+    step: &TaskStep,
+    path: &str,
+    vars: &mut Variables,
+    report: &mut RunReport,
+    control: &RunControl,
+    depth: usize,
+) -> Result<Vec<Artifact>, StepError> {
+    match step {
+        TaskStep::If { condition, then, otherwise } => {
+            let (label, branch) = if control_flow::evaluate(client.as_deref_mut(), condition, vars).await? {
+                ("then", then)
+            } else {
+                ("else", otherwise)
+            };
+            let body = format!("{}.{}", path, label);
+            run_block(client.as_deref_mut(), branch, &body, vars, report, control, depth).await?;
+        }
+        TaskStep::ForEach { items, item, steps } => {
+            let body = format!("{}.steps", path);
+            let items = control_flow::loop_items(items, vars)?;
+            println!("🔁 Looping over {} item(s)", items.len());
+            for value in items {
+                vars.push_scope();
+                vars.define(item, value);
+                let result = run_block(client.as_deref_mut(), steps, &body, vars, report, control, depth).await;
+                vars.pop_scope();
+                result?;
             }
-            TaskStep::TakeScreenshot(file_path) => {
-                if let Err(err) = take_screenshot(&mut client, &file_path).await {
-                    eprintln!("❌ Failed to take screenshot: {:?}", err);
+        }
+        TaskStep::While { condition, max_iterations, steps } => {
+            let body = format!("{}.steps", path);
+            let mut iterations = 0;
+            while control_flow::evaluate(client.as_deref_mut(), condition, vars).await? {
+                if iterations == *max_iterations {
+                    return Err(StepError::new(
+                        ErrorKind::ControlFlow,
+                        format!("condition still true after {} iterations", max_iterations),
+                    ));
                 }
+                iterations += 1;
+                run_block(client.as_deref_mut(), steps, &body, vars, report, control, depth).await?;
+            }
+        }
+        TaskStep::CallWorkflow { workflow, args, into } => {
+            if depth >= MAX_CALL_DEPTH {
+                return Err(StepError::new(
+                    ErrorKind::ControlFlow,
+                    format!("workflows nested more than {} deep", MAX_CALL_DEPTH),
+                ));
             }
-            TaskStep::WaitForElement(selector) => {
-                if let Err(err) = wait_for_element(&mut client, &selector).await {
-                    eprintln!("❌ Failed to wait for element {}: {:?}", selector, err);
+            let reference = vars.render(workflow)?;
+            let called = resolve_workflow(&reference).map_err(|err| StepError::new(ErrorKind::ControlFlow, err))?;
+            let args = args
+                .iter()
+                .map(|(name, value)| Ok((name.clone(), vars.render(value)?)))
+                .collect::<Result<HashMap<_, _>, VariableError>>()?;
+
+            // The called workflow only sees the arguments it was given
+            let mut called_vars = bind_variables(&called, &args).map_err(|errors| {
+                let message = errors.iter().map(|e| e.to_string()).collect::<Vec<_>>().join("; ");
+                StepError::new(ErrorKind::Variables, format!("{}: {}", reference, message))
+            })?;
+            println!("📞 Calling workflow '{}' ({})", called.name, reference);
+            for (i, called_step) in called.steps.iter().enumerate() {
+                // Steps of the called workflow are located under the call
+                let path = format!("{}.workflow.steps[{}]", path, i);
+                let flow = run_with_policy_at(
+                    client.as_deref_mut(),
+                    called_step,
+                    &path,
+                    &mut called_vars,
+                    report,
+                    control,
+                    depth + 1,
+                )
+                .await;
+                match flow {
+                    Flow::Continue => {}
+                    Flow::Abort => {
+                        return Err(StepError::new(
+                            ErrorKind::ControlFlow,
+                            format!("workflow '{}' aborted", called.name),
+                        ))
+                    }
+                    Flow::Cancelled => return Err(Cancelled.into()),
                 }
             }
+
+            if let Some(variable) = into {
+                let outputs = called_vars
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), value.clone()))
+                    .collect();
+                return Ok(vec![store(vars, variable.clone(), Value::Object(outputs))]);
+            }
         }
+        _ => return run_healing(browser(client)?, step.render(vars)?, vars).await,
     }
+    Ok(Vec::new())
+}
 
-    if let Err(err) = client.close().await {
-        eprintln!("❌ Failed to close browser: {:?}", err);
-    } else {
-        println!("✅ Task execution completed!");
+/// Run nested steps in their own variable scope. A pause takes effect
+/// between them, so long loops can be paused (and cancelled) too. `path`
+/// locates the block, like `steps[2].then`.
+async fn run_block(
+    mut client: Option<&mut Client>,
+    steps: &[Step],
+    path: &str,
+    vars: &mut Variables,
+    report: &mut RunReport,
+    control: &RunControl,
+    depth: usize,
+) -> Result<(), StepError> {
+    vars.push_scope();
+    let mut result = Ok(());
+    for (i, step) in steps.iter().enumerate() {
+        if let Err(cancelled) = control.checkpoint().await {
+            result = Err(cancelled.into());
+            break;
+        }
+        let path = format!("{}[{}]", path, i);
+        match run_with_policy_at(client.as_deref_mut(), step, &path, vars, report, control, depth).await {
+            Flow::Continue => {}
+            Flow::Abort => {
+                result = Err(StepError::new(
+                    ErrorKind::ControlFlow,
+                    format!("nested step {:?} failed", step.action),
+                ));
+                break;
+            }
+            Flow::Cancelled => {
+                result = Err(Cancelled.into());
+                break;
+            }
+        }
+    }
+    vars.pop_scope();
+    result
+}
+
+/// The browser a leaf step or an element condition runs against. A worker
+/// without one only gets subtasks that require `browser: none`.
+pub(crate) fn browser(client: Option<&mut Client>) -> Result<&mut Client, StepError> {
+    client.ok_or_else(|| StepError::new(ErrorKind::BrowserStart, "step needs a browser, but its subtask requires none"))
+}
+
+/// Run a leaf step, and when its target no longer matches but was recorded
+/// with a fingerprint, retry once against the closest look-alike element
+async fn run_healing(
+    client: &mut Client,
+    mut step: TaskStep,
+    vars: &mut Variables,
+) -> Result<Vec<Artifact>, StepError> {
+    let err = match run_action(client, step.clone(), vars).await {
+        Err(err) if matches!(err.kind, ErrorKind::ElementNotFound | ErrorKind::Timeout) => err,
+        result => return result,
+    };
+    let Some(target) = step.target_mut() else {
+        return Err(err);
+    };
+    let Some(healed) = heal(client, target, DEFAULT_CONFIDENCE).await else {
+        return Err(err);
+    };
+    let artifact = Artifact::HealedTarget {
+        original: target.to_string(),
+        healed: healed.target.to_string(),
+        confidence: healed.confidence,
+    };
+    *target = healed.target;
+    let mut artifacts = run_action(client, step, vars).await?;
+    artifacts.push(artifact);
+    Ok(artifacts)
+}
+
+/// Run a leaf step whose placeholders are already filled in
+async fn run_action(
+    client: &mut Client,
+    step: TaskStep,
+    vars: &mut Variables,
+) -> Result<Vec<Artifact>, StepError> {
+    match step {
+        TaskStep::OpenWebsite(url) => {
+            open_website(client, &url)
+                .await
+                .map_err(|err| StepError::new(ErrorKind::Navigation, err))?;
+        }
+        TaskStep::ClickElement(target) => {
+            click_element(client, &target).await?;
+        }
+        TaskStep::FillForm(target, value) => {
+            fill_form_field(client, &target, &value).await?;
+        }
+        TaskStep::SelectOption(target, value) => {
+            select_option(client, &target, &value).await?;
+        }
+        TaskStep::SubmitForm(target) => {
+            submit_form(client, &target).await?;
+        }
+        TaskStep::TakeScreenshot(file_path) => {
+            take_screenshot(client, &file_path)
+                .await
+                .map_err(|err| StepError::new(ErrorKind::Io, err))?;
+            return Ok(vec![Artifact::Screenshot { path: file_path }]);
+        }
+        TaskStep::WaitForElement(target) => {
+            wait_for_element(client, &target)
+                .await
+                .map_err(|err| StepError::new(ErrorKind::Timeout, err))?;
+        }
+        TaskStep::CategorizeElements => {
+            let categorized_elements = universal_locator(client).await?;
+            println!("✅ Categorized elements: {:?}", categorized_elements);
+            return Ok(vec![Artifact::ExtractedData {
+                name: "categorized_elements".to_string(),
+                value: serde_json::to_value(categorized_elements).unwrap_or_default(),
+            }]);
+        }
+        TaskStep::ExtractText(target, variable) => {
+            let text = extract_text(client, &target).await?;
+            return Ok(vec![store(vars, variable, Value::String(text))]);
+        }
+        TaskStep::ExtractAttribute(target, attribute, variable) => {
+            let value = extract_attribute(client, &target, &attribute).await?.ok_or_else(|| {
+                StepError::new(
+                    ErrorKind::Extraction,
+                    format!("'{}' has no attribute '{}'", target, attribute),
+                )
+            })?;
+            return Ok(vec![store(vars, variable, Value::String(value))]);
+        }
+        TaskStep::ExtractProperty(target, property, variable) => {
+            let value = extract_property(client, &target, &property).await?.ok_or_else(|| {
+                StepError::new(
+                    ErrorKind::Extraction,
+                    format!("'{}' has no property '{}'", target, property),
+                )
+            })?;
+            return Ok(vec![store(vars, variable, Value::String(value))]);
+        }
+        TaskStep::ExtractTable(target, variable) => {
+            let rows = extract_table(client, &target).await?;
+            return Ok(vec![store(vars, variable, rows)]);
+        }
+        TaskStep::EvaluateScript(script, variable) => {
+            let value = evaluate_script(client, &script).await?;
+            return Ok(vec![store(vars, variable, value)]);
+        }
+        TaskStep::If { .. }
+        | TaskStep::ForEach { .. }
+        | TaskStep::While { .. }
+        | TaskStep::CallWorkflow { .. } => unreachable!("control flow is handled by run_step"),
+    }
+    Ok(Vec::new())
+}
+
+fn store(vars: &mut Variables, variable: String, value: Value) -> Artifact {
+    println!("✅ Stored '{}' = {}", variable, value);
+    vars.set(&variable, value.clone());
+    Artifact::ExtractedData { name: variable, value }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::run_report::{RunStatus, StepStatus};
+    use crate::task_model::{CompareOp, Condition};
+
+    fn holds(holds: bool) -> Condition {
+        let right = if holds { "1" } else { "2" };
+        Condition::Compare {
+            left: "1".to_string(),
+            op: CompareOp::Eq,
+            right: right.to_string(),
+        }
+    }
+
+    fn when(condition: bool, then: Vec<Step>, otherwise: Vec<Step>) -> Step {
+        Step::new(TaskStep::If {
+            condition: holds(condition),
+            then,
+            otherwise,
+        })
+    }
+
+    async fn run(steps: &[Step]) -> RunReport {
+        run_under(steps, &RunControl::new()).await.0
+    }
+
+    async fn run_under(steps: &[Step], control: &RunControl) -> (RunReport, Vec<Flow>) {
+        let mut report = RunReport::start("test");
+        let mut vars = Variables::new();
+        let mut flows = Vec::new();
+        for (i, step) in steps.iter().enumerate() {
+            let path = format!("steps[{}]", i);
+            flows.push(run_with_policy(None, step, &path, &mut vars, &mut report, control).await);
+        }
+        (report, flows)
+    }
+
+    #[tokio::test]
+    async fn reports_steps_in_workflow_order_with_their_paths() {
+        let inner = when(false, Vec::new(), Vec::new());
+        let outer = when(true, vec![inner.clone(), inner], Vec::new());
+        let report = run(&[outer, when(false, Vec::new(), Vec::new())]).await;
+        let paths: Vec<_> = report.steps.iter().map(|step| step.path.as_str()).collect();
+        assert_eq!(paths, ["steps[0]", "steps[0].then[0]", "steps[0].then[1]", "steps[1]"]);
+        assert!(report.passed());
+    }
+
+    #[tokio::test]
+    async fn reports_nested_steps_of_the_last_attempt_only() {
+        let open = Step::new(TaskStep::OpenWebsite("https://example.com".to_string()));
+        let retried = when(true, vec![open], Vec::new()).on_error(ErrorPolicy::Retry {
+            attempts: 2,
+            backoff_ms: 0,
+        });
+        let report = run(&[retried]).await;
+        let steps: Vec<_> = report.steps.iter().map(|step| (step.path.as_str(), step.attempts)).collect();
+        assert_eq!(steps, [("steps[0]", 3), ("steps[0].then[0]", 1)]);
+        assert_eq!(report.status, RunStatus::Failed);
+    }
+
+    #[tokio::test]
+    async fn cancellation_skips_retries_and_fallbacks() {
+        let nested = || vec![when(true, Vec::new(), Vec::new())];
+        let retried = when(true, nested(), Vec::new()).on_error(ErrorPolicy::Retry {
+            attempts: 3,
+            backoff_ms: 0,
+        });
+        let fallback = when(true, nested(), Vec::new()).on_error(ErrorPolicy::Fallback(nested()));
+        let tolerant = when(true, nested(), Vec::new()).on_error(ErrorPolicy::Continue);
+
+        let control = RunControl::new();
+        control.cancel();
+        let (report, flows) = run_under(&[retried, fallback, tolerant], &control).await;
+        assert_eq!(flows, [Flow::Cancelled; 3]);
+        let steps: Vec<_> = report.steps.iter().map(|step| (step.path.as_str(), step.status, step.attempts)).collect();
+        assert_eq!(
+            steps,
+            [
+                ("steps[0]", StepStatus::Cancelled, 1),
+                ("steps[1]", StepStatus::Cancelled, 1),
+                ("steps[2]", StepStatus::Cancelled, 1),
+            ]
+        );
+        assert!(report.steps.iter().all(|step| step.error.is_none()));
+    }
+
+    #[tokio::test]
+    async fn locates_else_branches_and_fallbacks() {
+        let open = Step::new(TaskStep::OpenWebsite("https://example.com".to_string())); // Fails, there's no browser
+        let fallback = Step::new(TaskStep::OpenWebsite("https://example.com".to_string()))
+            .on_error(ErrorPolicy::Fallback(vec![when(true, Vec::new(), Vec::new())]));
+        let report = run(&[when(false, Vec::new(), vec![open.on_error(ErrorPolicy::Continue)]), fallback]).await;
+        let steps: Vec<_> = report.steps.iter().map(|step| (step.path.as_str(), step.status)).collect();
+        assert_eq!(
+            steps,
+            [
+                ("steps[0]", StepStatus::Passed),
+                ("steps[0].else[0]", StepStatus::Tolerated),
+                ("steps[1]", StepStatus::Tolerated),
+                ("steps[1].on_error.fallback[0]", StepStatus::Passed),
+            ]
+        );
+        assert_eq!(report.status, RunStatus::Passed);
     }
 }
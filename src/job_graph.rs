@@ -0,0 +1,628 @@
+use crate::job_queue::{CancelOutcome, JobQueue};
+use crate::task_model::{Job, TaskResult, TaskStatus};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum GraphError {
+    #[error("Failed to read graph: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid graph document: {0}")]
+    Parse(#[from] serde_yaml::Error),
+    #[error("Node '{0}' is defined more than once")]
+    DuplicateNode(String),
+    #[error("Invalid node name '{0}' (must be non-empty and contain no '.')")]
+    InvalidName(String),
+    #[error("Node '{node}' depends on unknown node '{dependency}'")]
+    UnknownDependency { node: String, dependency: String },
+    #[error("Input '{input}' of node '{node}' must look like '<node>.<output>', got '{reference}'")]
+    InvalidInput {
+        node: String,
+        input: String,
+        reference: String,
+    },
+    #[error("Dependency cycle: {}", .0.join(" -> "))]
+    Cycle(Vec<String>),
+    #[error("The subtasks of node '{0}' need different browsers, so no single worker can run them")]
+    ConflictingRequirements(String),
+}
+
+/// One job in a graph, run once every node it depends on has completed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GraphNode {
+    pub name: String,
+    pub job: Job,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub after: Vec<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub inputs: BTreeMap<String, String>, // Job input -> "<node>.<output>"; implies a dependency
+}
+
+impl GraphNode {
+    pub fn new(name: impl Into<String>, job: Job) -> Self {
+        Self {
+            name: name.into(),
+            job,
+            after: Vec::new(),
+            inputs: BTreeMap::new(),
+        }
+    }
+
+    /// Run only after `node` completed
+    pub fn after(mut self, node: impl Into<String>) -> Self {
+        self.after.push(node.into());
+        self
+    }
+
+    /// Set the job input `name` from `output` of `node`
+    pub fn input(mut self, name: impl Into<String>, node: &str, output: &str) -> Self {
+        self.inputs.insert(name.into(), format!("{}.{}", node, output));
+        self
+    }
+}
+
+/// Jobs with dependencies between them. Cycles are rejected when the graph is
+/// submitted.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct JobGraph {
+    pub nodes: Vec<GraphNode>,
+}
+
+impl JobGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load a graph from a YAML document
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, GraphError> {
+        Ok(serde_yaml::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn node(mut self, node: GraphNode) -> Self {
+        self.nodes.push(node);
+        self
+    }
+
+    /// Check names, requirements, references and acyclicity; returns every node's
+    /// predecessors and an order in which all of them come first
+    fn validate(&self) -> Result<(Vec<BTreeSet<usize>>, Vec<usize>), GraphError> {
+        let mut index = HashMap::new();
+        for (i, node) in self.nodes.iter().enumerate() {
+            if node.name.is_empty() || node.name.contains('.') {
+                return Err(GraphError::InvalidName(node.name.clone()));
+            }
+            if index.insert(node.name.as_str(), i).is_some() {
+                return Err(GraphError::DuplicateNode(node.name.clone()));
+            }
+            if node.job.requirements().is_none() {
+                return Err(GraphError::ConflictingRequirements(node.name.clone()));
+            }
+        }
+
+        let mut preds = Vec::with_capacity(self.nodes.len());
+        for node in &self.nodes {
+            let mut deps = BTreeSet::new();
+            let referenced = node.inputs.iter().map(|(input, reference)| {
+                reference
+                    .split_once('.')
+                    .filter(|(source, output)| !source.is_empty() && !output.is_empty())
+                    .map(|(source, _)| source)
+                    .ok_or_else(|| GraphError::InvalidInput {
+                        node: node.name.clone(),
+                        input: input.clone(),
+                        reference: reference.clone(),
+                    })
+            });
+            for dependency in node.after.iter().map(|name| Ok(name.as_str())).chain(referenced) {
+                let dependency = dependency?;
+                let Some(&i) = index.get(dependency) else {
+                    return Err(GraphError::UnknownDependency {
+                        node: node.name.clone(),
+                        dependency: dependency.to_string(),
+                    });
+                };
+                deps.insert(i);
+            }
+            preds.push(deps);
+        }
+
+        // Depth-first; reaching a node that is still on the stack closes a cycle
+        #[derive(Clone, Copy, PartialEq)]
+        enum Mark {
+            New,
+            Active,
+            Done,
+        }
+        fn visit(
+            i: usize,
+            preds: &[BTreeSet<usize>],
+            marks: &mut [Mark],
+            stack: &mut Vec<usize>,
+            order: &mut Vec<usize>,
+        ) -> Result<(), Vec<usize>> {
+            match marks[i] {
+                Mark::Done => return Ok(()),
+                Mark::Active => {
+                    let start = stack.iter().position(|&j| j == i).unwrap_or(0);
+                    let mut cycle = stack[start..].to_vec();
+                    cycle.push(i);
+                    return Err(cycle);
+                }
+                Mark::New => {}
+            }
+            marks[i] = Mark::Active;
+            stack.push(i);
+            for &p in &preds[i] {
+                visit(p, preds, marks, stack, order)?;
+            }
+            stack.pop();
+            marks[i] = Mark::Done;
+            order.push(i);
+            Ok(())
+        }
+
+        let mut marks = vec![Mark::New; self.nodes.len()];
+        let mut order = Vec::with_capacity(self.nodes.len());
+        for i in 0..self.nodes.len() {
+            let mut stack = Vec::new();
+            if let Err(cycle) = visit(i, &preds, &mut marks, &mut stack, &mut order) {
+                // Found walking predecessors, so reverse to read in run order
+                let names = cycle.iter().rev().map(|&j| self.nodes[j].name.clone()).collect();
+                return Err(GraphError::Cycle(names));
+            }
+        }
+        Ok((preds, order))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeStatus {
+    Waiting,
+    Running,
+    Completed,
+    Failed,
+    Skipped, // A node it depends on failed, was skipped or was cancelled
+    Cancelled,
+}
+
+impl fmt::Display for NodeStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            NodeStatus::Waiting => "waiting",
+            NodeStatus::Running => "running",
+            NodeStatus::Completed => "completed",
+            NodeStatus::Failed => "failed",
+            NodeStatus::Skipped => "skipped",
+            NodeStatus::Cancelled => "cancelled",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NodeReport {
+    pub name: String,
+    pub status: NodeStatus,
+    pub job_id: Option<usize>, // Queue id, once enqueued
+    pub details: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub outputs: BTreeMap<String, serde_json::Value>,
+}
+
+/// A submitted graph. Feed it every `TaskResult` and it enqueues nodes as
+/// their predecessors complete, passing outputs on to inputs. Dependents of a
+/// failed or cancelled node are skipped; unrelated branches keep going.
+pub struct GraphRun {
+    nodes: Vec<GraphNode>,
+    preds: Vec<BTreeSet<usize>>,
+    order: Vec<usize>,
+    reports: Vec<NodeReport>,
+    by_job: HashMap<usize, usize>,     // Queue job id -> node
+    received: Vec<BTreeSet<usize>>,    // Subtask ids with a result, per node
+    next_subtask: usize,
+    state_path: Option<PathBuf>, // Where progress is saved, next to a durable queue
+}
+
+/// What `GraphRun` saves so an unfinished graph survives a restart
+#[derive(Serialize, Deserialize)]
+struct SavedRun {
+    reports: Vec<NodeReport>,
+    received: Vec<BTreeSet<usize>>,
+    next_subtask: usize,
+}
+
+impl GraphRun {
+    /// Validate `graph` and enqueue the nodes that have no dependencies. With
+    /// a durable queue, progress is saved next to it until the graph finishes,
+    /// and submitting the same graph again picks up where it left off instead
+    /// of running its nodes twice.
+    pub fn submit(graph: JobGraph, queue: &mut JobQueue) -> Result<Self, GraphError> {
+        let (preds, order) = graph.validate()?;
+        let state_path = match queue.path() {
+            Some(path) => Some(state_path(path, &graph)?),
+            None => None,
+        };
+        let reports = graph
+            .nodes
+            .iter()
+            .map(|node| NodeReport {
+                name: node.name.clone(),
+                status: NodeStatus::Waiting,
+                job_id: None,
+                details: None,
+                outputs: BTreeMap::new(),
+            })
+            .collect();
+        let mut run = GraphRun {
+            received: vec![BTreeSet::new(); graph.nodes.len()],
+            nodes: graph.nodes,
+            preds,
+            order,
+            reports,
+            by_job: HashMap::new(),
+            next_subtask: 0,
+            state_path,
+        };
+        run.resume(queue);
+        run.schedule(queue);
+        Ok(run)
+    }
+
+    /// Take over the progress saved by an earlier run of the same graph
+    fn resume(&mut self, queue: &JobQueue) {
+        let Some(path) = &self.state_path else {
+            return;
+        };
+        let saved = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return,
+            Err(err) => {
+                eprintln!("⚠️ Starting graph over, failed to read {}: {}", path.display(), err);
+                return;
+            }
+        };
+        let saved = match serde_json::from_slice::<SavedRun>(&saved) {
+            Ok(saved) if saved.reports.len() == self.reports.len() => saved,
+            _ => {
+                eprintln!("⚠️ Starting graph over, {} is unreadable", path.display());
+                return;
+            }
+        };
+        println!("🔁 Resuming graph from {}", path.display());
+        self.reports = saved.reports;
+        self.received = saved.received;
+        self.next_subtask = saved.next_subtask;
+        for (i, report) in self.reports.iter_mut().enumerate() {
+            match report.job_id {
+                // Acked before its results were recorded, so run it again
+                Some(job_id) if report.status == NodeStatus::Running && !queue.contains(job_id as u64) => {
+                    report.status = NodeStatus::Waiting;
+                    report.job_id = None;
+                    self.received[i].clear();
+                }
+                Some(job_id) => {
+                    self.by_job.insert(job_id, i);
+                }
+                None => {}
+            }
+        }
+    }
+
+    /// Save progress, or forget it once every node is done
+    fn save(&self) {
+        let Some(path) = &self.state_path else {
+            return;
+        };
+        let saved = if self.is_finished() {
+            match fs::remove_file(path) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+                _ => Ok(()),
+            }
+        } else {
+            let state = SavedRun {
+                reports: self.reports.clone(),
+                received: self.received.clone(),
+                next_subtask: self.next_subtask,
+            };
+            let temp = path.with_extension("json.tmp");
+            serde_json::to_vec(&state)
+                .map_err(io::Error::from)
+                .and_then(|bytes| fs::write(&temp, bytes))
+                .and_then(|()| fs::rename(&temp, path))
+        };
+        if let Err(err) = saved {
+            eprintln!("❌ Failed to save graph progress to {}: {}", path.display(), err);
+        }
+    }
+
+    /// Record a worker's result. Returns false for results that don't belong
+    /// to this graph.
+    pub fn on_result(&mut self, result: &TaskResult, queue: &mut JobQueue) -> bool {
+        let Some(&i) = self.by_job.get(&result.job_id) else {
+            return false;
+        };
+        let report = &mut self.reports[i];
+        // Redelivered jobs report again; the first outcome stands
+        if report.status != NodeStatus::Running || !self.received[i].insert(result.id) {
+            return true;
+        }
+
+        match result.status {
+            TaskStatus::Failed => {
+                report.status = NodeStatus::Failed;
+                report.details = result.details.clone();
+            }
+            TaskStatus::Cancelled => {
+                report.status = NodeStatus::Cancelled;
+                report.details = result.details.clone();
+            }
+            TaskStatus::Completed => {
+                report.outputs.extend(result.outputs.clone());
+                if self.received[i].len() == self.nodes[i].job.subtasks.len() {
+                    report.status = NodeStatus::Completed;
+                }
+            }
+        }
+        if report.status != NodeStatus::Running {
+            println!("🔗 Node '{}' {}", report.name, report.status);
+            self.schedule(queue);
+        } else {
+            self.save();
+        }
+        true
+    }
+
+    /// The queue gave up on job `job_id`: fail its node as if a subtask had
+    pub fn give_up(&mut self, job_id: usize, reason: String, queue: &mut JobQueue) -> bool {
+        self.settle(job_id, NodeStatus::Failed, reason, queue)
+    }
+
+    /// Job `job_id` left the queue cancelled without its worker reporting
+    pub fn cancelled(&mut self, job_id: usize, queue: &mut JobQueue) -> bool {
+        self.settle(job_id, NodeStatus::Cancelled, "Cancelled".to_string(), queue)
+    }
+
+    /// Cancel node `name`; its dependents are skipped. A node whose job is
+    /// running is cancelled once the worker reports back. Returns false for
+    /// unknown nodes.
+    pub fn cancel_node(&mut self, name: &str, queue: &mut JobQueue) -> bool {
+        let Some(i) = self.reports.iter().position(|report| report.name == name) else {
+            return false;
+        };
+        self.cancel_at(i, queue);
+        self.schedule(queue);
+        true
+    }
+
+    /// Cancel every node that hasn't finished
+    pub fn cancel(&mut self, queue: &mut JobQueue) {
+        for i in 0..self.reports.len() {
+            self.cancel_at(i, queue);
+        }
+        self.save();
+    }
+
+    fn cancel_at(&mut self, i: usize, queue: &mut JobQueue) {
+        let report = &mut self.reports[i];
+        match (report.status, report.job_id) {
+            (NodeStatus::Waiting, _) => {}
+            (NodeStatus::Running, Some(job_id)) => match queue.cancel(job_id as u64) {
+                Ok(CancelOutcome::Running) => return, // Settled by the worker's results
+                Ok(_) => {}
+                Err(err) => {
+                    eprintln!("❌ Failed to cancel job {}: {}", job_id, err);
+                    return;
+                }
+            },
+            _ => return,
+        }
+        report.status = NodeStatus::Cancelled;
+        report.details = Some("Cancelled before it started".to_string());
+        println!("🔗 Node '{}' {}", report.name, report.status);
+    }
+
+    fn settle(&mut self, job_id: usize, status: NodeStatus, details: String, queue: &mut JobQueue) -> bool {
+        let Some(&i) = self.by_job.get(&job_id) else {
+            return false;
+        };
+        if self.reports[i].status == NodeStatus::Running {
+            self.reports[i].status = status;
+            self.reports[i].details = Some(details);
+            println!("🔗 Node '{}' {}", self.reports[i].name, status);
+            self.schedule(queue);
+        }
+        true
+    }
+
+    /// No node is waiting or running
+    pub fn is_finished(&self) -> bool {
+        self.reports
+            .iter()
+            .all(|report| !matches!(report.status, NodeStatus::Waiting | NodeStatus::Running))
+    }
+
+    pub fn reports(&self) -> &[NodeReport] {
+        &self.reports
+    }
+
+    pub fn into_reports(self) -> Vec<NodeReport> {
+        self.reports
+    }
+
+    /// Settle every waiting node whose predecessors are all done, then save
+    /// progress. A single pass in dependency order is enough, since skips and
+    /// empty jobs settle before their dependents are looked at.
+    fn schedule(&mut self, queue: &mut JobQueue) {
+        for &i in &self.order {
+            if self.reports[i].status != NodeStatus::Waiting {
+                continue;
+            }
+            if let Some(&p) = self.preds[i]
+                .iter()
+                .find(|&&p| {
+                    matches!(
+                        self.reports[p].status,
+                        NodeStatus::Failed | NodeStatus::Skipped | NodeStatus::Cancelled
+                    )
+                })
+            {
+                self.reports[i].status = NodeStatus::Skipped;
+                self.reports[i].details = Some(format!("'{}' did not complete", self.reports[p].name));
+                println!("⏭️ Skipping node '{}'", self.reports[i].name);
+                continue;
+            }
+            if !self.preds[i].iter().all(|&p| self.reports[p].status == NodeStatus::Completed) {
+                continue;
+            }
+
+            let mut job = self.nodes[i].job.clone();
+            if let Err(details) = self.resolve_inputs(i, &mut job) {
+                self.reports[i].status = NodeStatus::Failed;
+                self.reports[i].details = Some(details);
+                continue;
+            }
+            if job.subtasks.is_empty() {
+                self.reports[i].status = NodeStatus::Completed;
+                self.reports[i].outputs = job.inputs;
+                continue;
+            }
+            for subtask in &mut job.subtasks {
+                subtask.id = self.next_subtask;
+                self.next_subtask += 1;
+            }
+            match queue.enqueue(job) {
+                Ok(id) => {
+                    self.by_job.insert(id as usize, i);
+                    self.reports[i].job_id = Some(id as usize);
+                    self.reports[i].status = NodeStatus::Running;
+                }
+                Err(err) => {
+                    self.reports[i].status = NodeStatus::Failed;
+                    self.reports[i].details = Some(err.to_string());
+                }
+            }
+        }
+        self.save();
+    }
+
+    fn resolve_inputs(&self, i: usize, job: &mut Job) -> Result<(), String> {
+        for (input, reference) in &self.nodes[i].inputs {
+            // Checked when the graph was submitted
+            let (source, output) = reference.split_once('.').unwrap_or_default();
+            let report = self.reports.iter().find(|report| report.name == source);
+            let Some(value) = report.and_then(|report| report.outputs.get(output)) else {
+                return Err(format!("Input '{}': '{}' has no output '{}'", input, source, output));
+            };
+            job.inputs.insert(input.clone(), value.clone());
+        }
+        Ok(())
+    }
+}
+
+/// `graphs/<hash of the graph>.json` beside the queue file, so each distinct
+/// graph has its own progress
+fn state_path(queue_path: &Path, graph: &JobGraph) -> Result<PathBuf, GraphError> {
+    let dir = queue_path.parent().unwrap_or(Path::new("")).join("graphs");
+    fs::create_dir_all(&dir)?;
+    let document = serde_json::to_vec(graph).map_err(io::Error::from)?;
+    let hash = format!("{:x}", Sha256::digest(document));
+    Ok(dir.join(format!("{}.json", &hash[..16])))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task_model::{Step, Subtask, TaskStep};
+
+    fn job(name: &str) -> Job {
+        Job::new(name).then(Subtask {
+            id: 0,
+            description: name.to_string(),
+            step: Step::new(TaskStep::OpenWebsite("https://example.com".to_string())),
+            requires: Default::default(),
+        })
+    }
+
+    fn graph(edges: &[(&str, &[&str])]) -> JobGraph {
+        edges.iter().fold(JobGraph::new(), |graph, (name, after)| {
+            let node = after.iter().fold(GraphNode::new(*name, job(name)), |node, dep| node.after(*dep));
+            graph.node(node)
+        })
+    }
+
+    fn names(graph: &JobGraph, order: &[usize]) -> Vec<String> {
+        order.iter().map(|&i| graph.nodes[i].name.clone()).collect()
+    }
+
+    #[test]
+    fn orders_nodes_after_their_dependencies() {
+        let graph = graph(&[("report", &["fetch", "login"]), ("fetch", &["login"]), ("login", &[])]);
+        let (preds, order) = graph.validate().unwrap();
+        assert_eq!(names(&graph, &order), ["login", "fetch", "report"]);
+        assert_eq!(preds[0], BTreeSet::from([1, 2]));
+    }
+
+    #[test]
+    fn detects_cycles() {
+        let graph = graph(&[("a", &["c"]), ("b", &["a"]), ("c", &["b"]), ("d", &[])]);
+        match graph.validate() {
+            Err(GraphError::Cycle(cycle)) => {
+                assert_eq!(cycle.len(), 4, "{:?}", cycle);
+                assert_eq!(cycle.first(), cycle.last());
+                for name in ["a", "b", "c"] {
+                    assert!(cycle.iter().any(|n| n == name), "{:?}", cycle);
+                }
+                let message = GraphError::Cycle(cycle).to_string();
+                assert!(message.starts_with("Dependency cycle: "), "{}", message);
+            }
+            other => panic!("expected a cycle, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn detects_self_dependencies() {
+        let graph = graph(&[("a", &["a"])]);
+        assert!(matches!(graph.validate(), Err(GraphError::Cycle(cycle)) if cycle == ["a", "a"]));
+    }
+
+    #[test]
+    fn inputs_count_as_dependencies() {
+        let graph = JobGraph::new()
+            .node(GraphNode::new("a", job("a")).input("token", "b", "token"))
+            .node(GraphNode::new("b", job("b")).after("a"));
+        assert!(matches!(graph.validate(), Err(GraphError::Cycle(_))));
+
+        let graph = JobGraph::new().node(GraphNode::new("a", job("a")).input("token", "b", ""));
+        assert!(matches!(graph.validate(), Err(GraphError::InvalidInput { .. })));
+    }
+
+    #[test]
+    fn rejects_bad_names_and_references() {
+        assert!(matches!(
+            graph(&[("a", &[]), ("a", &[])]).validate(),
+            Err(GraphError::DuplicateNode(name)) if name == "a"
+        ));
+        assert!(matches!(graph(&[("a.b", &[])]).validate(), Err(GraphError::InvalidName(_))));
+        assert!(matches!(
+            graph(&[("a", &["missing"])]).validate(),
+            Err(GraphError::UnknownDependency { dependency, .. }) if dependency == "missing"
+        ));
+    }
+
+    #[test]
+    fn submit_enqueues_only_the_roots() {
+        let mut queue = JobQueue::new();
+        let run = GraphRun::submit(graph(&[("a", &[]), ("b", &["a"]), ("c", &[])]), &mut queue).unwrap();
+        assert_eq!(queue.len(), 2);
+        let statuses: Vec<_> = run.reports().iter().map(|report| report.status).collect();
+        assert_eq!(statuses, [NodeStatus::Running, NodeStatus::Waiting, NodeStatus::Running]);
+        assert!(!run.is_finished());
+    }
+}
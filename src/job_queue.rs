@@ -0,0 +1,844 @@
+use crate::capabilities::{Capabilities, Requirements};
+use crate::scheduling::{tenant_of, FairShare, Priority, SchedulingPolicy};
+use crate::task_model::Job;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use thiserror::Error;
+
+/// Where the coordinator keeps its queue between runs
+pub const DEFAULT_QUEUE_PATH: &str = "queue/jobs.jsonl";
+
+/// How long a worker may hold a job before it is handed to someone else
+pub const DEFAULT_VISIBILITY_TIMEOUT_SECS: i64 = 300;
+
+/// Deliveries before a job is given up on as poison
+pub const DEFAULT_MAX_ATTEMPTS: u32 = 3;
+
+/// The queue shared between the coordinator and its workers
+pub type SharedQueue = Arc<parking_lot::Mutex<JobQueue>>;
+
+#[derive(Error, Debug)]
+pub enum QueueError {
+    #[error("Failed to persist queue: {0}")]
+    Io(#[from] io::Error),
+    #[error("Lease on job {0} expired or was taken over")]
+    LeaseLost(u64),
+}
+
+/// A job handed to a worker. Only the holder of the latest lease on a job
+/// can ack or nack it.
+#[derive(Debug, Clone)]
+pub struct Lease {
+    pub id: u64,
+    pub attempt: u32, // 1 for the first delivery
+    pub job: Job,
+    pub until: DateTime<Utc>,
+}
+
+/// What `JobQueue::cancel` did
+#[derive(Debug, Clone)]
+pub enum CancelOutcome {
+    Dequeued(Job), // Nobody held it; it is gone
+    Running,       // Its worker is told to stop and reports back
+    NotFound,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct QueueStats {
+    pub ready: usize,
+    pub delayed: usize, // Nacked with a delay that hasn't passed yet
+    pub leased: usize,
+}
+
+/// Where a job stands, see `JobQueue::positions`
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueuePosition {
+    pub id: u64,
+    pub description: String,
+    pub tenant: String,
+    pub priority: Priority,
+    pub attempts: u32,
+    pub position: Option<usize>, // 1 is next; `None` while a worker holds it
+}
+
+#[derive(Debug, Clone)]
+enum JobState {
+    Ready { visible_at: DateTime<Utc> },
+    Leased { worker_id: usize, until: DateTime<Utc> },
+}
+
+#[derive(Debug, Clone)]
+struct QueuedJob {
+    job: Job,
+    attempts: u32,
+    state: JobState,
+}
+
+/// One line of the queue file. Replaying them in order rebuilds the queue.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum QueueOp {
+    Enqueue {
+        id: u64,
+        job: Job,
+        #[serde(default, skip_serializing_if = "is_zero")]
+        attempts: u32, // Deliveries before the file was compacted
+    },
+    Lease { id: u64, attempt: u32, worker_id: usize, until: DateTime<Utc> },
+    Ack { id: u64 },
+    Nack { id: u64, visible_at: DateTime<Utc> },
+    Poison { id: u64 },
+    Reject { id: u64 }, // No worker could run it
+    Cancel { id: u64 },
+    CancelRequested { id: u64 }, // Its worker was told to stop; it is never delivered again
+}
+
+/// Job queue with at-least-once delivery.
+///
+/// Jobs are leased highest priority first. Within a priority, tenants take
+/// turns in proportion to their weights (oldest job first within a tenant),
+/// and a tenant at its quota of running jobs waits for one of them to end.
+///
+/// Workers `lease` a job for a visibility timeout and `ack` it when done. A
+/// job that is nacked, or whose lease runs out because its worker died, is
+/// delivered again. When opened on a file every change is appended to it, so
+/// a restarted coordinator picks up pending and in-flight jobs where it left
+/// off; leases held by the old process simply time out. A job that keeps
+/// coming back is dropped after `max_attempts` deliveries.
+pub struct JobQueue {
+    jobs: BTreeMap<u64, QueuedJob>,
+    next_id: u64,
+    closed: bool, // No more jobs will be enqueued
+    max_attempts: u32,
+    poisoned: Vec<(Job, u32)>, // Given up on since the last `take_poisoned`
+    cancelling: BTreeSet<u64>, // Leased jobs whose workers should stop
+    policy: SchedulingPolicy,
+    share: FairShare, // Not persisted; a restarted queue starts everyone even
+    path: Option<PathBuf>,
+    file: Option<File>,
+    stale_ops: usize, // Lines in the file that no longer describe a live job
+}
+
+impl Default for JobQueue {
+    fn default() -> Self {
+        JobQueue {
+            jobs: BTreeMap::new(),
+            next_id: 0,
+            closed: false,
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            poisoned: Vec::new(),
+            cancelling: BTreeSet::new(),
+            policy: SchedulingPolicy::default(),
+            share: FairShare::default(),
+            path: None,
+            file: None,
+            stale_ops: 0,
+        }
+    }
+}
+
+impl JobQueue {
+    /// In-memory only
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load the queue stored at `path` (creating it if needed) and persist
+    /// every change to it
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+
+        let mut queue = Self::read(path)?;
+        if !queue.jobs.is_empty() {
+            println!("📥 Resuming {} queued job(s) from {}", queue.jobs.len(), path.display());
+        }
+        queue.path = Some(path.to_path_buf());
+        queue.compact()?;
+        Ok(queue)
+    }
+
+    /// A snapshot of the queue stored at `path`, safe to take while a
+    /// coordinator has it open; changes to it are not persisted
+    pub fn read(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let mut queue = Self::default();
+        if path.exists() {
+            let mut skipped = 0;
+            for line in BufReader::new(File::open(path)?).lines() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                match serde_json::from_str::<QueueOp>(&line) {
+                    Ok(op) => queue.apply(op),
+                    Err(_) => skipped += 1,
+                }
+            }
+            if skipped > 0 {
+                eprintln!("⚠️ Skipped {} unreadable line(s) in {}", skipped, path.display());
+            }
+        }
+        Ok(queue)
+    }
+
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// Share workers between tenants according to `policy`
+    pub fn scheduling(mut self, policy: SchedulingPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Add a job; its `id` is replaced with the queue's id for it
+    pub fn enqueue(&mut self, mut job: Job) -> Result<u64, QueueError> {
+        let id = self.next_id;
+        job.id = id as usize;
+        self.record(QueueOp::Enqueue {
+            id,
+            job,
+            attempts: 0,
+        })?;
+        Ok(id)
+    }
+
+    /// Hand the next visible job that `capabilities` can run to `worker_id`
+    /// for `visibility`. Jobs already delivered `max_attempts` times are set
+    /// aside instead.
+    pub fn lease(
+        &mut self,
+        worker_id: usize,
+        capabilities: &Capabilities,
+        visibility: Duration,
+    ) -> Result<Option<Lease>, QueueError> {
+        let now = Utc::now();
+        let (id, job) = loop {
+            let running = self.running_by_tenant(now);
+            let Some((&id, job)) = self
+                .jobs
+                .iter()
+                .filter(|(id, _)| !self.cancelling.contains(id))
+                .filter(|(_, job)| is_visible(job, now) && can_run(capabilities, job))
+                .filter(|(_, job)| {
+                    let tenant = tenant_of(&job.job);
+                    self.policy.has_room(tenant, running.get(tenant).copied().unwrap_or(0))
+                })
+                .min_by(|(a_id, a), (b_id, b)| {
+                    b.job
+                        .priority
+                        .cmp(&a.job.priority)
+                        .then_with(|| {
+                            let (a, b) = (tenant_of(&a.job), tenant_of(&b.job));
+                            self.share.start(a).total_cmp(&self.share.start(b))
+                        })
+                        .then(a_id.cmp(b_id))
+                })
+            else {
+                return Ok(None);
+            };
+            if job.attempts < self.max_attempts {
+                break (id, job);
+            }
+            let poisoned = (job.job.clone(), job.attempts);
+            eprintln!("☠️ Giving up on job {} after {} attempts", id, job.attempts);
+            self.record(QueueOp::Poison { id })?;
+            self.poisoned.push(poisoned);
+        };
+        let attempt = job.attempts + 1;
+        let until = now + visibility;
+        let job = job.job.clone();
+        let tenant = tenant_of(&job);
+        self.share.charge(tenant, self.policy.tenant(tenant).weight);
+        self.record(QueueOp::Lease {
+            id,
+            attempt,
+            worker_id,
+            until,
+        })?;
+        Ok(Some(Lease {
+            id,
+            attempt,
+            job,
+            until,
+        }))
+    }
+
+    /// The job is done and leaves the queue
+    pub fn ack(&mut self, lease: &Lease) -> Result<(), QueueError> {
+        self.check(lease)?;
+        self.record(QueueOp::Ack { id: lease.id })
+    }
+
+    /// Give the job back, to be delivered again after `delay`
+    pub fn nack(&mut self, lease: &Lease, delay: Duration) -> Result<(), QueueError> {
+        self.check(lease)?;
+        self.record(QueueOp::Nack {
+            id: lease.id,
+            visible_at: Utc::now() + delay,
+        })
+    }
+
+    /// Keep holding a long-running job for another `visibility`
+    pub fn extend(&mut self, lease: &mut Lease, visibility: Duration) -> Result<(), QueueError> {
+        self.check(lease)?;
+        let JobState::Leased { worker_id, .. } = self.jobs[&lease.id].state else {
+            return Err(QueueError::LeaseLost(lease.id));
+        };
+        lease.until = Utc::now() + visibility;
+        self.record(QueueOp::Lease {
+            id: lease.id,
+            attempt: lease.attempt,
+            worker_id,
+            until: lease.until,
+        })
+    }
+
+    /// Put every job `worker_id` holds back on the queue at once, because the
+    /// worker died or went silent. Returns their ids.
+    pub fn release_worker(&mut self, worker_id: usize) -> Result<Vec<u64>, QueueError> {
+        let held: Vec<u64> = self
+            .jobs
+            .iter()
+            .filter(|(_, job)| matches!(job.state, JobState::Leased { worker_id: holder, .. } if holder == worker_id))
+            .map(|(&id, _)| id)
+            .collect();
+        for &id in &held {
+            self.record(QueueOp::Nack {
+                id,
+                visible_at: Utc::now(),
+            })?;
+        }
+        Ok(held)
+    }
+
+    /// Jobs that nobody holds and that `can_run` turns down, with their
+    /// requirements (`None` when its subtasks contradict each other)
+    pub fn unmatched(&self, can_run: impl Fn(&Requirements) -> bool) -> Vec<(u64, Option<Requirements>)> {
+        let now = Utc::now();
+        self.jobs
+            .iter()
+            .filter(|(_, job)| !matches!(job.state, JobState::Leased { until, .. } if until > now))
+            .filter_map(|(&id, job)| match job.job.requirements() {
+                Some(requirements) if can_run(&requirements) => None,
+                requirements => Some((id, requirements)),
+            })
+            .collect()
+    }
+
+    /// Drop a job no worker can run
+    pub fn reject(&mut self, id: u64) -> Result<Option<Job>, QueueError> {
+        let Some(job) = self.jobs.get(&id).map(|job| job.job.clone()) else {
+            return Ok(None);
+        };
+        self.record(QueueOp::Reject { id })?;
+        Ok(Some(job))
+    }
+
+    /// Cancel a job: one nobody holds leaves the queue right away, a running
+    /// one is flagged for its worker (see `is_cancelling`). The flag is saved,
+    /// so a restarted coordinator doesn't deliver the job again.
+    pub fn cancel(&mut self, id: u64) -> Result<CancelOutcome, QueueError> {
+        let Some(job) = self.jobs.get(&id) else {
+            return Ok(CancelOutcome::NotFound);
+        };
+        if matches!(job.state, JobState::Leased { until, .. } if until > Utc::now()) {
+            if !self.cancelling.contains(&id) {
+                self.record(QueueOp::CancelRequested { id })?;
+            }
+            return Ok(CancelOutcome::Running);
+        }
+        let job = job.job.clone();
+        self.record(QueueOp::Cancel { id })?;
+        Ok(CancelOutcome::Dequeued(job))
+    }
+
+    /// The holder of job `id` should stop working on it
+    pub fn is_cancelling(&self, id: u64) -> bool {
+        self.cancelling.contains(&id)
+    }
+
+    /// Drop cancelled jobs whose worker let go of them without finishing,
+    /// e.g. because it died; returns them
+    pub fn drop_cancelled(&mut self) -> Result<Vec<Job>, QueueError> {
+        let now = Utc::now();
+        let released: Vec<u64> = self
+            .cancelling
+            .iter()
+            .filter(|id| !matches!(self.jobs[id].state, JobState::Leased { until, .. } if until > now))
+            .copied()
+            .collect();
+        let mut dropped = Vec::new();
+        for id in released {
+            dropped.push(self.jobs[&id].job.clone());
+            self.record(QueueOp::Cancel { id })?;
+        }
+        Ok(dropped)
+    }
+
+    /// Jobs given up on since the last call, with how often they were tried
+    pub fn take_poisoned(&mut self) -> Vec<(Job, u32)> {
+        std::mem::take(&mut self.poisoned)
+    }
+
+    /// Stop accepting work; workers exit once the queue drains
+    pub fn close(&mut self) {
+        self.closed = true;
+    }
+
+    /// Closed and nothing left, leased or not
+    pub fn is_finished(&self) -> bool {
+        self.closed && self.jobs.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.jobs.len()
+    }
+
+    /// Whether job `id` is still waiting or leased
+    pub fn contains(&self, id: u64) -> bool {
+        self.jobs.contains_key(&id)
+    }
+
+    /// The file this queue is kept in, if any
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.jobs.is_empty()
+    }
+
+    /// Subtasks of the jobs ready to go that `capabilities` could run,
+    /// leaving out jobs their tenant's quota holds back
+    pub fn ready_subtasks_for(&self, capabilities: &Capabilities) -> usize {
+        let now = Utc::now();
+        let mut ready: HashMap<&str, Vec<usize>> = HashMap::new();
+        for job in self.jobs.values() {
+            if is_visible(job, now) && can_run(capabilities, job) {
+                ready.entry(tenant_of(&job.job)).or_default().push(job.job.subtasks.len().max(1));
+            }
+        }
+        let running = self.running_by_tenant(now);
+        ready
+            .into_iter()
+            .map(|(tenant, subtasks)| {
+                let allowed = match self.policy.tenant(tenant).max_running {
+                    Some(max) => max.saturating_sub(running.get(tenant).copied().unwrap_or(0)),
+                    None => subtasks.len(),
+                };
+                subtasks.iter().take(allowed).sum::<usize>()
+            })
+            .sum()
+    }
+
+    /// Every job with its place in line, in lease order. Positions assume
+    /// any worker could take any job right now; requirements, quotas and
+    /// retry delays can still reorder them.
+    pub fn positions(&self) -> Vec<QueuePosition> {
+        let now = Utc::now();
+        let waiting = self
+            .jobs
+            .iter()
+            .filter(|(_, job)| !matches!(job.state, JobState::Leased { until, .. } if until > now))
+            .map(|(&id, job)| (id, job.job.priority, tenant_of(&job.job)));
+        let order = self.share.order(&self.policy, waiting);
+
+        let entry = |id: u64, position: Option<usize>| {
+            let job = &self.jobs[&id];
+            QueuePosition {
+                id,
+                description: job.job.description.clone(),
+                tenant: tenant_of(&job.job).to_string(),
+                priority: job.job.priority,
+                attempts: job.attempts,
+                position,
+            }
+        };
+        let mut positions: Vec<QueuePosition> = self
+            .jobs
+            .iter()
+            .filter(|(_, job)| matches!(job.state, JobState::Leased { until, .. } if until > now))
+            .map(|(&id, _)| entry(id, None))
+            .collect();
+        positions.extend(order.into_iter().enumerate().map(|(i, id)| entry(id, Some(i + 1))));
+        positions
+    }
+
+    pub fn stats(&self) -> QueueStats {
+        let now = Utc::now();
+        let mut stats = QueueStats::default();
+        for job in self.jobs.values() {
+            match job.state {
+                JobState::Leased { until, .. } if until > now => stats.leased += 1,
+                JobState::Ready { visible_at } if visible_at > now => stats.delayed += 1,
+                _ => stats.ready += 1,
+            }
+        }
+        stats
+    }
+
+    /// Workers currently holding an unexpired lease
+    pub fn busy_workers(&self) -> BTreeSet<usize> {
+        let now = Utc::now();
+        self.jobs
+            .values()
+            .filter_map(|job| match job.state {
+                JobState::Leased { worker_id, until } if until > now => Some(worker_id),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Rewrite the backing file with one line per live job
+    pub fn compact(&mut self) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            self.stale_ops = 0;
+            return Ok(());
+        };
+        let temp = path.with_extension("jsonl.tmp");
+        {
+            let mut out = io::BufWriter::new(File::create(&temp)?);
+            for (&id, job) in &self.jobs {
+                let mut ops = vec![QueueOp::Enqueue {
+                    id,
+                    job: job.job.clone(),
+                    attempts: job.attempts,
+                }];
+                match job.state {
+                    JobState::Leased { worker_id, until } => ops.push(QueueOp::Lease {
+                        id,
+                        attempt: job.attempts,
+                        worker_id,
+                        until,
+                    }),
+                    JobState::Ready { visible_at } if visible_at > Utc::now() => {
+                        ops.push(QueueOp::Nack { id, visible_at })
+                    }
+                    JobState::Ready { .. } => {}
+                }
+                if self.cancelling.contains(&id) {
+                    ops.push(QueueOp::CancelRequested { id });
+                }
+                for op in ops {
+                    writeln!(out, "{}", serde_json::to_string(&op)?)?;
+                }
+            }
+            out.flush()?;
+            out.get_ref().sync_data()?;
+        }
+        fs::rename(&temp, path)?;
+        self.file = Some(OpenOptions::new().create(true).append(true).open(path)?);
+        self.stale_ops = 0;
+        Ok(())
+    }
+
+    /// Unexpired leases per tenant
+    fn running_by_tenant(&self, now: DateTime<Utc>) -> HashMap<&str, usize> {
+        let mut running = HashMap::new();
+        for job in self.jobs.values() {
+            if matches!(job.state, JobState::Leased { until, .. } if until > now) {
+                *running.entry(tenant_of(&job.job)).or_default() += 1;
+            }
+        }
+        running
+    }
+
+    fn check(&self, lease: &Lease) -> Result<(), QueueError> {
+        match self.jobs.get(&lease.id) {
+            Some(job) if job.attempts == lease.attempt && matches!(job.state, JobState::Leased { .. }) => Ok(()),
+            _ => Err(QueueError::LeaseLost(lease.id)),
+        }
+    }
+
+    /// Persist `op`, then apply it
+    fn record(&mut self, op: QueueOp) -> Result<(), QueueError> {
+        if let Some(file) = &mut self.file {
+            writeln!(file, "{}", serde_json::to_string(&op).map_err(io::Error::from)?)?;
+            file.sync_data()?;
+        }
+        self.apply(op);
+
+        // Every ack leaves a few dead lines behind
+        if self.stale_ops > 1000 && self.stale_ops > self.jobs.len() * 4 {
+            self.compact()?;
+        }
+        Ok(())
+    }
+
+    fn apply(&mut self, op: QueueOp) {
+        match op {
+            QueueOp::Enqueue { id, job, attempts } => {
+                self.next_id = self.next_id.max(id + 1);
+                self.jobs.insert(
+                    id,
+                    QueuedJob {
+                        job,
+                        attempts,
+                        state: JobState::Ready { visible_at: Utc::now() },
+                    },
+                );
+            }
+            QueueOp::Lease {
+                id,
+                attempt,
+                worker_id,
+                until,
+            } => {
+                if let Some(job) = self.jobs.get_mut(&id) {
+                    job.attempts = attempt;
+                    job.state = JobState::Leased { worker_id, until };
+                }
+                self.stale_ops += 1;
+            }
+            QueueOp::Ack { id } | QueueOp::Poison { id } | QueueOp::Reject { id } | QueueOp::Cancel { id } => {
+                self.jobs.remove(&id);
+                self.cancelling.remove(&id);
+                self.stale_ops += 2;
+            }
+            QueueOp::Nack { id, visible_at } => {
+                if let Some(job) = self.jobs.get_mut(&id) {
+                    job.state = JobState::Ready { visible_at };
+                }
+                self.stale_ops += 1;
+            }
+            QueueOp::CancelRequested { id } => {
+                if self.jobs.contains_key(&id) {
+                    self.cancelling.insert(id);
+                }
+            }
+        }
+    }
+}
+
+fn is_zero(n: &u32) -> bool {
+    *n == 0
+}
+
+fn can_run(capabilities: &Capabilities, job: &QueuedJob) -> bool {
+    job.job
+        .requirements()
+        .is_some_and(|requirements| capabilities.satisfies(&requirements))
+}
+
+fn is_visible(job: &QueuedJob, now: DateTime<Utc>) -> bool {
+    match job.state {
+        JobState::Ready { visible_at } => visible_at <= now,
+        JobState::Leased { until, .. } => until <= now, // The worker gave up or died
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::browser_config::BrowserKind;
+    use crate::capabilities::BrowserNeed;
+    use crate::task_model::{Step, Subtask, TaskStep};
+
+    const VISIBILITY: Duration = Duration::seconds(60);
+
+    fn any_worker() -> Capabilities {
+        Capabilities::default()
+    }
+
+    fn queue_with(descriptions: &[&str]) -> JobQueue {
+        let mut queue = JobQueue::new();
+        for description in descriptions {
+            queue.enqueue(Job::new(*description)).unwrap();
+        }
+        queue
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sae-job-queue-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        dir.join("jobs.jsonl")
+    }
+
+    #[test]
+    fn leases_oldest_first_and_ack_removes() {
+        let mut queue = queue_with(&["a", "b"]);
+        let first = queue.lease(1, &any_worker(), VISIBILITY).unwrap().unwrap();
+        assert_eq!((first.id, first.attempt, first.job.description.as_str()), (0, 1, "a"));
+
+        let second = queue.lease(2, &any_worker(), VISIBILITY).unwrap().unwrap();
+        assert_eq!(second.id, 1);
+        assert!(queue.lease(3, &any_worker(), VISIBILITY).unwrap().is_none(), "both jobs are held");
+
+        queue.ack(&first).unwrap();
+        queue.ack(&second).unwrap();
+        assert!(queue.is_empty());
+        queue.close();
+        assert!(queue.is_finished());
+    }
+
+    #[test]
+    fn higher_priority_goes_first() {
+        let mut queue = JobQueue::new();
+        queue.enqueue(Job::new("low").priority(Priority::Low)).unwrap();
+        queue.enqueue(Job::new("urgent").priority(Priority::Urgent)).unwrap();
+        let lease = queue.lease(1, &any_worker(), VISIBILITY).unwrap().unwrap();
+        assert_eq!(lease.job.description, "urgent");
+    }
+
+    #[test]
+    fn nack_redelivers_after_the_delay() {
+        let mut queue = queue_with(&["a"]);
+        let lease = queue.lease(1, &any_worker(), VISIBILITY).unwrap().unwrap();
+        queue.nack(&lease, Duration::seconds(60)).unwrap();
+        assert!(queue.lease(1, &any_worker(), VISIBILITY).unwrap().is_none(), "still delayed");
+        assert_eq!(queue.stats().delayed, 1);
+
+        let mut queue = queue_with(&["a"]);
+        let lease = queue.lease(1, &any_worker(), VISIBILITY).unwrap().unwrap();
+        queue.nack(&lease, Duration::zero()).unwrap();
+        let again = queue.lease(2, &any_worker(), VISIBILITY).unwrap().unwrap();
+        assert_eq!((again.id, again.attempt), (0, 2));
+    }
+
+    #[test]
+    fn expired_lease_is_taken_over() {
+        let mut queue = queue_with(&["a"]);
+        let stale = queue.lease(1, &any_worker(), Duration::zero()).unwrap().unwrap();
+        let fresh = queue.lease(2, &any_worker(), VISIBILITY).unwrap().unwrap();
+        assert_eq!(fresh.attempt, 2);
+        assert!(matches!(queue.ack(&stale), Err(QueueError::LeaseLost(0))));
+        queue.ack(&fresh).unwrap();
+    }
+
+    #[test]
+    fn release_worker_requeues_everything_it_held() {
+        let mut queue = queue_with(&["a", "b", "c"]);
+        queue.lease(7, &any_worker(), VISIBILITY).unwrap();
+        queue.lease(7, &any_worker(), VISIBILITY).unwrap();
+        queue.lease(8, &any_worker(), VISIBILITY).unwrap();
+        assert_eq!(queue.release_worker(7).unwrap(), vec![0, 1]);
+        assert_eq!(queue.stats(), QueueStats { ready: 2, delayed: 0, leased: 1 });
+    }
+
+    #[test]
+    fn poisons_a_job_after_max_attempts() {
+        let mut queue = queue_with(&["flaky"]).max_attempts(2);
+        for _ in 0..2 {
+            let lease = queue.lease(1, &any_worker(), VISIBILITY).unwrap().unwrap();
+            queue.nack(&lease, Duration::zero()).unwrap();
+        }
+        assert!(queue.lease(1, &any_worker(), VISIBILITY).unwrap().is_none());
+        let poisoned = queue.take_poisoned();
+        assert_eq!(poisoned.len(), 1);
+        assert_eq!((poisoned[0].0.description.as_str(), poisoned[0].1), ("flaky", 2));
+        assert!(queue.is_empty());
+        assert!(queue.take_poisoned().is_empty());
+    }
+
+    #[test]
+    fn only_leases_jobs_the_worker_can_run() {
+        let mut queue = JobQueue::new();
+        let step = Step::new(TaskStep::OpenWebsite("https://example.com".to_string()));
+        let subtask = Subtask {
+            id: 0,
+            description: "open".to_string(),
+            step,
+            requires: Requirements::default().browser(BrowserNeed::Chrome),
+        };
+        queue.enqueue(Job::new("chrome").then(subtask)).unwrap();
+
+        let firefox = Capabilities {
+            browsers: BTreeSet::from([BrowserKind::Firefox]),
+            ..Capabilities::default()
+        };
+        assert!(queue.lease(1, &firefox, VISIBILITY).unwrap().is_none());
+        assert_eq!(queue.unmatched(|requirements| firefox.satisfies(requirements)).len(), 1);
+
+        let chrome = Capabilities {
+            browsers: BTreeSet::from([BrowserKind::Chrome]),
+            ..Capabilities::default()
+        };
+        assert!(queue.lease(2, &chrome, VISIBILITY).unwrap().is_some());
+    }
+
+    #[test]
+    fn replays_the_file_it_was_opened_on() {
+        let path = temp_path("replay");
+        {
+            let mut queue = JobQueue::open(&path).unwrap();
+            for description in ["a", "b", "c"] {
+                queue.enqueue(Job::new(description)).unwrap();
+            }
+            let done = queue.lease(1, &any_worker(), VISIBILITY).unwrap().unwrap();
+            queue.ack(&done).unwrap();
+            queue.lease(1, &any_worker(), VISIBILITY).unwrap().unwrap(); // Still held when we "crash"
+        }
+
+        let snapshot = JobQueue::read(&path).unwrap();
+        assert_eq!(snapshot.len(), 2);
+        assert!(!snapshot.contains(0));
+
+        let mut queue = JobQueue::open(&path).unwrap();
+        assert_eq!(queue.stats(), QueueStats { ready: 1, delayed: 0, leased: 1 });
+        let lease = queue.lease(2, &any_worker(), VISIBILITY).unwrap().unwrap();
+        assert_eq!((lease.id, lease.job.description.as_str()), (2, "c"));
+        assert_eq!(queue.enqueue(Job::new("d")).unwrap(), 3, "ids are not reused");
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn skips_unreadable_lines() {
+        let path = temp_path("corrupt");
+        {
+            let mut queue = JobQueue::open(&path).unwrap();
+            queue.enqueue(Job::new("a")).unwrap();
+        }
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        writeln!(file, "{{\"op\": \"enq").unwrap();
+        assert_eq!(JobQueue::read(&path).unwrap().len(), 1);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn cancelling_a_running_job_survives_a_restart() {
+        let path = temp_path("cancel");
+        let id = {
+            let mut queue = JobQueue::open(&path).unwrap();
+            queue.enqueue(Job::new("a")).unwrap();
+            let lease = queue.lease(1, &any_worker(), VISIBILITY).unwrap().unwrap();
+            assert!(matches!(queue.cancel(lease.id).unwrap(), CancelOutcome::Running));
+            lease.id
+        };
+
+        for reopened in [JobQueue::open(&path).unwrap(), JobQueue::read(&path).unwrap()] {
+            assert!(reopened.is_cancelling(id));
+        }
+        let mut queue = JobQueue::open(&path).unwrap();
+        queue.compact().unwrap();
+        let mut queue = JobQueue::open(&path).unwrap();
+        assert!(queue.is_cancelling(id), "compaction keeps the request");
+
+        // Once the old lease runs out the job is dropped, not delivered again
+        queue.release_worker(1).unwrap();
+        assert!(queue.lease(2, &any_worker(), VISIBILITY).unwrap().is_none());
+        assert_eq!(queue.drop_cancelled().unwrap().len(), 1);
+        assert!(JobQueue::read(&path).unwrap().is_empty());
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn cancel_dequeues_idle_jobs_and_flags_running_ones() {
+        let mut queue = queue_with(&["running", "idle"]);
+        let lease = queue.lease(1, &any_worker(), VISIBILITY).unwrap().unwrap();
+        assert!(matches!(queue.cancel(1).unwrap(), CancelOutcome::Dequeued(job) if job.description == "idle"));
+        assert!(matches!(queue.cancel(lease.id).unwrap(), CancelOutcome::Running));
+        assert!(queue.is_cancelling(lease.id));
+        assert!(queue.lease(2, &any_worker(), Duration::zero()).unwrap().is_none());
+        assert!(matches!(queue.cancel(42).unwrap(), CancelOutcome::NotFound));
+    }
+}
@@ -0,0 +1,24 @@
+pub mod automation_engine;
+pub mod web_interaction;
+pub mod browser_config;
+pub mod target;
+pub mod observation_layer;
+pub mod task_library;
+pub mod bundle;
+pub mod ui;
+pub mod executor;
+pub mod cancellation;
+pub mod control_flow;
+pub mod central_coordinator;
+pub mod job_queue;
+pub mod job_graph;
+pub mod worker;
+pub mod worker_pool;
+pub mod capabilities;
+pub mod scheduling;
+pub mod remote;
+pub mod task_model;
+pub mod workflow;
+pub mod rewards;
+pub mod run_report;
+pub mod variables;
@@ -1,20 +1,767 @@
-mod automation_engine;
-mod web_interaction;
-mod task_library;
-mod ui;
-mod executor;
-mod central_coordinator;
-mod worker;
-mod task_model;
-mod rewards;
-
-
-use central_coordinator::task_distributor;
+use systematic_automation::browser_config::BrowserConfig;
+use systematic_automation::cancellation::RunControl;
+use systematic_automation::capabilities::Capabilities;
+use systematic_automation::bundle::{export_bundle, import_bundle, ConflictPolicy, ExportOptions, ImportOptions};
+use systematic_automation::central_coordinator::{run_graph, task_distributor, DEFAULT_SITES};
+use systematic_automation::executor::execute_task;
+use systematic_automation::job_graph::{JobGraph, NodeStatus};
+use systematic_automation::job_queue::{JobQueue, DEFAULT_QUEUE_PATH};
+use systematic_automation::scheduling::{parse_pairs, parse_weights, SchedulingPolicy};
+use systematic_automation::observation_layer::event_recorder::{EventRecorder, RecordedSession};
+use systematic_automation::observation_layer::event_replayer::{replay_events, ReplayOptions, ReplayPolicy};
+use systematic_automation::observation_layer::interaction_log::install_log_sink;
+use systematic_automation::observation_layer::log_repository::{LogQuery, LogRepository, Retention, DEFAULT_LOG_PATH};
+use systematic_automation::observation_layer::session_compiler::{compile_session, CompileOptions};
+use systematic_automation::task_library::{
+    library_dir, resolve_workflow, set_library_dir, DiffKind, LibrarySearch, TaskLibrary,
+};
+use systematic_automation::web_interaction::start_browser;
+use systematic_automation::workflow::{load_workflow, save_workflow};
+use systematic_automation::worker_pool::PoolConfig;
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::ExitCode;
 
 #[tokio::main]
-async fn main() {
+async fn main() -> ExitCode {
     println!("🚀 Welcome to the Systematic Automation Framework!");
-    
-    // Start the Central Coordinator
-    task_distributor().await;
-}
\ No newline at end of file
+
+    // Browser settings come from `SAE_*` variables; `run` flags override them
+    let mut browser = match BrowserConfig::from_env() {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("❌ {}", err);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    // `--library <dir>` anywhere picks the task library every command uses
+    if let Some(at) = args.iter().position(|arg| arg == "--library") {
+        let Some(dir) = args.get(at + 1).cloned() else {
+            eprintln!("❌ Missing value for '--library'");
+            return ExitCode::FAILURE;
+        };
+        set_library_dir(dir);
+        args.drain(at..at + 2);
+    }
+    match args.first().map(String::as_str) {
+        // `run <workflow file or library task> [--report <report.json>] [--arg name=value]...
+        //      [--browser firefox|chrome] [--webdriver <url>] [--headless]`
+        Some("run") => {
+            let Some(workflow_path) = args.get(1) else {
+                eprintln!(
+                    "Usage: systematic_automation run <workflow> [--report <file>] [--arg name=value]... \
+                     [--browser firefox|chrome] [--webdriver <url>] [--headless]"
+                );
+                return ExitCode::FAILURE;
+            };
+            let mut report_path = None;
+            let mut task_args = HashMap::new();
+            let mut rest = args[2..].iter();
+            while let Some(flag) = rest.next() {
+                if flag == "--headless" {
+                    browser.headless = true;
+                    continue;
+                }
+                match (flag.as_str(), rest.next()) {
+                    ("--report", Some(path)) => report_path = Some(path),
+                    ("--arg", Some(pair)) => match pair.split_once('=') {
+                        Some((name, value)) => {
+                            task_args.insert(name.to_string(), value.to_string());
+                        }
+                        None => {
+                            eprintln!("❌ Expected --arg name=value, got '{}'", pair);
+                            return ExitCode::FAILURE;
+                        }
+                    },
+                    ("--browser", Some(name)) => match name.parse() {
+                        Ok(kind) => browser.browser = kind,
+                        Err(err) => {
+                            eprintln!("❌ {}", err);
+                            return ExitCode::FAILURE;
+                        }
+                    },
+                    ("--webdriver", Some(url)) => browser.webdriver_url = url.clone(),
+                    _ => {
+                        eprintln!("❌ Unexpected argument '{}'", flag);
+                        return ExitCode::FAILURE;
+                    }
+                }
+            }
+
+            // `./login`, `/abs/login` or `login.yaml` is a file; anything else a library task
+            let task = match resolve_workflow(workflow_path) {
+                Ok(task) => task,
+                Err(err) => {
+                    eprintln!("❌ {}", err);
+                    return ExitCode::FAILURE;
+                }
+            };
+
+            install_default_log_sink();
+            let report = execute_task(task, &task_args, &browser, &signal_control()).await;
+            if let Some(path) = report_path {
+                if let Err(err) = report.write_json(path) {
+                    eprintln!("❌ Failed to write report to {}: {}", path, err);
+                    return ExitCode::FAILURE;
+                }
+            }
+
+            if report.passed() {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::FAILURE
+            }
+        }
+        // `record <url> [--out <session.json>]`, stopped with Ctrl-C
+        Some("record") => {
+            let Some(url) = args.get(1) else {
+                eprintln!("Usage: systematic_automation record <url> [--out <session.json>]");
+                return ExitCode::FAILURE;
+            };
+            let out = match args.get(2..4) {
+                Some([flag, path]) if flag == "--out" => path.clone(),
+                _ => "session.json".to_string(),
+            };
+
+            let mut client = match start_browser(&browser).await {
+                Ok(client) => client,
+                Err(err) => {
+                    eprintln!("❌ Failed to start browser: {:?}", err);
+                    return ExitCode::FAILURE;
+                }
+            };
+            let mut recorder = EventRecorder::new();
+            let result = async {
+                client.goto(url).await?;
+                recorder.start(&mut client).await?;
+                println!("🔴 Recording {}; press Ctrl-C to stop", url);
+                recorder
+                    .record_until(&mut client, async {
+                        let _ = tokio::signal::ctrl_c().await;
+                    })
+                    .await?;
+                recorder.save(&out)
+            }
+            .await;
+            if let Err(err) = client.close().await {
+                eprintln!("❌ Failed to close browser: {:?}", err);
+            }
+
+            match result {
+                Ok(()) => {
+                    println!("💾 Saved {} event(s) to {}", recorder.get_events().len(), out);
+                    ExitCode::SUCCESS
+                }
+                Err(err) => {
+                    eprintln!("❌ {}", err);
+                    ExitCode::FAILURE
+                }
+            }
+        }
+        // `replay <session.json> [--speed <scale>] [--continue]`
+        Some("replay") => {
+            let Some(session_path) = args.get(1) else {
+                eprintln!("Usage: systematic_automation replay <session.json> [--speed <scale>] [--continue]");
+                return ExitCode::FAILURE;
+            };
+            let mut options = ReplayOptions::default();
+            let mut rest = args[2..].iter();
+            while let Some(flag) = rest.next() {
+                if flag == "--continue" {
+                    options.policy = ReplayPolicy::Continue;
+                    continue;
+                }
+                match (flag.as_str(), rest.next()) {
+                    ("--speed", Some(scale)) => match scale.parse() {
+                        Ok(scale) => options.delay_scale = scale,
+                        Err(_) => {
+                            eprintln!("❌ Expected a number after --speed, got '{}'", scale);
+                            return ExitCode::FAILURE;
+                        }
+                    },
+                    _ => {
+                        eprintln!("❌ Unexpected argument '{}'", flag);
+                        return ExitCode::FAILURE;
+                    }
+                }
+            }
+
+            let session = match RecordedSession::load(session_path) {
+                Ok(session) => session,
+                Err(err) => {
+                    eprintln!("❌ {}", err);
+                    return ExitCode::FAILURE;
+                }
+            };
+            let mut client = match start_browser(&browser).await {
+                Ok(client) => client,
+                Err(err) => {
+                    eprintln!("❌ Failed to start browser: {:?}", err);
+                    return ExitCode::FAILURE;
+                }
+            };
+            let mut logs = match LogRepository::open(DEFAULT_LOG_PATH, Retention::default()) {
+                Ok(logs) => logs,
+                Err(err) => {
+                    eprintln!("❌ Failed to open {}: {}", DEFAULT_LOG_PATH, err);
+                    return ExitCode::FAILURE;
+                }
+            };
+            let summary = replay_events(&mut client, &session.events, &options, &mut logs).await;
+            if let Err(err) = client.close().await {
+                eprintln!("❌ Failed to close browser: {:?}", err);
+            }
+
+            if summary.passed() {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::FAILURE
+            }
+        }
+        // `logs [--run <id>] [--task <id>] [--step <n>] [--worker <n>] [--action <a>]
+        //       [--outcome passed|failed|skipped] [--since <time>] [--until <time>]
+        //       [--limit <n>]`, times in RFC 3339
+        Some("logs") => {
+            let mut query = LogQuery::default();
+            let mut rest = args[1..].iter();
+            while let Some(flag) = rest.next() {
+                let Some(value) = rest.next() else {
+                    eprintln!("❌ Missing value for '{}'", flag);
+                    return ExitCode::FAILURE;
+                };
+                let valid = match flag.as_str() {
+                    "--run" => {
+                        query.run_id = Some(value.clone());
+                        true
+                    }
+                    "--task" => {
+                        query.task_id = Some(value.clone());
+                        true
+                    }
+                    "--step" => {
+                        query.step_index = value.parse().ok();
+                        query.step_index.is_some()
+                    }
+                    "--action" => {
+                        query.action = value.parse().ok();
+                        query.action.is_some()
+                    }
+                    "--outcome" => {
+                        query.outcome = value.parse().ok();
+                        query.outcome.is_some()
+                    }
+                    "--worker" => {
+                        query.worker_id = value.parse().ok();
+                        query.worker_id.is_some()
+                    }
+                    "--limit" => {
+                        query.limit = value.parse().ok();
+                        query.limit.is_some()
+                    }
+                    "--since" => {
+                        query.since = parse_time(value);
+                        query.since.is_some()
+                    }
+                    "--until" => {
+                        query.until = parse_time(value);
+                        query.until.is_some()
+                    }
+                    _ => {
+                        eprintln!("❌ Unexpected argument '{}'", flag);
+                        return ExitCode::FAILURE;
+                    }
+                };
+                if !valid {
+                    eprintln!("❌ Invalid value '{}' for {}", value, flag);
+                    return ExitCode::FAILURE;
+                }
+            }
+
+            match LogRepository::read(DEFAULT_LOG_PATH, Retention::default()) {
+                Ok(logs) => {
+                    logs.view(&query);
+                    ExitCode::SUCCESS
+                }
+                Err(err) => {
+                    eprintln!("❌ Failed to open {}: {}", DEFAULT_LOG_PATH, err);
+                    ExitCode::FAILURE
+                }
+            }
+        }
+        // `compile <session.json> <workflow file> [--name <name>]`
+        Some("compile") => {
+            let (Some(session_path), Some(workflow_path)) = (args.get(1), args.get(2)) else {
+                eprintln!("Usage: systematic_automation compile <session.json> <workflow> [--name <name>]");
+                return ExitCode::FAILURE;
+            };
+            let name = match args.get(3..5) {
+                Some([flag, name]) if flag == "--name" => name.clone(),
+                _ => Path::new(workflow_path)
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+                    .unwrap_or_else(|| "recording".to_string()),
+            };
+
+            let session = match RecordedSession::load(session_path) {
+                Ok(session) => session,
+                Err(err) => {
+                    eprintln!("❌ {}", err);
+                    return ExitCode::FAILURE;
+                }
+            };
+            let task = compile_session(&session, &name, &CompileOptions::default());
+            match save_workflow(&task, workflow_path) {
+                Ok(()) => {
+                    println!("💾 Saved workflow '{}' to {}", task.name, workflow_path);
+                    ExitCode::SUCCESS
+                }
+                Err(err) => {
+                    eprintln!("❌ {}", err);
+                    ExitCode::FAILURE
+                }
+            }
+        }
+        Some("library") => match library_command(&args[1..]) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(err) => {
+                eprintln!("❌ {}", err);
+                ExitCode::FAILURE
+            }
+        },
+        Some("bundle") => match bundle_command(&args[1..]) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(err) => {
+                eprintln!("❌ {}", err);
+                ExitCode::FAILURE
+            }
+        },
+        // `queue [--tenant <name>]`: queued jobs in the order workers will get them
+        Some("queue") => match queue_command(&args[1..]) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(err) => {
+                eprintln!("❌ {}", err);
+                ExitCode::FAILURE
+            }
+        },
+        // `graph <graph.yaml> [pool flags]`: jobs with dependencies, run on the worker pool
+        Some("graph") => {
+            let Some(path) = args.get(1) else {
+                eprintln!("Usage: systematic_automation graph <graph.yaml> {}", POOL_USAGE);
+                return ExitCode::FAILURE;
+            };
+            let pool = match pool_config(&args[2..]) {
+                Ok((pool, _)) => pool,
+                Err(err) => {
+                    eprintln!("❌ {}", err);
+                    return ExitCode::FAILURE;
+                }
+            };
+            let graph = match JobGraph::load(path) {
+                Ok(graph) => graph,
+                Err(err) => {
+                    eprintln!("❌ {}: {}", path, err);
+                    return ExitCode::FAILURE;
+                }
+            };
+            let queue = match JobQueue::open(DEFAULT_QUEUE_PATH) {
+                Ok(queue) => queue,
+                Err(err) => {
+                    eprintln!("❌ Failed to open {}: {}", DEFAULT_QUEUE_PATH, err);
+                    return ExitCode::FAILURE;
+                }
+            };
+            let capabilities = match Capabilities::from_env(&browser) {
+                Ok(capabilities) => capabilities,
+                Err(err) => {
+                    eprintln!("❌ {}", err);
+                    return ExitCode::FAILURE;
+                }
+            };
+            install_default_log_sink();
+            let reports = match run_graph(browser, capabilities, queue, graph, pool, signal_control()).await {
+                Ok(reports) => reports,
+                Err(err) => {
+                    eprintln!("❌ {}", err);
+                    return ExitCode::FAILURE;
+                }
+            };
+            for report in &reports {
+                match &report.details {
+                    Some(details) => println!("{:<24} {:<10} {}", report.name, report.status, details),
+                    None => println!("{:<24} {}", report.name, report.status),
+                }
+            }
+            if reports.iter().all(|report| report.status == NodeStatus::Completed) {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::FAILURE
+            }
+        }
+        // `[--sites <file>] [pool flags]`: visit each site on the worker pool
+        _ => {
+            let (pool, sites) = match pool_config(&args) {
+                Ok(config) => config,
+                Err(err) => {
+                    eprintln!("❌ {}", err);
+                    eprintln!("Usage: systematic_automation [--sites <file>] {}", POOL_USAGE);
+                    return ExitCode::FAILURE;
+                }
+            };
+
+            // Start the Central Coordinator, resuming any queued jobs
+            let queue = match JobQueue::open(DEFAULT_QUEUE_PATH) {
+                Ok(queue) => queue,
+                Err(err) => {
+                    eprintln!("❌ Failed to open {}: {}", DEFAULT_QUEUE_PATH, err);
+                    return ExitCode::FAILURE;
+                }
+            };
+            let capabilities = match Capabilities::from_env(&browser) {
+                Ok(capabilities) => capabilities,
+                Err(err) => {
+                    eprintln!("❌ {}", err);
+                    return ExitCode::FAILURE;
+                }
+            };
+            install_default_log_sink();
+            task_distributor(browser, capabilities, queue, &sites, pool, signal_control()).await;
+            ExitCode::SUCCESS
+        }
+    }
+}
+
+const POOL_USAGE: &str =
+    "[--workers <n>] [--min-workers <n>] [--max-workers <n>] [--autoscale true|false] [--listen <addr>] \
+     [--tenant-weight <tenant>=<n>] [--tenant-quota <tenant>=<n>]";
+
+/// Worker pool settings (`SAE_*` variables overridden by flags) and the sites
+/// to visit: one URL per line of `--sites`, or the built-in list
+fn pool_config(args: &[String]) -> Result<(PoolConfig, Vec<String>), String> {
+    let (positional, flags) = split_flags(args)?;
+    if let Some(arg) = positional.first() {
+        return Err(format!("Unexpected argument '{}'", arg));
+    }
+    let mut pool = PoolConfig::from_env()?;
+    let mut sites: Vec<String> = DEFAULT_SITES.iter().map(|site| site.to_string()).collect();
+    for (flag, value) in &flags {
+        let number = || {
+            value
+                .parse::<usize>()
+                .map_err(|_| format!("{} expects a whole number, got '{}'", flag, value))
+        };
+        match flag.as_str() {
+            "--workers" => pool.workers = number()?,
+            "--min-workers" => pool.min_workers = number()?,
+            "--max-workers" => pool.max_workers = number()?,
+            "--autoscale" => {
+                pool.autoscale = value
+                    .parse()
+                    .map_err(|_| format!("--autoscale expects true or false, got '{}'", value))?
+            }
+            "--listen" => pool.listen = Some(value.clone()),
+            "--tenant-weight" => {
+                for (tenant, weight) in parse_weights(flag, value)? {
+                    pool.scheduling = pool.scheduling.weight(tenant, weight)?;
+                }
+            }
+            "--tenant-quota" => {
+                for (tenant, quota) in parse_pairs(flag, value)? {
+                    pool.scheduling = pool.scheduling.quota(tenant, quota);
+                }
+            }
+            "--sites" => {
+                let content =
+                    std::fs::read_to_string(value).map_err(|err| format!("Failed to read {}: {}", value, err))?;
+                sites = content
+                    .lines()
+                    .map(str::trim)
+                    .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                    .map(str::to_string)
+                    .collect();
+            }
+            _ => return Err(format!("Unexpected argument '{}'", flag)),
+        }
+    }
+    pool.check()?;
+    Ok((pool, sites))
+}
+
+/// `queue [--tenant <name>]`: show where every queued job stands. The
+/// running coordinator's fair-share history isn't visible from here, so the
+/// order is an estimate.
+fn queue_command(args: &[String]) -> Result<(), String> {
+    let (positional, flags) = split_flags(args)?;
+    if let Some(arg) = positional.first() {
+        return Err(format!("Unexpected argument '{}'", arg));
+    }
+    let mut tenant = None;
+    for (flag, value) in &flags {
+        match flag.as_str() {
+            "--tenant" => tenant = Some(value.as_str()),
+            _ => return Err(format!("Unexpected argument '{}'", flag)),
+        }
+    }
+
+    let queue = JobQueue::read(DEFAULT_QUEUE_PATH)
+        .map_err(|err| format!("Failed to read {}: {}", DEFAULT_QUEUE_PATH, err))?
+        .scheduling(SchedulingPolicy::from_env()?);
+    let positions: Vec<_> = queue
+        .positions()
+        .into_iter()
+        .filter(|entry| tenant.is_none_or(|tenant| entry.tenant == tenant))
+        .collect();
+    if positions.is_empty() {
+        println!("No queued jobs");
+    }
+    for entry in positions {
+        println!(
+            "{:>7}  job {:<6} {:<8} {:<12} {}{}",
+            entry.position.map(|position| format!("#{}", position)).unwrap_or_else(|| "running".to_string()),
+            entry.id,
+            entry.priority,
+            entry.tenant,
+            entry.description,
+            if entry.attempts > 0 { format!(" (delivered {} time(s))", entry.attempts) } else { String::new() }
+        );
+    }
+    Ok(())
+}
+
+const LIBRARY_USAGE: &str = "Usage: systematic_automation library <command>
+  list [--search <text>] [--tag <tag>] [--author <name>]
+  add <workflow> [--author <name>] [--note <text>] [--tag <tag>]...
+  history <task>
+  export <task> <workflow> [--version <n>]
+  diff <task> <from version> <to version>
+  rename <task> <new name>
+  delete <task>";
+
+/// `library ...`: manage saved tasks. Tasks are named by name or id.
+fn library_command(args: &[String]) -> Result<(), String> {
+    let library = TaskLibrary::open(library_dir()).map_err(|err| err.to_string())?;
+    let (command, rest) = args.split_first().ok_or(LIBRARY_USAGE)?;
+    let (positional, flags) = split_flags(rest)?;
+    let flag = |name: &str| flags.iter().find(|(flag, _)| flag == name).map(|(_, value)| value.clone());
+    let version = |name: &str| -> Result<Option<u32>, String> {
+        flag(name)
+            .map(|value| value.parse().map_err(|_| format!("Invalid version '{}'", value)))
+            .transpose()
+    };
+
+    match (command.as_str(), positional.as_slice()) {
+        ("list", []) => {
+            let search = LibrarySearch {
+                text: flag("--search"),
+                tag: flag("--tag"),
+                author: flag("--author"),
+            };
+            for entry in library.search(&search).map_err(|err| err.to_string())? {
+                let tags = entry.tags.iter().cloned().collect::<Vec<_>>().join(", ");
+                println!(
+                    "{}  {}  v{}  updated {}{}",
+                    entry.id,
+                    entry.name,
+                    entry.latest().version,
+                    entry.updated_at.format("%Y-%m-%d %H:%M"),
+                    if tags.is_empty() { String::new() } else { format!("  [{}]", tags) }
+                );
+            }
+        }
+        ("add", [path]) => {
+            let task = load_workflow(path).map_err(|err| err.to_string())?;
+            let entry = library
+                .save(&task, flag("--author").as_deref(), flag("--note").as_deref())
+                .map_err(|err| err.to_string())?;
+            let new_tags: Vec<String> = flags
+                .iter()
+                .filter(|(flag, _)| flag == "--tag")
+                .map(|(_, tag)| tag.clone())
+                .collect();
+            if !new_tags.is_empty() {
+                let tags = entry.tags.iter().cloned().chain(new_tags);
+                library.set_tags(&entry.id, tags).map_err(|err| err.to_string())?;
+            }
+        }
+        ("history", [key]) => {
+            let entry = library.get(key).map_err(|err| err.to_string())?;
+            println!("{} ({})", entry.name, entry.id);
+            for version in &entry.versions {
+                println!(
+                    "  v{}  {}  {}  {}",
+                    version.version,
+                    version.saved_at.format("%Y-%m-%d %H:%M"),
+                    version.author.as_deref().unwrap_or("-"),
+                    version.note.as_deref().unwrap_or("")
+                );
+            }
+        }
+        ("export", [key, path]) => {
+            let task = library.load_task(key, version("--version")?).map_err(|err| err.to_string())?;
+            save_workflow(&task, path).map_err(|err| err.to_string())?;
+            println!("💾 Saved workflow '{}' to {}", task.name, path);
+        }
+        ("diff", [key, from, to]) => {
+            let parse = |value: &String| value.parse::<u32>().map_err(|_| format!("Invalid version '{}'", value));
+            let lines = library.diff(key, parse(from)?, parse(to)?).map_err(|err| err.to_string())?;
+            if lines.iter().all(|line| line.kind == DiffKind::Same) {
+                println!("No differences");
+            }
+            for line in &lines {
+                println!("{}", line);
+            }
+        }
+        ("rename", [key, new_name]) => {
+            library.rename(key, new_name).map_err(|err| err.to_string())?;
+        }
+        ("delete", [key]) => {
+            library.delete(key).map_err(|err| err.to_string())?;
+        }
+        _ => return Err(LIBRARY_USAGE.to_string()),
+    }
+    Ok(())
+}
+
+const BUNDLE_USAGE: &str = "Usage: systematic_automation bundle <command>
+  export <task or workflow> <bundle.tar.gz> [--version <n>] [--upload <file>]... [--baseline <file>]...
+  import <bundle.tar.gz> [--assets <dir>] [--on-conflict rename|new_version|fail] [--author <name>]";
+
+/// `bundle ...`: share workflows with everything they need as one archive
+fn bundle_command(args: &[String]) -> Result<(), String> {
+    let (command, rest) = args.split_first().ok_or(BUNDLE_USAGE)?;
+    let (positional, flags) = split_flags(rest)?;
+    let flag = |name: &str| flags.iter().find(|(flag, _)| flag == name).map(|(_, value)| value.clone());
+    let all = |name: &str| -> Vec<std::path::PathBuf> {
+        flags
+            .iter()
+            .filter(|(flag, _)| flag == name)
+            .map(|(_, value)| value.into())
+            .collect()
+    };
+
+    match (command.as_str(), positional.as_slice()) {
+        ("export", [reference, out]) => {
+            let task = match flag("--version") {
+                Some(version) => {
+                    let version = version.parse().map_err(|_| format!("Invalid version '{}'", version))?;
+                    TaskLibrary::open(library_dir())
+                        .and_then(|library| library.load_task(reference, Some(version)))
+                        .map_err(|err| err.to_string())?
+                }
+                None => resolve_workflow(reference)?,
+            };
+            let options = ExportOptions {
+                uploads: all("--upload"),
+                baselines: all("--baseline"),
+            };
+            export_bundle(&task, out, &options).map_err(|err| err.to_string())?;
+        }
+        ("import", [bundle]) => {
+            let mut options = ImportOptions {
+                author: flag("--author"),
+                ..ImportOptions::default()
+            };
+            if let Some(dir) = flag("--assets") {
+                options.assets_dir = dir.into();
+            }
+            if let Some(policy) = flag("--on-conflict") {
+                options.on_conflict = match policy.as_str() {
+                    "rename" => ConflictPolicy::Rename,
+                    "new_version" => ConflictPolicy::NewVersion,
+                    "fail" => ConflictPolicy::Fail,
+                    _ => return Err(format!("Unknown conflict policy '{}'", policy)),
+                };
+            }
+            let library = TaskLibrary::open(library_dir()).map_err(|err| err.to_string())?;
+            let summary = import_bundle(bundle, &library, &options).map_err(|err| err.to_string())?;
+            for (original, imported) in &summary.workflows {
+                if original == imported {
+                    println!("  {}", imported);
+                } else {
+                    println!("  {} (imported as {})", original, imported);
+                }
+            }
+        }
+        _ => return Err(BUNDLE_USAGE.to_string()),
+    }
+    Ok(())
+}
+
+/// `--flag value` pairs in command-line order
+type Flags = Vec<(String, String)>;
+
+/// Separate `--flag value` pairs from positional arguments
+fn split_flags(args: &[String]) -> Result<(Vec<&String>, Flags), String> {
+    let mut positional = Vec::new();
+    let mut flags = Vec::new();
+    let mut rest = args.iter();
+    while let Some(arg) = rest.next() {
+        if arg.starts_with("--") {
+            let value = rest.next().ok_or_else(|| format!("Missing value for '{}'", arg))?;
+            flags.push((arg.clone(), value.clone()));
+        } else {
+            positional.push(arg);
+        }
+    }
+    Ok((positional, flags))
+}
+
+/// A control driven by signals: Ctrl-C cancels cleanly (a second one exits
+/// at once), and on Unix `SIGUSR1` pauses between steps and `SIGUSR2` resumes
+fn signal_control() -> RunControl {
+    let control = RunControl::new();
+    {
+        let control = control.clone();
+        tokio::spawn(async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                println!("🛑 Cancelling (Ctrl-C again to exit immediately)");
+                control.cancel();
+                if tokio::signal::ctrl_c().await.is_ok() {
+                    std::process::exit(130);
+                }
+            }
+        });
+    }
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let control = control.clone();
+        tokio::spawn(async move {
+            let (Ok(mut pause), Ok(mut resume)) =
+                (signal(SignalKind::user_defined1()), signal(SignalKind::user_defined2()))
+            else {
+                return;
+            };
+            loop {
+                tokio::select! {
+                    Some(()) = pause.recv() => {
+                        println!("⏸️ Pausing after the current step");
+                        control.pause();
+                    }
+                    Some(()) = resume.recv() => control.resume(),
+                    else => break,
+                }
+            }
+        });
+    }
+    control
+}
+
+/// Persist every browser action's log entry to `DEFAULT_LOG_PATH`; runs go on
+/// without logs if it cannot be opened
+fn install_default_log_sink() {
+    match LogRepository::open(DEFAULT_LOG_PATH, Retention::default()) {
+        Ok(logs) => {
+            install_log_sink(logs);
+        }
+        Err(err) => eprintln!("⚠️ Not persisting interaction logs, failed to open {}: {}", DEFAULT_LOG_PATH, err),
+    }
+}
+
+fn parse_time(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value)
+        .ok()
+        .map(|time| time.with_timezone(&Utc))
+}
@@ -1,11 +1,327 @@
-use webdriver::WebDriver; // Hypothetical WebDriver trait
-
-pub async fn find_element(driver: &WebDriver, selector: &str) -> Result<Element, String> {
-    match driver.find(selector).await {
-        Ok(element) => Ok(element),
-        Err(_) => {
-            println!("Fallback: Searching for element similar to '{}'", selector);
-            driver.find_near_text("Login").await // Hypothetical fallback mechanism
+use crate::target::Target;
+use fantoccini::elements::Element;
+use fantoccini::error::CmdError;
+use fantoccini::Client;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+
+/// Matches scoring below this are not trusted to be the element that was recorded
+pub const DEFAULT_CONFIDENCE: f64 = 0.6;
+
+/// What an element looked like when it was recorded, used to find it again
+/// after its selector stops matching
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ElementFingerprint {
+    pub tag: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub classes: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>, // Visible text, whitespace collapsed
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub attributes: BTreeMap<String, String>, // name, type, href, placeholder, aria-label, ...
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub position: Option<Rect>, // Page coordinates, so scrolling doesn't move it
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub neighbours: Vec<String>, // Text of adjacent siblings and the element's label
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Rect {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+/// An element found by fingerprint instead of by its target
+#[derive(Debug, Clone)]
+pub struct Healed {
+    pub target: Target, // CSS target for the matched element, keeping the fingerprint
+    pub confidence: f64,
+}
+
+#[derive(Deserialize)]
+struct Candidate {
+    selector: String,
+    fingerprint: ElementFingerprint,
+}
+
+/// JavaScript helpers shared by fingerprint capture and candidate collection:
+/// `describe(el)` builds an `ElementFingerprint`, `cssPath(el)` a selector
+/// that matches only `el`.
+pub const FINGERPRINT_JS: &str = r#"
+const clean = (s) => (s || '').trim().replace(/\s+/g, ' ');
+const describe = (el) => {
+    const attributes = {};
+    for (const name of ['name', 'type', 'href', 'placeholder', 'aria-label', 'role', 'title', 'alt', 'value', 'data-testid', 'for']) {
+        const value = el.getAttribute(name);
+        if (value !== null && value !== '') attributes[name] = value;
+    }
+    const neighbours = [];
+    const push = (n) => { const t = n ? clean(n.innerText || n.textContent).slice(0, 80) : ''; if (t) neighbours.push(t); };
+    push(el.previousElementSibling);
+    push(el.nextElementSibling);
+    if (el.id) document.querySelectorAll('label[for="' + CSS.escape(el.id) + '"]').forEach(push);
+    push(el.closest('label'));
+    const rect = el.getBoundingClientRect();
+    const text = clean(el.innerText || el.textContent).slice(0, 200);
+    return {
+        tag: el.tagName.toLowerCase(),
+        id: el.id || null,
+        classes: Array.from(el.classList),
+        text: text || null,
+        attributes,
+        position: { x: rect.x + window.scrollX, y: rect.y + window.scrollY, width: rect.width, height: rect.height },
+        neighbours,
+    };
+};
+const uniqueId = (el) => el.id && document.querySelectorAll('#' + CSS.escape(el.id)).length === 1;
+const cssPath = (el) => {
+    const parts = [];
+    while (el && el.nodeType === 1 && el !== document.documentElement) {
+        if (uniqueId(el)) { parts.unshift('#' + CSS.escape(el.id)); break; }
+        let part = el.tagName.toLowerCase();
+        const siblings = el.parentElement ? Array.from(el.parentElement.children).filter(s => s.tagName === el.tagName) : [];
+        if (siblings.length > 1) part += ':nth-of-type(' + (siblings.indexOf(el) + 1) + ')';
+        parts.unshift(part);
+        el = el.parentElement;
+    }
+    return parts.join(' > ');
+};
+"#;
+
+/// Record the fingerprint of an element that is on the page now
+pub async fn capture_fingerprint(client: &mut Client, element: &Element) -> Result<ElementFingerprint, CmdError> {
+    let element = serde_json::to_value(element)?;
+    let script = format!("{}\nreturn describe(arguments[0]);", FINGERPRINT_JS);
+    let value = client.execute(&script, vec![element]).await?;
+    Ok(serde_json::from_value(value)?)
+}
+
+/// Look for the element `target`'s fingerprint describes and return a CSS
+/// target for the best candidate scoring at least `threshold`. `None` when
+/// the target has no fingerprint or nothing is similar enough.
+pub async fn heal(client: &mut Client, target: &Target, threshold: f64) -> Option<Healed> {
+    let expected = target.fingerprint.as_deref()?;
+    println!("🩹 Searching for an element resembling: {}", target);
+
+    let script = format!(
+        "{}
+        const [tag, id, name] = arguments;
+        const seen = new Set();
+        const found = [];
+        const add = (el) => {{ if (!seen.has(el)) {{ seen.add(el); found.push(el); }} }};
+        if (tag) document.querySelectorAll(tag).forEach(add);
+        if (id) document.querySelectorAll('[id=\"' + CSS.escape(id) + '\"]').forEach(add);
+        if (name) document.querySelectorAll('[name=\"' + CSS.escape(name) + '\"]').forEach(add);
+        return found.slice(0, 500).map(el => ({{ selector: cssPath(el), fingerprint: describe(el) }}));",
+        FINGERPRINT_JS
+    );
+    let args = vec![
+        serde_json::json!(expected.tag),
+        serde_json::json!(expected.id),
+        serde_json::json!(expected.attributes.get("name")),
+    ];
+    let candidates: Vec<Candidate> = match client.execute(&script, args).await.map(serde_json::from_value) {
+        Ok(Ok(candidates)) => candidates,
+        Ok(Err(err)) => {
+            eprintln!("❌ Unexpected candidate list while healing {}: {}", target, err);
+            return None;
+        }
+        Err(err) => {
+            eprintln!("❌ Failed to collect candidates while healing {}: {:?}", target, err);
+            return None;
+        }
+    };
+
+    let (best, confidence) = candidates
+        .into_iter()
+        .map(|candidate| {
+            let confidence = score(expected, &candidate.fingerprint);
+            (candidate, confidence)
+        })
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))?;
+    if confidence < threshold {
+        println!(
+            "🩹 Best match for {} was {} with confidence {:.2}, below {:.2}",
+            target, best.selector, confidence, threshold
+        );
+        return None;
+    }
+
+    let mut healed = Target::css(best.selector);
+    healed.fingerprint = target.fingerprint.clone();
+    println!(
+        "🩹 Healed {} -> {} (confidence {:.2}); consider updating the workflow",
+        target, healed, confidence
+    );
+    Some(Healed { target: healed, confidence })
+}
+
+/// How alike two fingerprints are, from 0 to 1. Only features the expected
+/// fingerprint actually has count, so sparse recordings aren't penalised.
+pub fn score(expected: &ElementFingerprint, candidate: &ElementFingerprint) -> f64 {
+    let mut total = 0.0;
+    let mut weights = 0.0;
+    let mut add = |weight: f64, similarity: f64| {
+        total += weight * similarity;
+        weights += weight;
+    };
+
+    if !expected.tag.is_empty() {
+        add(0.10, (expected.tag.eq_ignore_ascii_case(&candidate.tag)) as u8 as f64);
+    }
+    if let Some(id) = &expected.id {
+        add(0.25, (candidate.id.as_ref() == Some(id)) as u8 as f64);
+    }
+    if !expected.classes.is_empty() {
+        add(0.10, jaccard(expected.classes.iter(), candidate.classes.iter()));
+    }
+    if let Some(text) = &expected.text {
+        add(0.20, text_similarity(text, candidate.text.as_deref().unwrap_or("")));
+    }
+    if !expected.attributes.is_empty() {
+        let matching = expected
+            .attributes
+            .iter()
+            .filter(|(name, value)| candidate.attributes.get(*name) == Some(value))
+            .count();
+        add(0.20, matching as f64 / expected.attributes.len() as f64);
+    }
+    if let (Some(a), Some(b)) = (expected.position, candidate.position) {
+        let distance = ((a.x - b.x).powi(2) + (a.y - b.y).powi(2)).sqrt();
+        add(0.05, 1.0 / (1.0 + distance / 100.0));
+    }
+    if !expected.neighbours.is_empty() {
+        add(0.10, jaccard(expected.neighbours.iter(), candidate.neighbours.iter()));
+    }
+
+    if weights == 0.0 {
+        0.0
+    } else {
+        total / weights
+    }
+}
+
+fn jaccard<'a>(a: impl Iterator<Item = &'a String>, b: impl Iterator<Item = &'a String>) -> f64 {
+    let a: HashSet<&str> = a.map(String::as_str).collect();
+    let b: HashSet<&str> = b.map(String::as_str).collect();
+    let union = a.union(&b).count();
+    if union == 0 {
+        return 1.0;
+    }
+    a.intersection(&b).count() as f64 / union as f64
+}
+
+fn text_similarity(a: &str, b: &str) -> f64 {
+    let a = a.trim().to_lowercase();
+    let b = b.trim().to_lowercase();
+    if a == b {
+        1.0
+    } else if a.is_empty() || b.is_empty() {
+        0.0
+    } else if a.contains(&b) || b.contains(&a) {
+        0.8
+    } else {
+        let words = |s: &str| s.split_whitespace().map(str::to_string).collect::<Vec<_>>();
+        jaccard(words(&a).iter(), words(&b).iter())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(items: &[&str]) -> Vec<String> {
+        items.iter().map(|item| item.to_string()).collect()
+    }
+
+    fn save_button() -> ElementFingerprint {
+        ElementFingerprint {
+            tag: "button".to_string(),
+            id: Some("save".to_string()),
+            classes: strings(&["btn", "primary"]),
+            text: Some("Save changes".to_string()),
+            attributes: BTreeMap::from([("type".to_string(), "submit".to_string())]),
+            position: None,
+            neighbours: strings(&["Cancel"]),
         }
     }
+
+    fn assert_near(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-9, "{} != {}", actual, expected);
+    }
+
+    #[test]
+    fn a_restyled_element_still_passes_the_threshold() {
+        let expected = save_button();
+        assert_near(score(&expected, &expected), 1.0);
+
+        let restyled = ElementFingerprint {
+            id: Some("save-2".to_string()),
+            classes: strings(&["btn", "btn-primary"]),
+            ..save_button()
+        };
+        // Everything but the id and one class still matches
+        assert_near(score(&expected, &restyled), (0.10 + 0.10 / 3.0 + 0.20 + 0.20 + 0.10) / 0.95);
+        assert!(score(&expected, &restyled) >= DEFAULT_CONFIDENCE);
+
+        let other = ElementFingerprint {
+            id: Some("delete".to_string()),
+            classes: strings(&["btn", "danger"]),
+            text: Some("Delete".to_string()),
+            attributes: BTreeMap::from([("type".to_string(), "button".to_string())]),
+            neighbours: Vec::new(),
+            ..save_button()
+        };
+        assert!(score(&expected, &other) < DEFAULT_CONFIDENCE);
+    }
+
+    #[test]
+    fn sparse_fingerprints_are_scored_on_what_they_have() {
+        let expected = ElementFingerprint {
+            tag: "a".to_string(),
+            text: Some("Next".to_string()),
+            ..ElementFingerprint::default()
+        };
+        let candidate = ElementFingerprint {
+            tag: "A".to_string(),
+            text: Some("Next page".to_string()),
+            ..save_button()
+        };
+        assert_near(score(&expected, &candidate), (0.10 + 0.20 * 0.8) / 0.30);
+        assert_near(score(&ElementFingerprint::default(), &candidate), 0.0);
+    }
+
+    #[test]
+    fn position_only_counts_when_both_have_one() {
+        let at = |x| Some(Rect { x, y: 0.0, width: 10.0, height: 10.0 });
+        let expected = ElementFingerprint {
+            position: at(0.0),
+            ..ElementFingerprint::default()
+        };
+        assert_near(score(&expected, &ElementFingerprint { position: at(100.0), ..expected.clone() }), 0.5);
+        assert_near(score(&expected, &ElementFingerprint::default()), 0.0);
+    }
+
+    #[test]
+    fn jaccard_of_nothing_is_a_full_match() {
+        let none: Vec<String> = Vec::new();
+        assert_near(jaccard(none.iter(), none.iter()), 1.0);
+        assert_near(jaccard(strings(&["a"]).iter(), none.iter()), 0.0);
+        assert_near(jaccard(strings(&["a", "b", "b"]).iter(), strings(&["b", "c"]).iter()), 1.0 / 3.0);
+    }
+
+    #[test]
+    fn text_similarity_ignores_case_and_rewards_overlap() {
+        assert_near(text_similarity("  Save Changes ", "save changes"), 1.0);
+        assert_near(text_similarity("", ""), 1.0);
+        assert_near(text_similarity("Save", ""), 0.0);
+        assert_near(text_similarity("Save", "Save changes"), 0.8);
+        assert_near(text_similarity("save all changes", "save changes now"), 0.5);
+        assert_near(text_similarity("save", "delete"), 0.0);
+    }
 }
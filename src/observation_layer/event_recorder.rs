@@ -0,0 +1,306 @@
+use super::dynamic_detection::FINGERPRINT_JS;
+use crate::target::Target;
+use chrono::{DateTime, Utc};
+use fantoccini::error::CmdError;
+use fantoccini::Client;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::future::Future;
+use std::path::Path;
+use thiserror::Error;
+use tokio::time::{sleep, Duration};
+
+/// Bumped whenever `RecordedSession` changes incompatibly
+pub const SESSION_FORMAT_VERSION: u32 = 1;
+
+#[derive(Error, Debug)]
+pub enum RecorderError {
+    #[error("WebDriver error: {0}")]
+    WebDriver(#[from] CmdError),
+    #[error("Failed to access {path}: {source}")]
+    Io {
+        path: String,
+        source: std::io::Error,
+    },
+    #[error("Invalid session: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Session format version {0} is not supported")]
+    UnsupportedVersion(u32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventAction {
+    Click,
+    Type, // `value` is the field's whole value after the keystroke, or none for a sensitive field
+    Select,
+    Navigate, // `url` is the new page; there is no target
+    Submit,
+}
+
+impl EventAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventAction::Click => "click",
+            EventAction::Type => "type",
+            EventAction::Select => "select",
+            EventAction::Navigate => "navigate",
+            EventAction::Submit => "submit",
+        }
+    }
+}
+
+/// One user interaction captured in the browser
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedEvent {
+    pub action: EventAction,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<Target>, // Most robust locator found at record time, with a fingerprint
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+    pub url: String,       // Page the event happened on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>, // That page's title
+    pub timestamp_ms: i64, // Browser clock, milliseconds since the Unix epoch
+}
+
+/// A recording as it is saved to disk
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedSession {
+    pub version: u32,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub events: Vec<RecordedEvent>,
+}
+
+impl RecordedSession {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, RecorderError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|source| RecorderError::Io {
+            path: path.display().to_string(),
+            source,
+        })?;
+        let session: RecordedSession = serde_json::from_str(&text)?;
+        if session.version != SESSION_FORMAT_VERSION {
+            return Err(RecorderError::UnsupportedVersion(session.version));
+        }
+        Ok(session)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), RecorderError> {
+        let path = path.as_ref();
+        fs::write(path, serde_json::to_string_pretty(self)?).map_err(|source| RecorderError::Io {
+            path: path.display().to_string(),
+            source,
+        })
+    }
+}
+
+const DRAIN_JS: &str = "return window.__saeRecorder ? window.__saeRecorder.drain() : null;";
+
+/// Installed into every page; keeps captured events in `sessionStorage` so
+/// they survive same-origin page loads until Rust drains them
+const RECORDER_JS: &str = r#"
+if (window.__saeRecorder) return true;
+const KEY = '__saeRecorderEvents';
+const stored = () => JSON.parse(sessionStorage.getItem(KEY) || '[]');
+const push = (event) => {
+    const events = stored();
+    events.push(Object.assign({ url: location.href, title: document.title, timestamp_ms: Date.now() }, event));
+    sessionStorage.setItem(KEY, JSON.stringify(events));
+};
+const generatedId = (id) => /\d{3,}|[0-9a-f]{8,}|^:|^ember|^react/i.test(id);
+const unique = (selector) => { try { return document.querySelectorAll(selector).length === 1; } catch (e) { return false; } };
+const labelOf = (el) => {
+    const label = (el.id && document.querySelector('label[for="' + CSS.escape(el.id) + '"]')) || el.closest('label');
+    return label ? clean(label.innerText || label.textContent) : '';
+};
+const roleOf = (el) => {
+    if (el.getAttribute('role')) return el.getAttribute('role');
+    const tag = el.tagName.toLowerCase();
+    const type = (el.getAttribute('type') || '').toLowerCase();
+    if (tag === 'button' || (tag === 'input' && ['button', 'submit', 'reset'].includes(type))) return 'button';
+    if (tag === 'a' && el.hasAttribute('href')) return 'link';
+    return null;
+};
+const nameOf = (el) => clean(el.getAttribute('aria-label') || el.innerText || el.value || el.title || '');
+const targetFor = (el) => {
+    const tag = el.tagName.toLowerCase();
+    let target;
+    const testId = el.getAttribute('data-testid');
+    if (testId && unique('[data-testid="' + CSS.escape(testId) + '"]')) target = { test_id: testId };
+    else if (el.id && !generatedId(el.id) && unique('#' + CSS.escape(el.id))) target = { css: '#' + CSS.escape(el.id) };
+    else if (el.name && ['input', 'select', 'textarea'].includes(tag) && unique(tag + '[name="' + CSS.escape(el.name) + '"]')) {
+        target = { css: tag + '[name="' + CSS.escape(el.name) + '"]' };
+    } else {
+        const label = ['input', 'select', 'textarea'].includes(tag) ? labelOf(el) : '';
+        const labelled = label && Array.from(document.querySelectorAll('input, select, textarea')).filter(o => labelOf(o) === label).length === 1;
+        const role = roleOf(el);
+        const name = role ? nameOf(el) : '';
+        const named = name && Array.from(document.querySelectorAll('a, button, input, [role]')).filter(o => roleOf(o) === role && nameOf(o) === name).length === 1;
+        if (labelled) target = { label };
+        else if (named) target = { role, name };
+        else target = { css: cssPath(el) };
+    }
+    target.fingerprint = describe(el);
+    if (sensitive(el)) delete target.fingerprint.attributes.value;
+    return target;
+};
+// Passwords, one-time codes, card numbers and the like are never recorded
+const sensitive = (el) => {
+    const type = (el.getAttribute('type') || '').toLowerCase();
+    const autocomplete = (el.getAttribute('autocomplete') || '').toLowerCase();
+    return type === 'password' || /password|one-time-code|cc-(number|csc|exp)/.test(autocomplete) ||
+        /passw|secret|token|otp|cvv|cvc|ssn|card.?num/i.test((el.name || '') + ' ' + (el.id || ''));
+};
+const actionable = (el) => el.closest('a, button, input, select, textarea, label, summary, [role], [onclick], [tabindex]') || el;
+const textLike = (el) => el.isContentEditable || el.tagName === 'TEXTAREA' ||
+    (el.tagName === 'INPUT' && !['checkbox', 'radio', 'button', 'submit', 'reset', 'file', 'image'].includes((el.type || '').toLowerCase()));
+
+document.addEventListener('click', (e) => {
+    const el = actionable(e.target);
+    if (el.tagName === 'SELECT' || el.tagName === 'OPTION') return; // Recorded as `select` on change
+    push({ action: 'click', target: targetFor(el) });
+}, true);
+document.addEventListener('input', (e) => {
+    if (!textLike(e.target)) return;
+    const event = { action: 'type', target: targetFor(e.target) };
+    if (!sensitive(e.target)) event.value = e.target.isContentEditable ? e.target.innerText : e.target.value;
+    push(event);
+}, true);
+document.addEventListener('change', (e) => {
+    if (e.target.tagName === 'SELECT') push({ action: 'select', target: targetFor(e.target), value: e.target.value });
+}, true);
+document.addEventListener('submit', (e) => push({ action: 'submit', target: targetFor(e.target) }), true);
+for (const method of ['pushState', 'replaceState']) {
+    const original = history[method];
+    history[method] = function () {
+        const result = original.apply(this, arguments);
+        push({ action: 'navigate' });
+        return result;
+    };
+}
+window.addEventListener('popstate', () => push({ action: 'navigate' }));
+window.addEventListener('hashchange', () => push({ action: 'navigate' }));
+
+window.__saeRecorder = {
+    drain: () => { const events = stored(); sessionStorage.removeItem(KEY); return events; },
+};
+push({ action: 'navigate' });
+return false;
+"#;
+
+/// Captures what a person does in a WebDriver-controlled browser.
+///
+/// `start` installs a listener in the current page; `poll` must then be called
+/// regularly (as `record_until` does) to pull events back and to re-install the
+/// listener after full page loads. Events fired in the short gap between a
+/// page load and the next poll are not captured.
+pub struct EventRecorder {
+    session: RecordedSession,
+    poll_interval: Duration,
+}
+
+impl Default for EventRecorder {
+    fn default() -> Self {
+        EventRecorder {
+            session: RecordedSession {
+                version: SESSION_FORMAT_VERSION,
+                started_at: Utc::now(),
+                finished_at: None,
+                events: Vec::new(),
+            },
+            poll_interval: Duration::from_millis(250),
+        }
+    }
+}
+
+impl EventRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    pub async fn start(&mut self, client: &mut Client) -> Result<(), RecorderError> {
+        println!("🔴 Recording started");
+        self.session.started_at = Utc::now();
+        self.install(client).await
+    }
+
+    /// Pull captured events out of the page; returns how many were new
+    pub async fn poll(&mut self, client: &mut Client) -> Result<usize, RecorderError> {
+        let mut drained = client.execute(DRAIN_JS, vec![]).await?;
+        if drained.is_null() {
+            // A full page load wiped the listener; the new one picks up
+            // whatever the old page left in sessionStorage
+            self.install(client).await?;
+            drained = client.execute(DRAIN_JS, vec![]).await?;
+        }
+        if drained.is_null() {
+            return Ok(0);
+        }
+
+        let events: Vec<RecordedEvent> = serde_json::from_value(drained)?;
+        for event in &events {
+            match &event.target {
+                Some(target) => println!("🔴 {} {}", event.action.as_str(), target),
+                None => println!("🔴 {} {}", event.action.as_str(), event.url),
+            }
+        }
+        let count = events.len();
+        self.session.events.extend(events);
+        Ok(count)
+    }
+
+    /// Poll until `stop` completes (e.g. `tokio::signal::ctrl_c()`), then
+    /// finish the session
+    pub async fn record_until(
+        &mut self,
+        client: &mut Client,
+        stop: impl Future<Output = ()>,
+    ) -> Result<&RecordedSession, RecorderError> {
+        tokio::pin!(stop);
+        loop {
+            tokio::select! {
+                _ = &mut stop => break,
+                _ = sleep(self.poll_interval) => {
+                    // Scripts fail while a page is loading; the next poll catches up
+                    if let Err(err) = self.poll(client).await {
+                        eprintln!("⚠️ Recorder poll failed: {}", err);
+                    }
+                }
+            }
+        }
+        self.stop(client).await
+    }
+
+    /// Collect the last events and close the session
+    pub async fn stop(&mut self, client: &mut Client) -> Result<&RecordedSession, RecorderError> {
+        self.poll(client).await?;
+        self.session.finished_at = Some(Utc::now());
+        println!("⏹️ Recording stopped with {} event(s)", self.session.events.len());
+        Ok(&self.session)
+    }
+
+    pub fn get_events(&self) -> &[RecordedEvent] {
+        &self.session.events
+    }
+
+    pub fn session(&self) -> &RecordedSession {
+        &self.session
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), RecorderError> {
+        self.session.save(path)
+    }
+
+    async fn install(&self, client: &mut Client) -> Result<(), RecorderError> {
+        let script = format!("{}\n{}", FINGERPRINT_JS, RECORDER_JS);
+        client.execute(&script, vec![]).await?;
+        Ok(())
+    }
+}
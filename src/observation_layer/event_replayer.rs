@@ -1,19 +1,430 @@
-pub async fn replay_events(client: &mut Client, recorder: &EventRecorder) {
-    for event in recorder.get_events() {
-        match event.action.as_str() {
-            "click" => {
-                let locator = Locator::Css(&event.target);
-                client.wait_for_find(locator).await.unwrap().click().await.unwrap();
-                println!("Replayed click on: {}", event.target);
+use super::dynamic_detection::{heal, DEFAULT_CONFIDENCE};
+use super::event_recorder::{EventAction, RecordedEvent};
+use super::interaction_log::{new_run_id, ActionKind, InteractionLog, Outcome};
+use super::log_repository::LogRepository;
+use crate::target::Target;
+use crate::web_interaction::{click_element, fill_form_field, locate, select_option, submit_form, WebInteractionError};
+use fantoccini::error::CmdError;
+use fantoccini::Client;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::time::{sleep, Duration, Instant};
+
+#[derive(Error, Debug)]
+pub enum ReplayError {
+    #[error("Element not found: {0}")]
+    ElementNotFound(String),
+    #[error("Checkpoint failed: {0}")]
+    Checkpoint(String),
+    #[error("{0}")]
+    Rejected(String), // The element was there but refused the action, e.g. a missing option
+    #[error("WebDriver error: {0}")]
+    WebDriver(#[from] CmdError),
+}
+
+impl From<WebInteractionError> for ReplayError {
+    fn from(err: WebInteractionError) -> Self {
+        match err {
+            WebInteractionError::ElementNotFound(target) => ReplayError::ElementNotFound(target),
+            WebInteractionError::Rejected(reason) => ReplayError::Rejected(reason),
+            WebInteractionError::FantocciniError(err) => ReplayError::WebDriver(err),
+        }
+    }
+}
+
+/// What to do with the remaining events after one fails
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplayPolicy {
+    #[default]
+    Stop,
+    Continue,
+}
+
+/// Page state that must hold before an event is replayed
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub before_event: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>, // Compared without query string and fragment
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub element: Option<Target>, // Must be present
+}
+
+impl Checkpoint {
+    /// The page the event was recorded on
+    pub fn from_event(index: usize, event: &RecordedEvent) -> Self {
+        Checkpoint {
+            before_event: index,
+            url: Some(event.url.clone()),
+            title: event.title.clone(),
+            element: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ReplayOptions {
+    pub delay_scale: f64, // Recorded gaps are multiplied by this; 0 replays as fast as possible
+    pub max_delay: Duration, // Longer recorded pauses are cut to this
+    pub element_timeout: Duration, // How long to wait for an element or checkpoint
+    pub policy: ReplayPolicy,
+    pub auto_checkpoints: bool, // Check URL and title against the recording before every event
+    pub checkpoints: Vec<Checkpoint>,
+}
+
+impl Default for ReplayOptions {
+    fn default() -> Self {
+        ReplayOptions {
+            delay_scale: 1.0,
+            max_delay: Duration::from_secs(10),
+            element_timeout: Duration::from_secs(10),
+            policy: ReplayPolicy::Stop,
+            auto_checkpoints: true,
+            checkpoints: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventOutcome {
+    pub index: usize,
+    pub action: EventAction,
+    pub outcome: Outcome,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>, // Error, skip reason or healed selector
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ReplaySummary {
+    pub run_id: String, // Tags this replay's entries in the `LogRepository`
+    pub outcomes: Vec<EventOutcome>,
+}
+
+impl ReplaySummary {
+    pub fn passed(&self) -> bool {
+        self.outcomes.iter().all(|event| event.outcome != Outcome::Failed)
+    }
+
+    pub fn count(&self, outcome: Outcome) -> usize {
+        self.outcomes.iter().filter(|event| event.outcome == outcome).count()
+    }
+}
+
+/// Replay recorded events with their original pacing, recording one log
+/// entry per event in `logs` with the event's index as its step
+pub async fn replay_events(
+    client: &mut Client,
+    events: &[RecordedEvent],
+    options: &ReplayOptions,
+    logs: &mut LogRepository,
+) -> ReplaySummary {
+    let mut summary = ReplaySummary {
+        run_id: new_run_id(),
+        outcomes: Vec::new(),
+    };
+    let mut stopped = false;
+
+    for (index, event) in events.iter().enumerate() {
+        let mut started = None;
+        let (outcome, detail) = if stopped {
+            (Outcome::Skipped, Some("an earlier event failed".to_string()))
+        } else if implied_submit(events, index) {
+            (Outcome::Skipped, Some("submitted by the preceding click".to_string()))
+        } else {
+            if let Some(previous) = index.checked_sub(1).map(|i| &events[i]) {
+                sleep(scaled_delay(previous, event, options)).await;
             }
-            "type" => {
-                let locator = Locator::Css(&event.target);
-                if let Some(value) = &event.value {
-                    client.wait_for_find(locator).await.unwrap().send_keys(value).await.unwrap();
-                    println!("Replayed typing on: {} with value: {}", event.target, value);
+            started = Some(Instant::now());
+            match replay_checked(client, events, index, options).await {
+                Ok(healed) => (Outcome::Passed, healed.map(|selector| format!("healed to {}", selector))),
+                Err(err) => {
+                    eprintln!("❌ Replaying event {} ({}) failed: {}", index, event.action.as_str(), err);
+                    stopped = options.policy == ReplayPolicy::Stop;
+                    (Outcome::Failed, Some(err.to_string()))
                 }
             }
-            _ => println!("Unknown action: {}", event.action),
+        };
+
+        let target = event.target.as_ref().map_or_else(|| event.url.clone(), Target::to_string);
+        let mut log = InteractionLog::new(action_kind(event.action), target, outcome)
+            .run(&summary.run_id)
+            .step(index)
+            .duration_ms(started.map_or(0, |started| started.elapsed().as_millis() as u64));
+        if let Some(detail) = &detail {
+            log = log.detail(detail.as_str());
+        }
+        logs.add_log(log);
+        summary.outcomes.push(EventOutcome {
+            index,
+            action: event.action,
+            outcome,
+            detail,
+        });
+    }
+
+    println!(
+        "⏯️ Replay {} finished: {} passed, {} failed, {} skipped",
+        summary.run_id,
+        summary.count(Outcome::Passed),
+        summary.count(Outcome::Failed),
+        summary.count(Outcome::Skipped)
+    );
+    summary
+}
+
+fn action_kind(action: EventAction) -> ActionKind {
+    match action {
+        EventAction::Click => ActionKind::Click,
+        EventAction::Type => ActionKind::Fill,
+        EventAction::Select => ActionKind::Select,
+        EventAction::Navigate => ActionKind::Navigate,
+        EventAction::Submit => ActionKind::Submit,
+    }
+}
+
+/// A submit right after a click on a submit button happens on its own
+fn implied_submit(events: &[RecordedEvent], index: usize) -> bool {
+    events[index].action == EventAction::Submit
+        && index
+            .checked_sub(1)
+            .map(|i| &events[i])
+            .is_some_and(|previous| {
+                previous.action == EventAction::Click && events[index].timestamp_ms - previous.timestamp_ms <= 1000
+            })
+}
+
+fn scaled_delay(previous: &RecordedEvent, event: &RecordedEvent, options: &ReplayOptions) -> Duration {
+    let gap_ms = (event.timestamp_ms - previous.timestamp_ms).max(0) as f64 * options.delay_scale.max(0.0);
+    Duration::from_millis(gap_ms as u64).min(options.max_delay)
+}
+
+/// Run the event's checkpoints, then the event. Returns the healed selector
+/// if the recorded target had to be healed.
+async fn replay_checked(
+    client: &mut Client,
+    events: &[RecordedEvent],
+    index: usize,
+    options: &ReplayOptions,
+) -> Result<Option<String>, ReplayError> {
+    let event = &events[index];
+    // A navigation's URL is where it leads, not where it starts
+    if options.auto_checkpoints && event.action != EventAction::Navigate {
+        check(client, &Checkpoint::from_event(index, event), options.element_timeout).await?;
+    }
+    for checkpoint in options.checkpoints.iter().filter(|c| c.before_event == index) {
+        check(client, checkpoint, options.element_timeout).await?;
+    }
+    replay_event(client, event, index == 0, options.element_timeout).await
+}
+
+async fn replay_event(
+    client: &mut Client,
+    event: &RecordedEvent,
+    first: bool,
+    timeout: Duration,
+) -> Result<Option<String>, ReplayError> {
+    if event.action == EventAction::Navigate {
+        // Most navigations follow from the previous click; only go there
+        // ourselves if that didn't happen
+        if first || !wait_for_page(client, &event.url, timeout.min(Duration::from_secs(2))).await? {
+            println!("🌐 Replaying navigation to {}", event.url);
+            client.goto(&event.url).await?;
         }
+        return Ok(None);
+    }
+
+    let Some(recorded) = &event.target else {
+        return Ok(None);
+    };
+    let (target, healed) = find(client, recorded, timeout).await?;
+    let value = event.value.as_deref().unwrap_or("");
+    match event.action {
+        EventAction::Click => click_element(client, &target).await?,
+        EventAction::Type if event.value.is_none() => {
+            println!("⚠️ {} was masked while recording; leaving it as is", target);
+        }
+        EventAction::Type => {
+            // Each event carries the whole value, so replace rather than append
+            locate(client, &target).await?.clear().await?;
+            fill_form_field(client, &target, value).await?;
+        }
+        EventAction::Select => select_option(client, &target, value).await?,
+        EventAction::Submit => submit_form(client, &target).await?,
+        EventAction::Navigate => unreachable!("handled above"),
+    }
+    Ok(healed)
+}
+
+/// Wait for `target`, healing it from its fingerprint if it never shows up
+async fn find(client: &mut Client, target: &Target, timeout: Duration) -> Result<(Target, Option<String>), ReplayError> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if locate(client, target).await.is_ok() {
+            return Ok((target.clone(), None));
+        }
+        if Instant::now() >= deadline {
+            break;
+        }
+        sleep(Duration::from_millis(250)).await;
+    }
+    match heal(client, target, DEFAULT_CONFIDENCE).await {
+        Some(healed) => {
+            let selector = healed.target.to_string();
+            Ok((healed.target, Some(selector)))
+        }
+        None => Err(ReplayError::ElementNotFound(target.to_string())),
+    }
+}
+
+async fn check(client: &mut Client, checkpoint: &Checkpoint, timeout: Duration) -> Result<(), ReplayError> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let Some(problem) = mismatch(client, checkpoint).await? else {
+            return Ok(());
+        };
+        if Instant::now() >= deadline {
+            return Err(ReplayError::Checkpoint(problem));
+        }
+        sleep(Duration::from_millis(250)).await;
+    }
+}
+
+async fn mismatch(client: &mut Client, checkpoint: &Checkpoint) -> Result<Option<String>, CmdError> {
+    if let Some(url) = &checkpoint.url {
+        let current = client.current_url().await?;
+        if !same_page(current.as_str(), url) {
+            return Ok(Some(format!("expected page {} but on {}", url, current)));
+        }
+    }
+    if let Some(title) = &checkpoint.title {
+        let current = client.execute("return document.title;", vec![]).await?;
+        let current = current.as_str().unwrap_or_default();
+        if current.trim() != title.trim() {
+            return Ok(Some(format!("expected title '{}' but got '{}'", title, current)));
+        }
+    }
+    if let Some(target) = &checkpoint.element {
+        if locate(client, target).await.is_err() {
+            return Ok(Some(format!("expected {} on the page", target)));
+        }
+    }
+    Ok(None)
+}
+
+async fn wait_for_page(client: &mut Client, url: &str, timeout: Duration) -> Result<bool, CmdError> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if same_page(client.current_url().await?.as_str(), url) {
+            return Ok(true);
+        }
+        if Instant::now() >= deadline {
+            return Ok(false);
+        }
+        sleep(Duration::from_millis(250)).await;
+    }
+}
+
+/// Same origin and path; query strings and fragments often carry per-visit tokens
+fn same_page(a: &str, b: &str) -> bool {
+    let strip = |url: &str| -> String {
+        let url = url.split(['#', '?']).next().unwrap_or(url);
+        url.trim_end_matches('/').to_string()
+    };
+    strip(a) == strip(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(action: EventAction, timestamp_ms: i64) -> RecordedEvent {
+        RecordedEvent {
+            action,
+            target: Some(Target::css("#go")),
+            value: None,
+            url: "https://example.com/cart?session=1".to_string(),
+            title: Some("Cart".to_string()),
+            timestamp_ms,
+        }
+    }
+
+    fn outcome(index: usize, outcome: Outcome) -> EventOutcome {
+        EventOutcome {
+            index,
+            action: EventAction::Click,
+            outcome,
+            detail: None,
+        }
+    }
+
+    #[test]
+    fn skips_submits_right_after_a_click() {
+        let clicked = [event(EventAction::Click, 0), event(EventAction::Submit, 1000)];
+        assert!(implied_submit(&clicked, 1));
+        assert!(!implied_submit(&clicked, 0));
+        let late = [event(EventAction::Click, 0), event(EventAction::Submit, 1001)];
+        assert!(!implied_submit(&late, 1));
+        let entered = [event(EventAction::Type, 0), event(EventAction::Submit, 10)];
+        assert!(!implied_submit(&entered, 1));
+        assert!(!implied_submit(&[event(EventAction::Submit, 0)], 0));
+    }
+
+    #[test]
+    fn scales_and_caps_recorded_gaps() {
+        let options = |delay_scale| ReplayOptions {
+            delay_scale,
+            max_delay: Duration::from_secs(2),
+            ..ReplayOptions::default()
+        };
+        let gap = |from, to, scale| {
+            scaled_delay(&event(EventAction::Click, from), &event(EventAction::Click, to), &options(scale))
+        };
+        assert_eq!(gap(0, 1000, 0.5), Duration::from_millis(500));
+        assert_eq!(gap(0, 1000, 0.0), Duration::ZERO);
+        assert_eq!(gap(0, 60_000, 1.0), Duration::from_secs(2));
+        assert_eq!(gap(1000, 0, 1.0), Duration::ZERO, "clock skew never goes negative");
+        assert_eq!(gap(0, 1000, -1.0), Duration::ZERO);
+    }
+
+    #[test]
+    fn pages_match_without_query_fragment_or_trailing_slash() {
+        assert!(same_page("https://example.com/cart?session=1", "https://example.com/cart/#top"));
+        assert!(!same_page("https://example.com/cart", "https://example.com/checkout"));
+        assert!(!same_page("https://example.com/cart", "http://example.com/cart"));
+    }
+
+    #[test]
+    fn checkpoints_default_to_the_recorded_page() {
+        let checkpoint = Checkpoint::from_event(3, &event(EventAction::Click, 0));
+        assert_eq!(checkpoint.before_event, 3);
+        assert_eq!(checkpoint.url.as_deref(), Some("https://example.com/cart?session=1"));
+        assert_eq!(checkpoint.title.as_deref(), Some("Cart"));
+        assert!(checkpoint.element.is_none());
+    }
+
+    #[test]
+    fn a_summary_passes_unless_something_failed() {
+        let mut summary = ReplaySummary {
+            run_id: "run".to_string(),
+            outcomes: vec![outcome(0, Outcome::Passed), outcome(1, Outcome::Skipped)],
+        };
+        assert!(summary.passed());
+        summary.outcomes.push(outcome(2, Outcome::Failed));
+        summary.outcomes.push(outcome(3, Outcome::Skipped));
+        assert!(!summary.passed());
+        assert_eq!(
+            [Outcome::Passed, Outcome::Failed, Outcome::Skipped].map(|outcome| summary.count(outcome)),
+            [1, 1, 2]
+        );
+    }
+
+    #[test]
+    fn logs_events_under_matching_action_kinds() {
+        assert_eq!(action_kind(EventAction::Type), ActionKind::Fill);
+        assert_eq!(action_kind(EventAction::Navigate), ActionKind::Navigate);
+        assert_eq!(action_kind(EventAction::Submit), ActionKind::Submit);
     }
 }
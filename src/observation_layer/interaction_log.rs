@@ -1,24 +1,326 @@
+use super::log_repository::LogRepository;
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use parking_lot::{Mutex, RwLock};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::future::Future;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::mpsc::UnboundedSender;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ActionKind {
+    StartBrowser,
+    Navigate,
+    Click,
+    Fill,
+    Select,
+    Submit,
+    Wait,
+    Extract,
+    Script,
+    Screenshot,
+    Categorize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Passed,
+    Failed,
+    Skipped, // Not attempted, e.g. a replay stopped at an earlier failure
+}
+
+macro_rules! snake_case_from_str {
+    ($kind:ty) => {
+        impl FromStr for $kind {
+            type Err = String;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                serde_json::from_value(serde_json::Value::String(s.to_string()))
+                    .map_err(|_| format!("unknown {} '{}'", stringify!($kind), s))
+            }
+        }
+
+        impl fmt::Display for $kind {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                match serde_json::to_value(self) {
+                    Ok(serde_json::Value::String(name)) => f.write_str(&name),
+                    _ => write!(f, "{:?}", self),
+                }
+            }
+        }
+    };
+}
+
+snake_case_from_str!(ActionKind);
+snake_case_from_str!(Outcome);
+
+/// One browser interaction, with enough ids to line up entries from many
+/// parallel workers
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct InteractionLog {
-    pub action: String,
-    pub selector: String,
-    pub status: String,
-    pub timestamp: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub task_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub step_index: Option<usize>, // Top-level step of the task; nested steps share their parent's
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub worker_id: Option<usize>,
+    pub action: ActionKind,
+    pub target: String, // Element, URL or file the action was about
+    pub outcome: Outcome,
+    pub duration_ms: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>, // Error message, or why the action was skipped
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub screenshot: Option<String>, // Path of a screenshot taken by or for this action
+    pub timestamp: DateTime<Utc>,
 }
 
 impl InteractionLog {
-    pub fn new(action: &str, selector: &str, status: &str) -> Self {
+    pub fn new(action: ActionKind, target: impl Into<String>, outcome: Outcome) -> Self {
         Self {
-            action: action.to_string(),
-            selector: selector.to_string(),
-            status: status.to_string(),
-            timestamp: chrono::Local::now().to_rfc3339(),
+            run_id: None,
+            task_id: None,
+            step_index: None,
+            worker_id: None,
+            action,
+            target: target.into(),
+            outcome,
+            duration_ms: 0,
+            detail: None,
+            screenshot: None,
+            timestamp: Utc::now(),
         }
     }
 
+    pub fn run(mut self, run_id: &str) -> Self {
+        self.run_id = Some(run_id.to_string());
+        self
+    }
+
+    pub fn step(mut self, step_index: usize) -> Self {
+        self.step_index = Some(step_index);
+        self
+    }
+
+    pub fn worker(mut self, worker_id: usize) -> Self {
+        self.worker_id = Some(worker_id);
+        self
+    }
+
+    pub fn detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+
+    pub fn duration_ms(mut self, duration_ms: u64) -> Self {
+        self.duration_ms = duration_ms;
+        self
+    }
+
+    pub fn screenshot(mut self, path: impl Into<String>) -> Self {
+        self.screenshot = Some(path.into());
+        self
+    }
+
+    /// Fill in whichever ids the context knows
+    pub fn context(mut self, context: &LogContext) -> Self {
+        self.run_id = self.run_id.or_else(|| context.run_id.clone());
+        self.task_id = self.task_id.or_else(|| context.task_id.clone());
+        self.step_index = self.step_index.or(context.step_index);
+        self.worker_id = self.worker_id.or(context.worker_id);
+        self
+    }
+
     pub fn log(&self) {
+        let ids: Vec<String> = [
+            self.run_id.as_ref().map(|id| format!("run={}", id)),
+            self.task_id.as_ref().map(|id| format!("task={}", id)),
+            self.step_index.map(|index| format!("step={}", index)),
+            self.worker_id.map(|id| format!("worker={}", id)),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
         println!(
-            "[{}] Action: {}, Selector: {}, Status: {}",
-            self.timestamp, self.action, self.selector, self.status
+            "[{}]{} Action: {}, Target: {}, Outcome: {} ({} ms){}{}",
+            self.timestamp.to_rfc3339(),
+            if ids.is_empty() { String::new() } else { format!(" [{}]", ids.join(" ")) },
+            self.action,
+            self.target,
+            self.outcome,
+            self.duration_ms,
+            self.detail.as_ref().map(|detail| format!(", {}", detail)).unwrap_or_default(),
+            self.screenshot.as_ref().map(|path| format!(", screenshot {}", path)).unwrap_or_default()
         );
     }
 }
+
+/// Ids attached to every log emitted while a future runs inside
+/// `LOG_CONTEXT.scope(..)`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LogContext {
+    pub run_id: Option<String>,
+    pub task_id: Option<String>,
+    pub step_index: Option<usize>,
+    pub worker_id: Option<usize>,
+}
+
+impl LogContext {
+    pub fn step(&self, step_index: usize) -> Self {
+        LogContext {
+            step_index: Some(step_index),
+            ..self.clone()
+        }
+    }
+
+    /// The context of the running task, or an empty one outside any scope
+    pub fn current() -> Self {
+        LOG_CONTEXT.try_with(LogContext::clone).unwrap_or_default()
+    }
+}
+
+tokio::task_local! {
+    pub static LOG_CONTEXT: LogContext;
+}
+
+lazy_static! {
+    static ref LOG_SINK: RwLock<Option<Arc<Mutex<LogRepository>>>> = RwLock::new(None);
+    static ref LOG_FORWARD: RwLock<Option<UnboundedSender<InteractionLog>>> = RwLock::new(None);
+}
+
+/// Send every log `emit` sees to `repository` from now on; returns a handle
+/// for querying it
+pub fn install_log_sink(repository: LogRepository) -> Arc<Mutex<LogRepository>> {
+    let sink = Arc::new(Mutex::new(repository));
+    *LOG_SINK.write() = Some(Arc::clone(&sink));
+    sink
+}
+
+/// Also hand every log `emit` sees to `forward` (a remote worker streams
+/// them to its coordinator this way)
+pub fn install_log_forwarder(forward: UnboundedSender<InteractionLog>) {
+    *LOG_FORWARD.write() = Some(forward);
+}
+
+/// Record `log` in the installed sink and forwarder, tagged with the current
+/// context. Without either this does nothing.
+pub fn emit(log: InteractionLog) {
+    let log = log.context(&LogContext::current());
+    if let Some(forward) = LOG_FORWARD.read().as_ref() {
+        let _ = forward.send(log.clone()); // The receiver going away only loses the log
+    }
+    let sink = LOG_SINK.read().clone();
+    if let Some(sink) = sink {
+        sink.lock().add_log(log);
+    }
+}
+
+/// Time `action` and emit its log entry with the outcome
+pub async fn logged<T, E: fmt::Display>(
+    kind: ActionKind,
+    target: impl fmt::Display,
+    screenshot: Option<&str>,
+    action: impl Future<Output = Result<T, E>>,
+) -> Result<T, E> {
+    let started = Instant::now();
+    let result = action.await;
+
+    let outcome = if result.is_ok() { Outcome::Passed } else { Outcome::Failed };
+    let mut log = InteractionLog::new(kind, target.to_string(), outcome)
+        .duration_ms(started.elapsed().as_millis() as u64);
+    if let Err(err) = &result {
+        log = log.detail(err.to_string());
+    }
+    if let Some(path) = screenshot {
+        log = log.screenshot(path);
+    }
+    emit(log);
+    result
+}
+
+/// A run id that sorts by start time, e.g. `20261018-220501-3fa9c2`
+pub fn new_run_id() -> String {
+    format!(
+        "{}-{:06x}",
+        Utc::now().format("%Y%m%d-%H%M%S"),
+        rand::thread_rng().gen_range(0..0x100_0000)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::observation_layer::log_repository::LogQuery;
+
+    #[test]
+    fn context_fills_only_missing_ids() {
+        let context = LogContext {
+            run_id: Some("run".to_string()),
+            task_id: Some("7".to_string()),
+            step_index: Some(2),
+            worker_id: Some(1),
+        };
+        let log = InteractionLog::new(ActionKind::Click, "#a", Outcome::Passed).step(5).context(&context);
+        assert_eq!(log.run_id.as_deref(), Some("run"));
+        assert_eq!(log.task_id.as_deref(), Some("7"));
+        assert_eq!((log.step_index, log.worker_id), (Some(5), Some(1)));
+        assert_eq!(context.step(3).step_index, Some(3));
+    }
+
+    #[test]
+    fn kinds_parse_and_print_in_snake_case() {
+        assert_eq!("start_browser".parse::<ActionKind>(), Ok(ActionKind::StartBrowser));
+        assert_eq!(ActionKind::StartBrowser.to_string(), "start_browser");
+        assert_eq!("skipped".parse::<Outcome>(), Ok(Outcome::Skipped));
+        assert_eq!("Passed".parse::<Outcome>(), Err("unknown Outcome 'Passed'".to_string()));
+    }
+
+    #[test]
+    fn run_ids_sort_by_start_time() {
+        let id = new_run_id();
+        let (time, suffix) = id.rsplit_once('-').unwrap();
+        assert!(chrono::NaiveDateTime::parse_from_str(time, "%Y%m%d-%H%M%S").is_ok(), "{}", id);
+        assert!(suffix.len() == 6 && suffix.chars().all(|c| c.is_ascii_hexdigit()), "{}", id);
+    }
+
+    #[tokio::test]
+    async fn logged_records_outcomes_under_the_current_context() {
+        assert_eq!(LogContext::current(), LogContext::default(), "no context outside a scope");
+        let sink = install_log_sink(LogRepository::new());
+        let run_id = new_run_id();
+        let context = LogContext {
+            run_id: Some(run_id.clone()),
+            worker_id: Some(4),
+            ..LogContext::default()
+        };
+        LOG_CONTEXT
+            .scope(context.clone(), async {
+                assert_eq!(LogContext::current(), context);
+                let passed: Result<u32, String> = logged(ActionKind::Click, "#ok", None, async { Ok(1) }).await;
+                assert_eq!(passed, Ok(1));
+                let failed: Result<(), String> =
+                    logged(ActionKind::Fill, "#email", Some("shot.png"), async { Err("gone".to_string()) }).await;
+                assert!(failed.is_err());
+            })
+            .await;
+
+        let query = LogQuery {
+            run_id: Some(run_id),
+            ..LogQuery::default()
+        };
+        let sink = sink.lock();
+        let logs = sink.query(&query);
+        assert_eq!(logs.len(), 2);
+        assert_eq!((logs[0].action, logs[0].outcome, logs[0].worker_id), (ActionKind::Click, Outcome::Passed, Some(4)));
+        assert_eq!((logs[1].outcome, logs[1].detail.as_deref()), (Outcome::Failed, Some("gone")));
+        assert_eq!(logs[1].screenshot.as_deref(), Some("shot.png"));
+    }
+}
@@ -1,23 +1,446 @@
-use super::interaction_log::InteractionLog;
+use super::interaction_log::{ActionKind, InteractionLog, Outcome};
+use chrono::{DateTime, Duration, Utc};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
 
+/// Where the CLI keeps interaction logs between runs
+pub const DEFAULT_LOG_PATH: &str = "logs/interactions.jsonl";
+
+/// How much history to keep; older entries are dropped on open and as new
+/// ones arrive
+#[derive(Debug, Clone, Copy)]
+pub struct Retention {
+    pub max_age: Option<Duration>,
+    pub max_entries: Option<usize>,
+}
+
+impl Default for Retention {
+    fn default() -> Self {
+        Retention {
+            max_age: Some(Duration::days(30)),
+            max_entries: Some(100_000),
+        }
+    }
+}
+
+/// Filter for `LogRepository::query`; unset fields match everything
+#[derive(Debug, Clone, Default)]
+pub struct LogQuery {
+    pub run_id: Option<String>,
+    pub task_id: Option<String>,
+    pub step_index: Option<usize>,
+    pub worker_id: Option<usize>,
+    pub action: Option<ActionKind>,
+    pub outcome: Option<Outcome>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub limit: Option<usize>, // Keep only the newest matches
+}
+
+impl LogQuery {
+    fn matches(&self, log: &InteractionLog) -> bool {
+        self.run_id.as_ref().is_none_or(|run_id| log.run_id.as_ref() == Some(run_id))
+            && self.task_id.as_ref().is_none_or(|task_id| log.task_id.as_ref() == Some(task_id))
+            && self.step_index.is_none_or(|step_index| log.step_index == Some(step_index))
+            && self.worker_id.is_none_or(|worker_id| log.worker_id == Some(worker_id))
+            && self.action.is_none_or(|action| log.action == action)
+            && self.outcome.is_none_or(|outcome| log.outcome == outcome)
+            && self.since.is_none_or(|since| log.timestamp >= since)
+            && self.until.is_none_or(|until| log.timestamp < until)
+    }
+}
+
+/// Interaction logs kept in memory and, when opened on a file, appended to
+/// it as JSON lines so they outlive the process
+#[derive(Default)]
 pub struct LogRepository {
     logs: Vec<InteractionLog>,
+    path: Option<PathBuf>,
+    file: Option<File>,
+    lock: Option<File>, // Shared while appending, exclusive while compacting
+    retention: Retention,
+    dropped: usize, // Entries removed by retention but still in the file
+    retention_checked: Option<DateTime<Utc>>,
+    by_run: HashMap<String, Vec<usize>>,
+    by_worker: HashMap<usize, Vec<usize>>,
+    by_action: HashMap<ActionKind, Vec<usize>>,
+    by_outcome: HashMap<Outcome, Vec<usize>>,
 }
 
 impl LogRepository {
+    /// In-memory only
     pub fn new() -> Self {
-        Self { logs: vec![] }
+        Self::default()
+    }
+
+    /// Load the logs stored at `path` (creating it if needed) and append new
+    /// ones to it. Unreadable lines, e.g. one cut short by a crash, are skipped.
+    /// Several processes may append at once; the file is only compacted by
+    /// whichever of them finds itself the only one writing.
+    pub fn open(path: impl AsRef<Path>, retention: Retention) -> io::Result<Self> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+
+        // Held shared for as long as we append; waits out a compaction in progress
+        let lock = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(path.with_extension("jsonl.lock"))?;
+        lock.lock_shared()?;
+
+        let mut repository = Self::load(path, retention)?;
+        repository.path = Some(path.to_path_buf());
+        repository.lock = Some(lock);
+        repository.file = Some(OpenOptions::new().create(true).append(true).open(path)?);
+        if repository.dropped > 0 {
+            repository.compact()?;
+        }
+        Ok(repository)
+    }
+
+    /// Snapshot of the logs stored at `path`, for queries. Never writes to the
+    /// file, so it is safe while other processes are appending.
+    pub fn read(path: impl AsRef<Path>, retention: Retention) -> io::Result<Self> {
+        let mut repository = Self::load(path.as_ref(), retention)?;
+        repository.dropped = 0;
+        Ok(repository)
+    }
+
+    fn load(path: &Path, retention: Retention) -> io::Result<Self> {
+        let mut logs = Vec::new();
+        let mut skipped = 0;
+        if path.exists() {
+            for line in BufReader::new(File::open(path)?).lines() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                match serde_json::from_str::<InteractionLog>(&line) {
+                    Ok(log) => logs.push(log),
+                    Err(_) => skipped += 1,
+                }
+            }
+        }
+        if skipped > 0 {
+            eprintln!("⚠️ Skipped {} unreadable log line(s) in {}", skipped, path.display());
+        }
+
+        let mut repository = LogRepository {
+            retention,
+            dropped: skipped,
+            ..Self::default()
+        };
+        for log in logs {
+            repository.index(log);
+        }
+        repository.apply_retention();
+        Ok(repository)
     }
 
     pub fn add_log(&mut self, log: InteractionLog) {
-        self.logs.push(log);
         log.log(); // Print log details for immediate feedback
+        if let Some(file) = &mut self.file {
+            let written = serde_json::to_string(&log)
+                .map_err(io::Error::from)
+                .and_then(|line| writeln!(file, "{}", line));
+            if let Err(err) = written {
+                eprintln!("❌ Failed to persist log entry: {}", err);
+            }
+        }
+        self.index(log);
+
+        if self.retention_due() {
+            self.apply_retention();
+            // Rewriting the file is expensive, so let dropped entries pile up a bit
+            if self.dropped > 1000 && self.dropped > self.logs.len() / 2 {
+                if let Err(err) = self.compact() {
+                    eprintln!("❌ Failed to compact logs: {}", err);
+                }
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.logs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.logs.is_empty()
+    }
+
+    /// Matching entries, oldest first
+    pub fn query(&self, query: &LogQuery) -> Vec<&InteractionLog> {
+        // Start from the narrowest index that applies
+        let indexed = [
+            query.run_id.as_ref().map(|run_id| self.by_run.get(run_id)),
+            query.worker_id.map(|worker_id| self.by_worker.get(&worker_id)),
+            query.action.map(|action| self.by_action.get(&action)),
+            query.outcome.map(|outcome| self.by_outcome.get(&outcome)),
+        ];
+        let candidates: Box<dyn Iterator<Item = &InteractionLog>> = match indexed
+            .into_iter()
+            .flatten()
+            .min_by_key(|positions| positions.map_or(0, Vec::len))
+        {
+            Some(Some(positions)) => Box::new(positions.iter().map(|&i| &self.logs[i])),
+            Some(None) => return Vec::new(),
+            None => Box::new(self.logs.iter()),
+        };
+
+        let mut matches: Vec<&InteractionLog> = candidates.filter(|log| query.matches(log)).collect();
+        matches.sort_by_key(|log| log.timestamp);
+        if let Some(limit) = query.limit {
+            let skip = matches.len().saturating_sub(limit);
+            matches.drain(..skip);
+        }
+        matches
     }
 
     pub fn view_logs(&self) {
+        self.view(&LogQuery::default());
+    }
+
+    pub fn view(&self, query: &LogQuery) {
         println!("--- Logs ---");
-        for log in &self.logs {
+        for log in self.query(query) {
             log.log();
         }
     }
+
+    /// Rewrite the backing file with only the retained entries, unless
+    /// another process has it open too: it would keep appending to the old
+    /// file. Returns whether the file was rewritten.
+    pub fn compact(&mut self) -> io::Result<bool> {
+        let (Some(path), Some(lock)) = (&self.path, &self.lock) else {
+            self.dropped = 0;
+            return Ok(true);
+        };
+        lock.unlock()?;
+        let exclusive = match lock.try_lock() {
+            Ok(()) => true,
+            Err(TryLockError::WouldBlock) => false,
+            Err(TryLockError::Error(err)) => {
+                lock.lock_shared()?;
+                return Err(err);
+            }
+        };
+        let rewritten = if exclusive { Self::rewrite(path, self.retention) } else { Ok(()) };
+        lock.unlock()?;
+        lock.lock_shared()?;
+        rewritten?;
+
+        // Someone else may have compacted while we were not holding the lock
+        self.file = Some(OpenOptions::new().append(true).open(path)?);
+        if exclusive {
+            self.dropped = 0;
+        }
+        Ok(exclusive)
+    }
+
+    /// Other processes may have appended entries we never loaded, so start
+    /// over from the file
+    fn rewrite(path: &Path, retention: Retention) -> io::Result<()> {
+        let retained = Self::load(path, retention)?;
+        let temp = path.with_extension("jsonl.tmp");
+        {
+            let mut out = io::BufWriter::new(File::create(&temp)?);
+            for log in &retained.logs {
+                writeln!(out, "{}", serde_json::to_string(log)?)?;
+            }
+            out.flush()?;
+        }
+        fs::rename(&temp, path)
+    }
+
+    fn index(&mut self, log: InteractionLog) {
+        let position = self.logs.len();
+        if let Some(run_id) = &log.run_id {
+            self.by_run.entry(run_id.clone()).or_default().push(position);
+        }
+        if let Some(worker_id) = log.worker_id {
+            self.by_worker.entry(worker_id).or_default().push(position);
+        }
+        self.by_action.entry(log.action).or_default().push(position);
+        self.by_outcome.entry(log.outcome).or_default().push(position);
+        self.logs.push(log);
+    }
+
+    /// Trimming means reindexing, so it is done in batches: once the entry
+    /// limit is overshot by 10%, or at most once a minute for age
+    fn retention_due(&self) -> bool {
+        let over_limit = self
+            .retention
+            .max_entries
+            .is_some_and(|max_entries| self.logs.len() > max_entries + max_entries / 10);
+        let age_due = self.retention.max_age.is_some()
+            && self
+                .retention_checked
+                .is_none_or(|checked| Utc::now() - checked > Duration::minutes(1));
+        over_limit || age_due
+    }
+
+    fn apply_retention(&mut self) {
+        self.retention_checked = Some(Utc::now());
+        let before = self.logs.len();
+        if let Some(max_age) = self.retention.max_age {
+            let cutoff = Utc::now() - max_age;
+            self.logs.retain(|log| log.timestamp >= cutoff);
+        }
+        if let Some(max_entries) = self.retention.max_entries {
+            if self.logs.len() > max_entries {
+                // Entries arrive roughly in time order, so the front is the oldest
+                let excess = self.logs.len() - max_entries;
+                self.logs.drain(..excess);
+            }
+        }
+
+        let removed = before - self.logs.len();
+        if removed > 0 {
+            self.dropped += removed;
+            self.reindex();
+        }
+    }
+
+    fn reindex(&mut self) {
+        let logs = std::mem::take(&mut self.logs);
+        self.by_run.clear();
+        self.by_worker.clear();
+        self.by_action.clear();
+        self.by_outcome.clear();
+        for log in logs {
+            self.index(log);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_log(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sae-logs-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        dir.join("interactions.jsonl")
+    }
+
+    fn entry(run: &str, task: &str, outcome: Outcome, minutes_ago: i64) -> InteractionLog {
+        let mut log = InteractionLog::new(ActionKind::Click, format!("#{}-{}", run, task), outcome).run(run);
+        log.task_id = Some(task.to_string());
+        log.timestamp = Utc::now() - Duration::minutes(minutes_ago);
+        log
+    }
+
+    fn targets(logs: Vec<&InteractionLog>) -> Vec<&str> {
+        logs.into_iter().map(|log| log.target.as_str()).collect()
+    }
+
+    fn lines(path: &Path) -> usize {
+        fs::read_to_string(path).unwrap().lines().count()
+    }
+
+    #[test]
+    fn queries_by_run_task_outcome_and_time() {
+        let mut repository = LogRepository::new();
+        repository.add_log(entry("r1", "t1", Outcome::Passed, 30));
+        repository.add_log(entry("r1", "t2", Outcome::Failed, 20));
+        repository.add_log(entry("r2", "t1", Outcome::Failed, 10));
+        repository.add_log(entry("r2", "t2", Outcome::Passed, 0));
+
+        let run = |run_id: &str| LogQuery {
+            run_id: Some(run_id.to_string()),
+            ..LogQuery::default()
+        };
+        assert_eq!(targets(repository.query(&run("r1"))), ["#r1-t1", "#r1-t2"]);
+        assert!(repository.query(&run("r3")).is_empty());
+        let task = LogQuery {
+            task_id: Some("t1".to_string()),
+            ..LogQuery::default()
+        };
+        assert_eq!(targets(repository.query(&task)), ["#r1-t1", "#r2-t1"]);
+        let failed = LogQuery {
+            outcome: Some(Outcome::Failed),
+            ..LogQuery::default()
+        };
+        assert_eq!(targets(repository.query(&failed)), ["#r1-t2", "#r2-t1"]);
+        let window = LogQuery {
+            since: Some(Utc::now() - Duration::minutes(25)),
+            until: Some(Utc::now() - Duration::minutes(5)),
+            ..LogQuery::default()
+        };
+        assert_eq!(targets(repository.query(&window)), ["#r1-t2", "#r2-t1"]);
+        let newest = LogQuery {
+            limit: Some(1),
+            ..failed
+        };
+        assert_eq!(targets(repository.query(&newest)), ["#r2-t1"]);
+    }
+
+    #[test]
+    fn open_prunes_by_age_and_count() {
+        let path = temp_log("retention");
+        let mut repository = LogRepository::open(&path, Retention::default()).unwrap();
+        repository.add_log(entry("r1", "old", Outcome::Passed, 60 * 24 * 40));
+        for n in 0..3 {
+            repository.add_log(entry("r1", &format!("t{}", n), Outcome::Passed, 3 - n));
+        }
+        drop(repository);
+        assert_eq!(lines(&path), 4);
+
+        let retention = Retention {
+            max_age: Some(Duration::days(30)),
+            max_entries: Some(2),
+        };
+        let repository = LogRepository::open(&path, retention).unwrap();
+        assert_eq!(targets(repository.query(&LogQuery::default())), ["#r1-t1", "#r1-t2"]);
+        assert_eq!(lines(&path), 2, "pruned entries are compacted out of the file");
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn read_never_rewrites_the_file() {
+        let path = temp_log("read");
+        let mut repository = LogRepository::open(&path, Retention::default()).unwrap();
+        repository.add_log(entry("r1", "t1", Outcome::Passed, 2));
+        repository.add_log(entry("r1", "t2", Outcome::Passed, 1));
+        drop(repository);
+
+        let retention = Retention {
+            max_age: None,
+            max_entries: Some(1),
+        };
+        assert_eq!(LogRepository::read(&path, retention).unwrap().len(), 1);
+        assert_eq!(lines(&path), 2);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn reopens_after_compaction() {
+        let path = temp_log("reopen");
+        let mut repository = LogRepository::open(&path, Retention::default()).unwrap();
+        repository.add_log(entry("r1", "t1", Outcome::Passed, 2));
+        repository.add_log(entry("r1", "t2", Outcome::Failed, 1));
+        drop(repository);
+        // A line cut short by a crash
+        OpenOptions::new().append(true).open(&path).unwrap().write_all(b"{\"run_id\":").unwrap();
+
+        let mut repository = LogRepository::open(&path, Retention::default()).unwrap();
+        assert_eq!(lines(&path), 2, "the broken line was compacted away");
+        repository.add_log(entry("r2", "t1", Outcome::Passed, 0));
+        assert!(repository.compact().unwrap());
+        drop(repository);
+
+        let repository = LogRepository::open(&path, Retention::default()).unwrap();
+        assert_eq!(targets(repository.query(&LogQuery::default())), ["#r1-t1", "#r1-t2", "#r2-t1"]);
+        let failed = LogQuery {
+            outcome: Some(Outcome::Failed),
+            ..LogQuery::default()
+        };
+        assert_eq!(targets(repository.query(&failed)), ["#r1-t2"]);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
 }
@@ -2,4 +2,5 @@ pub mod interaction_log;
 pub mod log_repository;
 pub mod dynamic_detection;
 pub mod event_recorder;
-pub mod event_replayer;
\ No newline at end of file
+pub mod event_replayer;
+pub mod session_compiler;
@@ -0,0 +1,426 @@
+use super::event_recorder::{EventAction, RecordedEvent, RecordedSession};
+use crate::target::{By, Target};
+use crate::task_model::{AutomationTask, InputKind, TaskInput, TaskStep};
+use std::collections::HashSet;
+
+/// Knobs for turning a recording into a workflow
+#[derive(Debug, Clone)]
+pub struct CompileOptions {
+    pub lift_literals: bool, // Turn typed values into task inputs with the recorded value as default
+    pub navigation_gap_ms: i64, // A navigation this soon after an action is that action's doing
+    pub settle_ms: i64, // An action this long after a click probably waited for something it revealed
+}
+
+impl Default for CompileOptions {
+    fn default() -> Self {
+        CompileOptions {
+            lift_literals: true,
+            navigation_gap_ms: 2000,
+            settle_ms: 1500,
+        }
+    }
+}
+
+/// Turn a recorded session into an editable workflow:
+///
+/// - keystrokes into the same field collapse into one `FillForm` with the
+///   final value, dropping the click that focused the field;
+/// - navigations become `OpenWebsite` only when no action caused them, and
+///   repeats of the current URL are dropped;
+/// - the first action on a new page, and actions that came well after a
+///   click, get a `WaitForElement` first since their element may appear late;
+/// - submits caused by clicking a submit button are dropped;
+/// - typed values become task inputs; password fields and fields the
+///   recorder masked get no default.
+pub fn compile_session(session: &RecordedSession, name: &str, options: &CompileOptions) -> AutomationTask {
+    let mut compiler = Compiler {
+        options,
+        steps: Vec::new(),
+        current_url: None,
+        previous: None,
+        page_changed: false,
+        masked: Vec::new(),
+    };
+    for event in &session.events {
+        compiler.add(event);
+    }
+
+    let mut task = AutomationTask::new();
+    task.name = name.to_string();
+    task.description = Some(format!("Recorded on {}", session.started_at.format("%Y-%m-%d %H:%M UTC")));
+    task.metadata.insert("source".to_string(), "recording".to_string());
+    task.metadata.insert("recorded_at".to_string(), session.started_at.to_rfc3339());
+
+    let mut steps = compiler.steps;
+    if options.lift_literals {
+        task.inputs = lift_literals(&mut steps, &compiler.masked);
+    } else if !compiler.masked.is_empty() {
+        eprintln!("⚠️ {} field(s) were masked while recording; fill in their values", compiler.masked.len());
+    }
+    for step in steps {
+        task.add_step(step);
+    }
+    println!(
+        "🛠️ Compiled {} event(s) into {} step(s) and {} input(s)",
+        session.events.len(),
+        task.steps.len(),
+        task.inputs.len()
+    );
+    task
+}
+
+struct Compiler<'a> {
+    options: &'a CompileOptions,
+    steps: Vec<TaskStep>,
+    current_url: Option<String>,
+    previous: Option<&'a RecordedEvent>, // Last event other than a navigation
+    page_changed: bool,
+    masked: Vec<Target>, // Sensitive fields whose value wasn't recorded
+}
+
+impl<'a> Compiler<'a> {
+    fn add(&mut self, event: &'a RecordedEvent) {
+        if event.action == EventAction::Navigate {
+            self.navigate(event);
+            return;
+        }
+        let Some(target) = &event.target else {
+            return;
+        };
+
+        match event.action {
+            EventAction::Type => {
+                if event.value.is_none() && !self.masked.iter().any(|masked| same(masked, target)) {
+                    self.masked.push(target.clone());
+                }
+                let value = event.value.clone().unwrap_or_default();
+                match self.steps.last_mut() {
+                    Some(TaskStep::FillForm(last, last_value)) if same(last, target) => *last_value = value,
+                    Some(TaskStep::ClickElement(last)) if same(last, target) => {
+                        // The click only focused the field
+                        *self.steps.last_mut().unwrap() = TaskStep::FillForm(target.clone(), value);
+                    }
+                    _ => {
+                        self.wait_if_late(event, target);
+                        self.steps.push(TaskStep::FillForm(target.clone(), value));
+                    }
+                }
+            }
+            EventAction::Select => {
+                let value = event.value.clone().unwrap_or_default();
+                match self.steps.last_mut() {
+                    Some(TaskStep::SelectOption(last, last_value)) if same(last, target) => *last_value = value,
+                    _ => {
+                        self.wait_if_late(event, target);
+                        self.steps.push(TaskStep::SelectOption(target.clone(), value));
+                    }
+                }
+            }
+            EventAction::Click => {
+                self.wait_if_late(event, target);
+                self.steps.push(TaskStep::ClickElement(target.clone()));
+            }
+            EventAction::Submit => {
+                let clicked = self.previous.is_some_and(|previous| {
+                    previous.action == EventAction::Click
+                        && event.timestamp_ms - previous.timestamp_ms <= self.options.navigation_gap_ms
+                });
+                if !clicked {
+                    // Submitted with Enter
+                    self.steps.push(TaskStep::SubmitForm(target.clone()));
+                }
+            }
+            EventAction::Navigate => unreachable!("handled above"),
+        }
+        self.previous = Some(event);
+    }
+
+    fn navigate(&mut self, event: &RecordedEvent) {
+        if self.current_url.as_deref() == Some(event.url.as_str()) {
+            return;
+        }
+        let caused = self
+            .previous
+            .is_some_and(|previous| event.timestamp_ms - previous.timestamp_ms <= self.options.navigation_gap_ms);
+        if self.current_url.is_none() || !caused {
+            self.steps.push(TaskStep::OpenWebsite(event.url.clone()));
+        }
+        self.current_url = Some(event.url.clone());
+        self.page_changed = true;
+    }
+
+    fn wait_if_late(&mut self, event: &RecordedEvent, target: &Target) {
+        let after_slow_click = self.previous.is_some_and(|previous| {
+            previous.action == EventAction::Click
+                && previous.target.as_ref().is_some_and(|clicked| !same(clicked, target))
+                && event.timestamp_ms - previous.timestamp_ms >= self.options.settle_ms
+        });
+        if self.page_changed || after_slow_click {
+            self.steps.push(TaskStep::WaitForElement(target.clone()));
+        }
+        self.page_changed = false;
+    }
+}
+
+/// Whether two targets locate the same element, ignoring fingerprints
+fn same(a: &Target, b: &Target) -> bool {
+    a.by == b.by && a.nth == b.nth && a.within == b.within
+}
+
+/// Replace typed values with `{{input}}` placeholders and declare the inputs.
+/// Every fill of the same field uses the same input, defaulting to the value
+/// typed first.
+fn lift_literals(steps: &mut [TaskStep], masked: &[Target]) -> Vec<TaskInput> {
+    let mut inputs = Vec::new();
+    let mut lifted: Vec<(Target, String)> = Vec::new(); // Field -> its input
+    let mut used = HashSet::new();
+    for step in steps {
+        let TaskStep::FillForm(target, value) = step else {
+            continue;
+        };
+        if let Some((_, name)) = lifted.iter().find(|(field, _)| same(field, target)) {
+            *value = format!("{{{{{}}}}}", name);
+            continue;
+        }
+        let base = input_name(target);
+        let mut name = base.clone();
+        let mut n = 2;
+        while !used.insert(name.clone()) {
+            name = format!("{}_{}", base, n);
+            n += 1;
+        }
+
+        let secret = masked.iter().any(|masked| same(masked, target))
+            || target
+                .fingerprint
+                .as_ref()
+                .is_some_and(|fingerprint| fingerprint.attributes.get("type").map(String::as_str) == Some("password"));
+        inputs.push(TaskInput {
+            name: name.clone(),
+            kind: InputKind::String,
+            description: Some(format!("Typed into {}", target)),
+            default: (!secret).then(|| value.clone()),
+        });
+        lifted.push((target.clone(), name.clone()));
+        *value = format!("{{{{{}}}}}", name);
+    }
+    inputs
+}
+
+/// A variable name describing the field, e.g. `email` for `input[name="email"]`
+fn input_name(target: &Target) -> String {
+    let hint = match &target.by {
+        By::Label(text) | By::TestId(text) | By::Text(text) | By::LinkText(text) => text.as_str(),
+        By::Role { name: Some(name), .. } => name.as_str(),
+        By::Css(selector) => css_hint(selector),
+        By::Role { name: None, .. } | By::XPath(_) => "",
+    };
+
+    let mut name = String::new();
+    for c in hint.chars() {
+        if c.is_ascii_alphanumeric() {
+            name.push(c.to_ascii_lowercase());
+        } else if !name.is_empty() && !name.ends_with('_') {
+            name.push('_');
+        }
+    }
+    let name = name.trim_end_matches('_');
+    if name.is_empty() {
+        "field".to_string()
+    } else if name.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("field_{}", name)
+    } else {
+        name.to_string()
+    }
+}
+
+/// The `name` attribute or id a simple CSS selector refers to
+fn css_hint(selector: &str) -> &str {
+    if let Some(start) = selector.find("[name=") {
+        let rest = selector[start + 6..].trim_start_matches(['"', '\'']);
+        return rest.split(['"', '\'', ']']).next().unwrap_or("");
+    }
+    match selector.rsplit_once('#') {
+        Some((_, id)) => id.split([' ', '.', '[', ':', '>']).next().unwrap_or(""),
+        None => "",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::observation_layer::dynamic_detection::ElementFingerprint;
+    use chrono::Utc;
+
+    const URL: &str = "https://example.com/login";
+
+    fn event(action: EventAction, target: Option<&Target>, value: Option<&str>, timestamp_ms: i64) -> RecordedEvent {
+        RecordedEvent {
+            action,
+            target: target.cloned(),
+            value: value.map(str::to_string),
+            url: URL.to_string(),
+            title: None,
+            timestamp_ms,
+        }
+    }
+
+    fn navigate(url: &str, timestamp_ms: i64) -> RecordedEvent {
+        RecordedEvent {
+            url: url.to_string(),
+            ..event(EventAction::Navigate, None, None, timestamp_ms)
+        }
+    }
+
+    fn click(target: &Target, timestamp_ms: i64) -> RecordedEvent {
+        event(EventAction::Click, Some(target), None, timestamp_ms)
+    }
+
+    fn typed(target: &Target, value: &str, timestamp_ms: i64) -> RecordedEvent {
+        event(EventAction::Type, Some(target), Some(value), timestamp_ms)
+    }
+
+    fn field(name: &str) -> Target {
+        Target::css(format!(r#"input[name="{}"]"#, name))
+    }
+
+    fn compile(events: Vec<RecordedEvent>) -> AutomationTask {
+        let session = RecordedSession {
+            version: 1,
+            started_at: Utc::now(),
+            finished_at: None,
+            events,
+        };
+        compile_session(&session, "recorded", &CompileOptions::default())
+    }
+
+    fn steps(task: &AutomationTask) -> Vec<TaskStep> {
+        task.steps.iter().map(|step| step.action.clone()).collect()
+    }
+
+    fn defaults(task: &AutomationTask) -> Vec<(&str, Option<&str>)> {
+        task.inputs.iter().map(|input| (input.name.as_str(), input.default.as_deref())).collect()
+    }
+
+    #[test]
+    fn keystrokes_collapse_into_one_fill() {
+        let email = field("email");
+        let task = compile(vec![
+            navigate(URL, 0),
+            click(&email, 1000),
+            typed(&email, "a", 1100),
+            typed(&email, "ab", 1200),
+            typed(&email, "ab@x.io", 1300),
+        ]);
+        assert_eq!(
+            steps(&task),
+            [
+                TaskStep::OpenWebsite(URL.to_string()),
+                TaskStep::WaitForElement(email.clone()),
+                TaskStep::FillForm(email, "{{email}}".to_string()),
+            ]
+        );
+        assert_eq!(defaults(&task), [("email", Some("ab@x.io"))]);
+    }
+
+    #[test]
+    fn opens_only_navigations_no_action_caused() {
+        let link = Target::css("a.next");
+        let task = compile(vec![
+            navigate(URL, 0),
+            navigate(URL, 100),
+            click(&link, 1000),
+            navigate("https://example.com/next", 1500),
+            navigate("https://example.com/other", 10_000),
+        ]);
+        assert_eq!(
+            steps(&task),
+            [
+                TaskStep::OpenWebsite(URL.to_string()),
+                TaskStep::WaitForElement(link.clone()),
+                TaskStep::ClickElement(link),
+                TaskStep::OpenWebsite("https://example.com/other".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn waits_for_elements_a_slow_click_revealed() {
+        let (menu, item, other) = (Target::css("#menu"), Target::css("#item"), Target::css("#other"));
+        let task = compile(vec![navigate(URL, 0), click(&menu, 100), click(&item, 2000), click(&other, 2100)]);
+        assert_eq!(
+            steps(&task),
+            [
+                TaskStep::OpenWebsite(URL.to_string()),
+                TaskStep::WaitForElement(menu.clone()),
+                TaskStep::ClickElement(menu),
+                TaskStep::WaitForElement(item.clone()),
+                TaskStep::ClickElement(item),
+                TaskStep::ClickElement(other),
+            ]
+        );
+    }
+
+    #[test]
+    fn drops_submits_caused_by_a_click() {
+        let (button, form, search) = (Target::css("#go"), Target::css("form"), field("q"));
+        let task = compile(vec![
+            navigate(URL, 0),
+            click(&button, 100),
+            event(EventAction::Submit, Some(&form), None, 150),
+            typed(&search, "rust", 5000),
+            event(EventAction::Submit, Some(&form), None, 5100), // Enter
+        ]);
+        assert_eq!(
+            steps(&task),
+            [
+                TaskStep::OpenWebsite(URL.to_string()),
+                TaskStep::WaitForElement(button.clone()),
+                TaskStep::ClickElement(button),
+                TaskStep::FillForm(search, "{{q}}".to_string()),
+                TaskStep::SubmitForm(form),
+            ]
+        );
+    }
+
+    #[test]
+    fn refilling_a_field_reuses_its_input() {
+        let email = field("email");
+        let mut fingerprint = ElementFingerprint::default();
+        fingerprint.attributes.insert("type".to_string(), "password".to_string());
+        let password = field("password").fingerprint(fingerprint);
+        let task = compile(vec![
+            navigate(URL, 0),
+            typed(&email, "a@x.io", 100),
+            typed(&password, "hunter2", 200),
+            typed(&email, "b@x.io", 300),
+        ]);
+        assert_eq!(
+            steps(&task)[3..],
+            [
+                TaskStep::FillForm(password, "{{password}}".to_string()),
+                TaskStep::FillForm(email, "{{email}}".to_string()),
+            ]
+        );
+        assert_eq!(defaults(&task), [("email", Some("a@x.io")), ("password", None)]);
+    }
+
+    #[test]
+    fn masked_fields_become_inputs_without_a_default() {
+        let otp = field("otp");
+        let task = compile(vec![
+            navigate(URL, 0),
+            event(EventAction::Type, Some(&otp), None, 100),
+            event(EventAction::Type, Some(&otp), None, 150),
+        ]);
+        assert_eq!(
+            steps(&task),
+            [
+                TaskStep::OpenWebsite(URL.to_string()),
+                TaskStep::WaitForElement(otp.clone()),
+                TaskStep::FillForm(otp, "{{otp}}".to_string()),
+            ]
+        );
+        assert_eq!(defaults(&task), [("otp", None)]);
+    }
+}
@@ -0,0 +1,309 @@
+use super::protocol::{receive, send, CoordinatorMessage, RemoteError, WorkerMessage, PROTOCOL_VERSION};
+use crate::browser_config::BrowserConfig;
+use crate::cancellation::RunControl;
+use crate::capabilities::Capabilities;
+use crate::observation_layer::interaction_log::{install_log_forwarder, LogContext, LOG_CONTEXT};
+use crate::web_interaction::{close_browser, start_browser};
+use crate::worker::{run_job, wait_while_paused, JobEnd};
+use fantoccini::Client;
+use parking_lot::Mutex;
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::BufReader;
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::{interval, sleep};
+
+/// Replies come back in the order the requests went out, so each one goes to
+/// the oldest waiting slot. `None` once the connection is gone.
+type Pending = Arc<Mutex<Option<VecDeque<oneshot::Sender<CoordinatorMessage>>>>>;
+
+/// The coordinator connection, shared by every slot
+#[derive(Clone)]
+struct Connection {
+    out: mpsc::UnboundedSender<WorkerMessage>,
+    pending: Pending,
+    run: RunControl,                               // Paused and resumed by the coordinator
+    jobs: Arc<Mutex<HashMap<usize, RunControl>>>, // Running jobs, for `Cancel`
+}
+
+impl Connection {
+    fn send(&self, message: WorkerMessage) -> Result<(), RemoteError> {
+        self.out.send(message).map_err(|_| RemoteError::Disconnected)
+    }
+
+    /// Ask for a job and wait for the coordinator's answer
+    async fn lease(&self) -> Result<CoordinatorMessage, RemoteError> {
+        let (reply_tx, reply) = oneshot::channel();
+        {
+            // Queue the reply slot and the request together so they stay in order
+            let mut pending = self.pending.lock();
+            let Some(pending) = pending.as_mut() else {
+                return Err(RemoteError::Disconnected);
+            };
+            pending.push_back(reply_tx);
+            self.send(WorkerMessage::Lease)?;
+        }
+        reply.await.map_err(|_| RemoteError::Disconnected)
+    }
+}
+
+/// Work for the coordinator at `addr` until it runs out of jobs, the
+/// connection drops, or `drain` is set (checked between jobs). Runs up to
+/// `capabilities.max_concurrency` jobs at once, each in its own browser, or
+/// without one if `capabilities.browsers` is empty.
+pub async fn run_remote_worker(
+    addr: &str,
+    name: &str,
+    browser: &BrowserConfig,
+    capabilities: Capabilities,
+    drain: Arc<AtomicBool>,
+) -> Result<(), RemoteError> {
+    let stream = TcpStream::connect(addr).await?;
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+
+    let slots = capabilities.max_concurrency.max(1);
+    let browser = (!capabilities.browsers.is_empty()).then(|| browser.clone());
+    send(
+        &mut writer,
+        &WorkerMessage::Register {
+            protocol: PROTOCOL_VERSION,
+            name: name.to_string(),
+            capabilities,
+        },
+    )
+    .await?;
+    let (worker_id, heartbeat_secs) = match receive(&mut reader).await? {
+        Some(CoordinatorMessage::Registered {
+            worker_id,
+            heartbeat_secs,
+            ..
+        }) => (worker_id, heartbeat_secs),
+        Some(CoordinatorMessage::Rejected { reason }) => return Err(RemoteError::Rejected(reason)),
+        Some(other) => return Err(RemoteError::Protocol(format!("unexpected {:?}", other))),
+        None => return Err(RemoteError::Disconnected),
+    };
+    println!("🤝 Registered with {} as worker {}", addr, worker_id);
+
+    // Everything we send goes through one writer task, so heartbeats and
+    // logs can be sent while a job runs
+    let (out, mut outgoing) = mpsc::unbounded_channel::<WorkerMessage>();
+    let writer_task = tokio::spawn(async move {
+        while let Some(message) = outgoing.recv().await {
+            if let Err(err) = send(&mut writer, &message).await {
+                eprintln!("❌ Lost the coordinator: {}", err);
+                break;
+            }
+            if matches!(message, WorkerMessage::Goodbye) {
+                break;
+            }
+        }
+    });
+
+    // Everything we receive is either a reply to some slot's lease request
+    // or a control message
+    let pending: Pending = Arc::new(Mutex::new(Some(VecDeque::new())));
+    let run = RunControl::new();
+    let jobs: Arc<Mutex<HashMap<usize, RunControl>>> = Arc::default();
+    let reader_task = {
+        let pending = Arc::clone(&pending);
+        let run = run.clone();
+        let jobs = Arc::clone(&jobs);
+        tokio::spawn(async move {
+            loop {
+                match receive(&mut reader).await {
+                    Ok(Some(CoordinatorMessage::Cancel { job_id })) => {
+                        println!("🛑 Coordinator cancelled job {}", job_id);
+                        if let Some(job) = jobs.lock().get(&job_id) {
+                            job.cancel();
+                        }
+                    }
+                    Ok(Some(CoordinatorMessage::Pause)) => {
+                        println!("⏸️ Coordinator paused the run");
+                        run.pause();
+                    }
+                    Ok(Some(CoordinatorMessage::Resume)) => run.resume(),
+                    Ok(Some(message)) => {
+                        let waiting = pending.lock().as_mut().and_then(VecDeque::pop_front);
+                        match waiting {
+                            Some(reply) => {
+                                let _ = reply.send(message);
+                            }
+                            None => eprintln!("Worker {}: unexpected {:?}", worker_id, message),
+                        }
+                    }
+                    Ok(None) => break,
+                    Err(err) => {
+                        eprintln!("❌ Lost the coordinator: {}", err);
+                        break;
+                    }
+                }
+            }
+            *pending.lock() = None; // Wakes every waiting slot with `Disconnected`
+        })
+    };
+
+    let heartbeat = {
+        let out = out.clone();
+        tokio::spawn(async move {
+            let mut tick = interval(Duration::from_secs(heartbeat_secs.max(1)));
+            while out.send(WorkerMessage::Heartbeat).is_ok() {
+                tick.tick().await;
+            }
+        })
+    };
+
+    // Stream every interaction log to the coordinator
+    let (log_tx, mut log_rx) = mpsc::unbounded_channel();
+    install_log_forwarder(log_tx);
+    {
+        let out = out.clone();
+        tokio::spawn(async move {
+            while let Some(log) = log_rx.recv().await {
+                if out.send(WorkerMessage::Log { log }).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    let connection = Connection { out, pending, run, jobs };
+    let handles: Vec<_> = (0..slots)
+        .map(|slot| {
+            let label = if slots > 1 {
+                format!("{}/{}", worker_id, slot + 1)
+            } else {
+                worker_id.to_string()
+            };
+            tokio::spawn(run_slot(
+                worker_id,
+                label,
+                browser.clone(),
+                connection.clone(),
+                Arc::clone(&drain),
+            ))
+        })
+        .collect();
+    let mut outcome = Ok(());
+    for handle in handles {
+        let ended = match handle.await {
+            Ok(ended) => ended,
+            Err(err) => Err(RemoteError::Browser(format!("slot crashed: {}", err))),
+        };
+        if outcome.is_ok() {
+            outcome = ended;
+        }
+    }
+
+    heartbeat.abort();
+    let _ = connection.send(WorkerMessage::Goodbye);
+    let _ = writer_task.await; // Flushes everything queued before the goodbye
+    reader_task.abort();
+    outcome
+}
+
+/// One browser (or none, for a worker without one) leasing and running jobs
+/// until there are none left
+async fn run_slot(
+    worker_id: usize,
+    label: String,
+    browser: Option<BrowserConfig>,
+    connection: Connection,
+    drain: Arc<AtomicBool>,
+) -> Result<(), RemoteError> {
+    let context = LogContext {
+        worker_id: Some(worker_id),
+        ..LogContext::default()
+    };
+    let start = || async {
+        let Some(browser) = &browser else {
+            return Ok(None);
+        };
+        LOG_CONTEXT
+            .scope(context.clone(), start_browser(browser))
+            .await
+            .map(Some)
+            .map_err(|err| RemoteError::Browser(err.to_string()))
+    };
+    let mut client = start().await?;
+
+    let outcome = loop {
+        if drain.load(Ordering::Relaxed) {
+            println!("Worker {} draining", label);
+            break Ok(());
+        }
+        if wait_while_paused(&connection.run, || {}).await.is_err() {
+            break Ok(());
+        }
+        let job = match connection.lease().await {
+            Ok(CoordinatorMessage::Job { attempt, job }) => {
+                println!(
+                    "Worker {} processing job {}: {} ({} subtasks, attempt {})",
+                    label,
+                    job.id,
+                    job.description,
+                    job.subtasks.len(),
+                    attempt
+                );
+                job
+            }
+            Ok(CoordinatorMessage::NoJob { retry_ms }) => {
+                sleep(Duration::from_millis(retry_ms)).await;
+                continue;
+            }
+            Ok(CoordinatorMessage::Shutdown) => break Ok(()),
+            Ok(CoordinatorMessage::Error { message }) => break Err(RemoteError::Protocol(message)),
+            Ok(other) => break Err(RemoteError::Protocol(format!("unexpected {:?}", other))),
+            Err(err) => break Err(err),
+        };
+
+        // Results are streamed as they come; `Complete` follows the last one
+        let (result_tx, mut results) = mpsc::channel(job.subtasks.len().max(1));
+        let forward = {
+            let connection = connection.clone();
+            tokio::spawn(async move {
+                while let Some(result) = results.recv().await {
+                    let _ = connection.send(WorkerMessage::Result { result });
+                }
+            })
+        };
+        let job_run = connection.run.child();
+        connection.jobs.lock().insert(job.id, job_run.clone());
+        let end = run_job(worker_id, client.as_mut(), &job, &context, &result_tx, &job_run, || {}).await;
+        connection.jobs.lock().remove(&job.id);
+        drop(result_tx);
+        let _ = forward.await;
+        match end {
+            JobEnd::Finished => {
+                let _ = connection.send(WorkerMessage::Complete { job_id: job.id });
+            }
+            JobEnd::Cancelled => {
+                // A WebDriver call may have been cut off halfway, so start
+                // the next job in a fresh browser
+                let _ = connection.send(WorkerMessage::Complete { job_id: job.id });
+                close(&label, client.take()).await;
+                client = start().await?;
+            }
+            JobEnd::SessionLost => {
+                let _ = connection.send(WorkerMessage::Release { job_id: job.id });
+                break Err(RemoteError::Browser("session lost".to_string()));
+            }
+            JobEnd::Disconnected => break Err(RemoteError::Disconnected),
+        }
+    };
+
+    println!("Worker {} exiting", label);
+    close(&label, client).await;
+    outcome
+}
+
+async fn close(label: &str, client: Option<Client>) {
+    if let Some(client) = client {
+        if let Err(err) = close_browser(client).await {
+            eprintln!("Worker {}: Failed to close browser: {:?}", label, err);
+        }
+    }
+}
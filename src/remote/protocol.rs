@@ -0,0 +1,191 @@
+use crate::capabilities::Capabilities;
+use crate::observation_layer::interaction_log::InteractionLog;
+use crate::task_model::{Job, TaskResult};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::io;
+use thiserror::Error;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Bumped on any change a peer on the previous version couldn't handle
+pub const PROTOCOL_VERSION: u32 = 4;
+
+/// How often a worker says it is still alive
+pub const HEARTBEAT_INTERVAL_SECS: u64 = 5;
+
+/// Silence after which the coordinator drops a worker and requeues its jobs
+pub const HEARTBEAT_TIMEOUT_SECS: u64 = 20;
+
+/// Longest message either side accepts, so a peer can't make the other
+/// buffer without end; far more than any job or log entry needs
+pub const MAX_MESSAGE_BYTES: u64 = 8 * 1024 * 1024;
+
+#[derive(Error, Debug)]
+pub enum RemoteError {
+    #[error("Connection failed: {0}")]
+    Io(#[from] io::Error),
+    #[error("Protocol error: {0}")]
+    Protocol(String),
+    #[error("Coordinator rejected the worker: {0}")]
+    Rejected(String),
+    #[error("Failed to start browser: {0}")]
+    Browser(String),
+    #[error("Connection closed")]
+    Disconnected,
+}
+
+/// Worker to coordinator. `Register` and `Lease` get exactly one reply; the
+/// rest are one-way.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum WorkerMessage {
+    Register {
+        protocol: u32,
+        name: String,
+        #[serde(default)]
+        capabilities: Capabilities, // Jobs are only routed to workers that can run them
+    },
+    Heartbeat, // Also keeps the worker's leases from expiring
+    Lease,
+    Log { log: InteractionLog },
+    Result { result: TaskResult },
+    Complete { job_id: usize }, // Every result of the job has been sent
+    Release { job_id: usize },  // Can't finish the job (its browser died); give it to someone else
+    Goodbye,
+}
+
+/// Coordinator to worker. `Cancel`, `Pause` and `Resume` can arrive at any
+/// time; everything else answers the worker's requests in order.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CoordinatorMessage {
+    Registered {
+        protocol: u32,
+        worker_id: usize,
+        heartbeat_secs: u64,
+    },
+    Rejected { reason: String },
+    Job { attempt: u32, job: Job },
+    NoJob { retry_ms: u64 },
+    Shutdown, // Nothing left to do
+    Error { message: String },
+    Cancel { job_id: usize }, // Stop working on the job and report it cancelled
+    Pause,                    // Start no new subtask until `Resume`
+    Resume,
+}
+
+impl CoordinatorMessage {
+    /// Sent unprompted rather than in reply to a request
+    pub fn is_control(&self) -> bool {
+        matches!(
+            self,
+            CoordinatorMessage::Cancel { .. } | CoordinatorMessage::Pause | CoordinatorMessage::Resume
+        )
+    }
+}
+
+/// Write `message` as one line of JSON
+pub async fn send<W: AsyncWrite + Unpin>(writer: &mut W, message: &impl Serialize) -> io::Result<()> {
+    let mut line = serde_json::to_vec(message).map_err(io::Error::from)?;
+    line.push(b'\n');
+    writer.write_all(&line).await?;
+    writer.flush().await
+}
+
+/// Read the next message; `None` once the peer hung up. Messages longer
+/// than `MAX_MESSAGE_BYTES` are refused rather than buffered.
+pub async fn receive<R: AsyncBufRead + Unpin, M: DeserializeOwned>(reader: &mut R) -> Result<Option<M>, RemoteError> {
+    let mut line = String::new();
+    loop {
+        let read = (&mut *reader).take(MAX_MESSAGE_BYTES).read_line(&mut line).await?;
+        if read == 0 {
+            return Ok(None);
+        }
+        if read as u64 == MAX_MESSAGE_BYTES && !line.ends_with('\n') {
+            return Err(RemoteError::Protocol(format!(
+                "message longer than {} bytes",
+                MAX_MESSAGE_BYTES
+            )));
+        }
+        if !line.trim().is_empty() {
+            break;
+        }
+        line.clear();
+    }
+    serde_json::from_str(&line)
+        .map(Some)
+        .map_err(|err| RemoteError::Protocol(format!("{} in '{}'", err, line.trim())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::BufReader;
+
+    /// An `error` message whose JSON line, newline included, is `len` bytes
+    fn error_line(len: usize) -> Vec<u8> {
+        let empty = br#"{"type":"error","message":""}"#.len() + 1;
+        let message = "x".repeat(len - empty);
+        let mut line = serde_json::to_vec(&CoordinatorMessage::Error { message }).unwrap();
+        line.push(b'\n');
+        assert_eq!(line.len(), len);
+        line
+    }
+
+    async fn receive_all(bytes: &[u8]) -> Result<Option<CoordinatorMessage>, RemoteError> {
+        receive(&mut BufReader::new(bytes)).await
+    }
+
+    #[tokio::test]
+    async fn round_trips_messages_and_skips_blank_lines() {
+        let mut bytes = Vec::new();
+        send(&mut bytes, &CoordinatorMessage::Cancel { job_id: 3 }).await.unwrap();
+        bytes.extend_from_slice(b"\n  \n");
+        send(&mut bytes, &CoordinatorMessage::Shutdown).await.unwrap();
+
+        let mut reader = BufReader::new(bytes.as_slice());
+        let first: Option<CoordinatorMessage> = receive(&mut reader).await.unwrap();
+        assert!(matches!(first, Some(CoordinatorMessage::Cancel { job_id: 3 })));
+        let second: Option<CoordinatorMessage> = receive(&mut reader).await.unwrap();
+        assert!(matches!(second, Some(CoordinatorMessage::Shutdown)));
+        let end: Option<CoordinatorMessage> = receive(&mut reader).await.unwrap();
+        assert!(end.is_none());
+    }
+
+    #[tokio::test]
+    async fn accepts_a_message_of_exactly_the_limit() {
+        let line = error_line(MAX_MESSAGE_BYTES as usize);
+        match receive_all(&line).await {
+            Ok(Some(CoordinatorMessage::Error { message })) => assert_eq!(message.len() as u64, MAX_MESSAGE_BYTES - 30),
+            other => panic!("expected the message, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn refuses_a_message_over_the_limit() {
+        let line = error_line(MAX_MESSAGE_BYTES as usize + 1);
+        match receive_all(&line).await {
+            Err(RemoteError::Protocol(message)) => {
+                assert_eq!(message, format!("message longer than {} bytes", MAX_MESSAGE_BYTES))
+            }
+            other => panic!("expected the size limit, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn reports_malformed_messages() {
+        match receive_all(b"{\"type\":\"bogus\"}\n").await {
+            Err(RemoteError::Protocol(message)) => assert!(message.contains("bogus"), "{}", message),
+            other => panic!("expected a protocol error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn only_cancel_pause_and_resume_are_control_messages() {
+        assert!(CoordinatorMessage::Cancel { job_id: 1 }.is_control());
+        assert!(CoordinatorMessage::Pause.is_control());
+        assert!(CoordinatorMessage::Resume.is_control());
+        assert!(!CoordinatorMessage::Shutdown.is_control());
+        assert!(!CoordinatorMessage::NoJob { retry_ms: 10 }.is_control());
+    }
+}
@@ -0,0 +1,230 @@
+use super::protocol::{
+    receive, send, CoordinatorMessage, RemoteError, WorkerMessage, HEARTBEAT_INTERVAL_SECS, HEARTBEAT_TIMEOUT_SECS,
+    PROTOCOL_VERSION,
+};
+use crate::cancellation::{RunControl, RunState};
+use crate::job_queue::{Lease, SharedQueue, DEFAULT_VISIBILITY_TIMEOUT_SECS};
+use crate::observation_layer::interaction_log::emit;
+use crate::task_model::TaskResult;
+use crate::worker_pool::SharedRegistry;
+use std::collections::{BTreeSet, HashMap};
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::BufReader;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::timeout;
+
+/// How long a worker with nothing to do waits before asking again
+const NO_JOB_RETRY_MS: u64 = 500;
+
+/// Accept remote workers on `addr`. They lease jobs from `queue` like local
+/// workers do, their results go to `result_tx`, and they are paused along
+/// with `control`.
+pub async fn listen(
+    addr: &str,
+    queue: SharedQueue,
+    result_tx: mpsc::Sender<TaskResult>,
+    registry: SharedRegistry,
+    control: RunControl,
+) -> io::Result<JoinHandle<()>> {
+    let listener = TcpListener::bind(addr).await?;
+    println!("📡 Listening for remote workers on {}", listener.local_addr()?);
+    Ok(tokio::spawn(async move {
+        loop {
+            let (stream, peer) = match listener.accept().await {
+                Ok(accepted) => accepted,
+                Err(err) => {
+                    eprintln!("❌ Failed to accept a worker connection: {}", err);
+                    continue;
+                }
+            };
+            let queue = Arc::clone(&queue);
+            let result_tx = result_tx.clone();
+            let registry = Arc::clone(&registry);
+            let control = control.clone();
+            tokio::spawn(async move {
+                if let Err(err) = serve_worker(stream, queue, result_tx, registry, control).await {
+                    eprintln!("❌ Remote worker at {}: {}", peer, err);
+                }
+            });
+        }
+    }))
+}
+
+/// Talk to one worker until it leaves, goes quiet or breaks the protocol.
+/// Whatever it still holds is put back on the queue for someone else.
+/// Cancellations and pauses reach it with the next heartbeat.
+async fn serve_worker(
+    stream: TcpStream,
+    queue: SharedQueue,
+    result_tx: mpsc::Sender<TaskResult>,
+    registry: SharedRegistry,
+    control: RunControl,
+) -> Result<(), RemoteError> {
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+    let silence = Duration::from_secs(HEARTBEAT_TIMEOUT_SECS);
+
+    let (name, capabilities) = match timeout(silence, receive(&mut reader)).await {
+        Ok(Ok(Some(WorkerMessage::Register {
+            protocol,
+            name,
+            capabilities,
+        }))) if protocol == PROTOCOL_VERSION => (name, capabilities),
+        Ok(Ok(Some(WorkerMessage::Register { protocol, .. }))) => {
+            let reason = format!("protocol {} is not supported (coordinator speaks {})", protocol, PROTOCOL_VERSION);
+            send(&mut writer, &CoordinatorMessage::Rejected { reason: reason.clone() }).await?;
+            return Err(RemoteError::Rejected(reason));
+        }
+        Ok(Ok(Some(_))) => return Err(RemoteError::Protocol("expected register".to_string())),
+        Ok(Ok(None)) => return Err(RemoteError::Disconnected),
+        Ok(Err(err)) => return Err(err),
+        Err(_) => return Err(RemoteError::Protocol("no register message".to_string())),
+    };
+    if capabilities.max_concurrency == 0 {
+        let reason = "max_concurrency must be at least 1".to_string();
+        send(&mut writer, &CoordinatorMessage::Rejected { reason: reason.clone() }).await?;
+        return Err(RemoteError::Rejected(reason));
+    }
+    let worker_id = registry.register(capabilities.clone());
+    send(
+        &mut writer,
+        &CoordinatorMessage::Registered {
+            protocol: PROTOCOL_VERSION,
+            worker_id,
+            heartbeat_secs: HEARTBEAT_INTERVAL_SECS,
+        },
+    )
+    .await?;
+    println!(
+        "🤝 Remote worker {} registered as {} ({} slot(s), browsers {:?}, plugins {:?}, labels {:?})",
+        name,
+        worker_id,
+        capabilities.max_concurrency,
+        capabilities.browsers,
+        capabilities.plugins,
+        capabilities.labels
+    );
+
+    let visibility = chrono::Duration::seconds(DEFAULT_VISIBILITY_TIMEOUT_SECS);
+    let mut leases: HashMap<usize, Lease> = HashMap::new();
+    let mut cancel_sent: BTreeSet<usize> = BTreeSet::new();
+    let mut paused = false;
+    let outcome = loop {
+        let message = match timeout(silence, receive(&mut reader)).await {
+            Ok(Ok(Some(message))) => message,
+            Ok(Ok(None)) => break Err(RemoteError::Disconnected),
+            Ok(Err(err)) => {
+                let _ = send(&mut writer, &CoordinatorMessage::Error { message: err.to_string() }).await;
+                break Err(err);
+            }
+            Err(_) => break Err(RemoteError::Protocol(format!("no heartbeat for {}s", HEARTBEAT_TIMEOUT_SECS))),
+        };
+        match message {
+            WorkerMessage::Heartbeat => {
+                let mut notices = Vec::new();
+                {
+                    let mut queue = queue.lock();
+                    for (&job_id, lease) in leases.iter_mut() {
+                        if let Err(err) = queue.extend(lease, visibility) {
+                            eprintln!("Remote worker {}: {}", worker_id, err);
+                        }
+                        if queue.is_cancelling(lease.id) && cancel_sent.insert(job_id) {
+                            notices.push(CoordinatorMessage::Cancel { job_id });
+                        }
+                    }
+                }
+                if paused != (control.state() == RunState::Paused) {
+                    paused = !paused;
+                    notices.push(if paused { CoordinatorMessage::Pause } else { CoordinatorMessage::Resume });
+                }
+                for notice in &notices {
+                    send(&mut writer, notice).await?;
+                }
+            }
+            WorkerMessage::Lease => {
+                let (leased, finished) = if leases.len() >= capabilities.max_concurrency || paused {
+                    (Ok(None), false) // Every slot is busy (it shouldn't have asked), or we're paused
+                } else {
+                    let mut queue = queue.lock();
+                    (queue.lease(worker_id, &capabilities, visibility), queue.is_finished())
+                };
+                let reply = match leased {
+                    Ok(Some(lease)) => {
+                        let reply = CoordinatorMessage::Job {
+                            attempt: lease.attempt,
+                            job: lease.job.clone(),
+                        };
+                        leases.insert(lease.id as usize, lease);
+                        reply
+                    }
+                    Ok(None) if finished => CoordinatorMessage::Shutdown,
+                    Ok(None) => CoordinatorMessage::NoJob {
+                        retry_ms: NO_JOB_RETRY_MS,
+                    },
+                    Err(err) => {
+                        eprintln!("Remote worker {}: {}", worker_id, err);
+                        CoordinatorMessage::NoJob {
+                            retry_ms: NO_JOB_RETRY_MS,
+                        }
+                    }
+                };
+                send(&mut writer, &reply).await?;
+            }
+            WorkerMessage::Log { log } => emit(log.worker(worker_id)),
+            WorkerMessage::Result { mut result } => {
+                if !leases.contains_key(&result.job_id) {
+                    eprintln!("Remote worker {} sent a result for job {} it doesn't hold", worker_id, result.job_id);
+                    continue;
+                }
+                result.worker_id = worker_id;
+                if result_tx.send(result).await.is_err() {
+                    break Ok(()); // The coordinator is shutting down
+                }
+            }
+            WorkerMessage::Complete { job_id } => {
+                cancel_sent.remove(&job_id);
+                let Some(lease) = leases.remove(&job_id) else {
+                    eprintln!("Remote worker {} completed job {} it doesn't hold", worker_id, job_id);
+                    continue;
+                };
+                let acked = queue.lock().ack(&lease);
+                if let Err(err) = acked {
+                    eprintln!("Remote worker {}: {}", worker_id, err);
+                }
+            }
+            WorkerMessage::Release { job_id } => {
+                cancel_sent.remove(&job_id);
+                let Some(lease) = leases.remove(&job_id) else {
+                    continue;
+                };
+                let released = queue.lock().nack(&lease, chrono::Duration::zero());
+                match released {
+                    Ok(()) => println!("🔁 Remote worker {} released job {}", worker_id, job_id),
+                    Err(err) => eprintln!("Remote worker {}: {}", worker_id, err),
+                }
+            }
+            WorkerMessage::Goodbye => break Ok(()),
+            WorkerMessage::Register { .. } => {
+                let message = "already registered".to_string();
+                let _ = send(&mut writer, &CoordinatorMessage::Error { message: message.clone() }).await;
+                break Err(RemoteError::Protocol(message));
+            }
+        }
+    };
+
+    // Hand unfinished jobs to someone else right away instead of waiting for
+    // their leases to run out
+    registry.unregister(worker_id);
+    let released = queue.lock().release_worker(worker_id);
+    match released {
+        Ok(ids) if !ids.is_empty() => println!("🔁 Requeued job(s) {:?} from remote worker {}", ids, worker_id),
+        Ok(_) => {}
+        Err(err) => eprintln!("❌ Failed to requeue jobs of remote worker {}: {}", worker_id, err),
+    }
+    println!("👋 Remote worker {} ({}) left", worker_id, name);
+    outcome
+}
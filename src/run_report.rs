@@ -0,0 +1,414 @@
+use crate::cancellation::Cancelled;
+use crate::observation_layer::interaction_log::new_run_id;
+use crate::variables::VariableError;
+use crate::web_interaction::WebInteractionError;
+use chrono::{DateTime, Utc};
+use fantoccini::error::CmdError;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunStatus {
+    Passed,
+    Failed,
+    Aborted, // A step failed under the `abort` policy and the rest of the task was skipped
+    Cancelled, // Stopped from outside before it was done
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StepStatus {
+    Passed,
+    Failed,
+    Tolerated, // Failed, but its `continue` or `fallback` policy let the task carry on
+    Cancelled, // Interrupted while running
+}
+
+/// Coarse classification of a failure, stable enough for CI to match on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    BrowserStart,
+    Navigation,
+    ElementNotFound,
+    Timeout,
+    WebDriver,
+    Io,
+    Variables, // Missing, mistyped or unbound workflow variables
+    Extraction, // The element was found but had nothing to extract
+    ControlFlow, // A loop hit its cap, a loop source was unusable or a nested step failed
+    Cancelled, // Stopped from outside; never retried or handled by `on_error`
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StepError {
+    pub kind: ErrorKind,
+    pub message: String,
+}
+
+impl StepError {
+    pub fn new(kind: ErrorKind, message: impl fmt::Display) -> Self {
+        StepError {
+            kind,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for StepError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}: {}", self.kind, self.message)
+    }
+}
+
+impl From<Cancelled> for StepError {
+    fn from(err: Cancelled) -> Self {
+        StepError::new(ErrorKind::Cancelled, err)
+    }
+}
+
+impl From<VariableError> for StepError {
+    fn from(err: VariableError) -> Self {
+        StepError::new(ErrorKind::Variables, err)
+    }
+}
+
+impl From<CmdError> for StepError {
+    fn from(err: CmdError) -> Self {
+        let kind = match err {
+            CmdError::NoSuchElement(_) => ErrorKind::ElementNotFound,
+            CmdError::BadUrl(_) => ErrorKind::Navigation,
+            _ => ErrorKind::WebDriver,
+        };
+        StepError::new(kind, err)
+    }
+}
+
+impl From<WebInteractionError> for StepError {
+    fn from(err: WebInteractionError) -> Self {
+        match err {
+            WebInteractionError::FantocciniError(err) => err.into(),
+            err => StepError::new(ErrorKind::ElementNotFound, err),
+        }
+    }
+}
+
+/// Something a step produced that is worth keeping after the run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Artifact {
+    Screenshot { path: String },
+    ExtractedData { name: String, value: serde_json::Value },
+    // The step's target no longer matched and a look-alike element was used;
+    // `healed` is the selector to put in the workflow
+    HealedTarget { original: String, healed: String, confidence: f64 },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StepReport {
+    pub path: String, // Where the step sits in its workflow, e.g. `steps[2].then[0]`
+    pub step: String, // Human readable description of the step
+    pub status: StepStatus,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    pub duration_ms: u64,
+    pub attempts: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<StepError>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub artifacts: Vec<Artifact>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunReport {
+    #[serde(default)]
+    pub run_id: String, // Tags this run's entries in the interaction log
+    pub task: String,
+    pub status: RunStatus,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    pub duration_ms: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<StepError>, // Failure outside of any step, e.g. the browser never started
+    pub steps: Vec<StepReport>, // In the order they ran, each control-flow step before its nested steps
+}
+
+impl StepReport {
+    /// Start timing a step; finish it with `passed`, `failed`, `tolerated`
+    /// or `cancelled`
+    pub fn start(path: impl Into<String>, step: impl fmt::Debug) -> Self {
+        let now = Utc::now();
+        StepReport {
+            path: path.into(),
+            step: format!("{:?}", step),
+            status: StepStatus::Passed,
+            started_at: now,
+            finished_at: now,
+            duration_ms: 0,
+            attempts: 1,
+            error: None,
+            artifacts: Vec::new(),
+        }
+    }
+
+    pub fn attempts(mut self, attempts: u32) -> Self {
+        self.attempts = attempts;
+        self
+    }
+
+    pub fn passed(mut self, artifacts: Vec<Artifact>) -> Self {
+        self.artifacts = artifacts;
+        self.finish(StepStatus::Passed)
+    }
+
+    pub fn failed(mut self, error: StepError) -> Self {
+        self.error = Some(error);
+        self.finish(StepStatus::Failed)
+    }
+
+    pub fn tolerated(mut self, error: StepError) -> Self {
+        self.error = Some(error);
+        self.finish(StepStatus::Tolerated)
+    }
+
+    pub fn cancelled(self) -> Self {
+        self.finish(StepStatus::Cancelled)
+    }
+
+    fn finish(mut self, status: StepStatus) -> Self {
+        self.status = status;
+        self.finished_at = Utc::now();
+        self.duration_ms = elapsed_ms(self.started_at, self.finished_at);
+        self
+    }
+}
+
+impl RunReport {
+    pub fn start(task: &str) -> Self {
+        let now = Utc::now();
+        RunReport {
+            run_id: new_run_id(),
+            task: task.to_string(),
+            status: RunStatus::Passed,
+            started_at: now,
+            finished_at: now,
+            duration_ms: 0,
+            error: None,
+            steps: Vec::new(),
+        }
+    }
+
+    pub fn push_step(&mut self, step: StepReport) {
+        self.insert_step(self.steps.len(), step);
+    }
+
+    /// Record a step that finished after the steps nested in it, ahead of
+    /// them; they were recorded from `at` on
+    pub fn insert_step(&mut self, at: usize, step: StepReport) {
+        if step.status == StepStatus::Failed && self.status == RunStatus::Passed {
+            self.status = RunStatus::Failed;
+        }
+        self.steps.insert(at.min(self.steps.len()), step);
+    }
+
+    /// The steps recorded from `first` on ran inside a step whose policy
+    /// absorbed their failure (or that passed on a retry), so they no longer
+    /// fail the run
+    pub fn tolerate_from(&mut self, first: usize) {
+        for step in self.steps.iter_mut().skip(first) {
+            if step.status == StepStatus::Failed {
+                step.status = StepStatus::Tolerated;
+            }
+        }
+        self.recheck();
+    }
+
+    /// Forget the steps recorded from `first` on, because the attempt that
+    /// ran them is being retried
+    pub fn discard_from(&mut self, first: usize) {
+        self.steps.truncate(first);
+        self.recheck();
+    }
+
+    /// A run failed only by steps that are no longer failures passes again
+    fn recheck(&mut self) {
+        let failed = self.steps.iter().any(|step| step.status == StepStatus::Failed);
+        if self.status == RunStatus::Failed && !failed && self.error.is_none() {
+            self.status = RunStatus::Passed;
+        }
+    }
+
+    /// Mark the run as stopped early by an `abort` policy
+    pub fn abort(&mut self) {
+        self.status = RunStatus::Aborted;
+    }
+
+    /// Mark the run as stopped from outside; the steps after the last one
+    /// recorded never ran
+    pub fn cancel(&mut self) {
+        self.status = RunStatus::Cancelled;
+    }
+
+    /// All artifacts produced by the run, in step order
+    pub fn artifacts(&self) -> Vec<Artifact> {
+        self.steps
+            .iter()
+            .flat_map(|step| step.artifacts.iter().cloned())
+            .collect()
+    }
+
+    /// The first hard failure, if any
+    pub fn first_error(&self) -> Option<&StepError> {
+        self.error.as_ref().or_else(|| {
+            self.steps
+                .iter()
+                .find(|step| step.status == StepStatus::Failed)
+                .and_then(|step| step.error.as_ref())
+        })
+    }
+
+    /// Record a failure that is not tied to a particular step
+    pub fn fail(&mut self, error: StepError) {
+        self.status = RunStatus::Failed;
+        self.error = Some(error);
+    }
+
+    pub fn finish(mut self) -> Self {
+        self.finished_at = Utc::now();
+        self.duration_ms = elapsed_ms(self.started_at, self.finished_at);
+        self
+    }
+
+    pub fn passed(&self) -> bool {
+        self.status == RunStatus::Passed
+    }
+
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+
+    pub fn write_json(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        fs::write(path, self.to_json()?)
+    }
+}
+
+fn elapsed_ms(start: DateTime<Utc>, end: DateTime<Utc>) -> u64 {
+    (end - start).num_milliseconds().max(0) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn not_found() -> StepError {
+        StepError::new(ErrorKind::ElementNotFound, "no #submit")
+    }
+
+    #[test]
+    fn a_failed_step_fails_the_run() {
+        let mut report = RunReport::start("login");
+        report.push_step(StepReport::start("steps[0]", "open").passed(Vec::new()));
+        assert!(report.passed());
+        report.push_step(StepReport::start("steps[1]", "click").tolerated(not_found()));
+        assert!(report.passed(), "tolerated failures don't fail the run");
+        report.push_step(StepReport::start("steps[2]", "click").failed(not_found()));
+        assert_eq!(report.status, RunStatus::Failed);
+        assert_eq!(report.first_error().map(|err| err.kind), Some(ErrorKind::ElementNotFound));
+
+        report.abort();
+        assert_eq!(report.status, RunStatus::Aborted);
+        report.push_step(StepReport::start("steps[3]", "click").failed(not_found()));
+        assert_eq!(report.status, RunStatus::Aborted, "a later failure doesn't downgrade an abort");
+    }
+
+    #[test]
+    fn a_failure_outside_the_steps_fails_the_run() {
+        let mut report = RunReport::start("login");
+        report.fail(StepError::new(ErrorKind::BrowserStart, "no driver"));
+        assert_eq!(report.status, RunStatus::Failed);
+        assert_eq!(report.first_error().map(|err| err.kind), Some(ErrorKind::BrowserStart));
+    }
+
+    #[test]
+    fn tolerate_from_absorbs_nested_failures() {
+        let mut report = RunReport::start("login");
+        report.push_step(StepReport::start("steps[0]", "open").passed(Vec::new()));
+        report.push_step(StepReport::start("steps[1].then[0]", "click").failed(not_found()));
+        assert_eq!(report.status, RunStatus::Failed);
+
+        report.tolerate_from(1);
+        assert_eq!(report.steps[1].status, StepStatus::Tolerated);
+        assert_eq!(report.status, RunStatus::Passed);
+        assert!(report.first_error().is_none());
+    }
+
+    #[test]
+    fn tolerate_from_leaves_earlier_failures_alone() {
+        let mut report = RunReport::start("login");
+        report.push_step(StepReport::start("steps[0]", "click").failed(not_found()));
+        report.push_step(StepReport::start("steps[1].then[0]", "click").failed(not_found()));
+        report.tolerate_from(1);
+        assert_eq!(report.steps[0].status, StepStatus::Failed);
+        assert_eq!(report.status, RunStatus::Failed);
+
+        let mut report = RunReport::start("login");
+        report.fail(StepError::new(ErrorKind::BrowserStart, "no driver"));
+        report.push_step(StepReport::start("steps[0].then[0]", "click").failed(not_found()));
+        report.tolerate_from(0);
+        assert_eq!(report.status, RunStatus::Failed, "a failure outside the steps still counts");
+    }
+
+    #[test]
+    fn discard_from_forgets_a_retried_attempt() {
+        let mut report = RunReport::start("login");
+        report.push_step(StepReport::start("steps[0]", "open").passed(Vec::new()));
+        report.push_step(StepReport::start("steps[1].then[0]", "click").failed(not_found()));
+        report.discard_from(1);
+        assert_eq!(report.steps.len(), 1);
+        assert!(report.passed());
+    }
+
+    #[test]
+    fn parents_come_before_their_nested_steps() {
+        let mut report = RunReport::start("login");
+        report.push_step(StepReport::start("steps[0].then[0]", "click").passed(Vec::new()));
+        report.push_step(StepReport::start("steps[0].then[1]", "fill").passed(Vec::new()));
+        report.insert_step(0, StepReport::start("steps[0]", "if").passed(Vec::new()));
+        let paths: Vec<_> = report.steps.iter().map(|step| step.path.as_str()).collect();
+        assert_eq!(paths, ["steps[0]", "steps[0].then[0]", "steps[0].then[1]"]);
+    }
+
+    #[test]
+    fn serializes_to_the_json_ci_reads() {
+        let mut report = RunReport::start("login");
+        let screenshot = Artifact::Screenshot { path: "done.png".to_string() };
+        report.push_step(StepReport::start("steps[0]", "shot").attempts(2).passed(vec![screenshot]));
+        report.push_step(StepReport::start("steps[1]", "click").failed(not_found()));
+        let report = report.finish();
+
+        let value: serde_json::Value = serde_json::from_str(&report.to_json().unwrap()).unwrap();
+        assert_eq!(value["task"], "login");
+        assert_eq!(value["status"], "failed");
+        assert_eq!(value["run_id"], json!(report.run_id));
+        assert!(value.get("error").is_none(), "no failure outside the steps");
+        for key in ["started_at", "finished_at", "duration_ms"] {
+            assert!(value.get(key).is_some(), "{}", key);
+        }
+
+        let steps = value["steps"].as_array().unwrap();
+        assert_eq!(steps[0]["path"], "steps[0]");
+        assert_eq!(steps[0]["status"], "passed");
+        assert_eq!(steps[0]["attempts"], 2);
+        assert_eq!(steps[0]["artifacts"], json!([{"type": "screenshot", "path": "done.png"}]));
+        assert!(steps[0].get("error").is_none());
+        assert_eq!(steps[1]["error"], json!({"kind": "element_not_found", "message": "no #submit"}));
+        assert!(steps[1].get("artifacts").is_none());
+
+        let parsed: RunReport = serde_json::from_value(value).unwrap();
+        assert_eq!(parsed.steps[1].status, StepStatus::Failed);
+    }
+}
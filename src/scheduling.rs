@@ -0,0 +1,283 @@
+use crate::task_model::Job;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::env;
+use std::fmt;
+use std::str::FromStr;
+
+/// Tenant of jobs that don't name one
+pub const DEFAULT_TENANT: &str = "default";
+
+/// How urgent a job is. Higher priorities are always leased first.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+    Urgent,
+}
+
+impl Priority {
+    pub fn is_normal(&self) -> bool {
+        *self == Priority::Normal
+    }
+}
+
+impl FromStr for Priority {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "low" => Ok(Priority::Low),
+            "normal" => Ok(Priority::Normal),
+            "high" => Ok(Priority::High),
+            "urgent" => Ok(Priority::Urgent),
+            _ => Err(format!("unknown priority '{}' (low, normal, high or urgent)", s)),
+        }
+    }
+}
+
+impl fmt::Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(&format!("{:?}", self).to_lowercase())
+    }
+}
+
+/// The tenant `job` is submitted for
+pub fn tenant_of(job: &Job) -> &str {
+    job.tenant.as_deref().unwrap_or(DEFAULT_TENANT)
+}
+
+/// How one tenant shares the workers with the others
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TenantPolicy {
+    pub weight: u32,                // Share of leases relative to other busy tenants
+    pub max_running: Option<usize>, // Jobs it may have leased at once
+}
+
+impl Default for TenantPolicy {
+    fn default() -> Self {
+        TenantPolicy {
+            weight: 1,
+            max_running: None,
+        }
+    }
+}
+
+/// Per-tenant weights and quotas; tenants not listed get `default`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SchedulingPolicy {
+    pub tenants: BTreeMap<String, TenantPolicy>,
+    pub default: TenantPolicy,
+}
+
+impl SchedulingPolicy {
+    /// Read from `SAE_TENANT_WEIGHTS` and `SAE_TENANT_QUOTAS` (both like
+    /// `alice=3,bob=1`) and `SAE_DEFAULT_TENANT_QUOTA`
+    pub fn from_env() -> Result<Self, String> {
+        let var = |name: &str| env::var(name).ok().filter(|v| !v.trim().is_empty());
+        let mut policy = SchedulingPolicy::default();
+        if let Some(weights) = var("SAE_TENANT_WEIGHTS") {
+            for (tenant, weight) in parse_weights("SAE_TENANT_WEIGHTS", &weights)? {
+                policy = policy.weight(tenant, weight)?;
+            }
+        }
+        if let Some(quotas) = var("SAE_TENANT_QUOTAS") {
+            for (tenant, quota) in parse_pairs("SAE_TENANT_QUOTAS", &quotas)? {
+                policy = policy.quota(tenant, quota);
+            }
+        }
+        if let Some(quota) = var("SAE_DEFAULT_TENANT_QUOTA") {
+            policy.default.max_running = Some(quota.trim().parse().map_err(|_| {
+                format!("SAE_DEFAULT_TENANT_QUOTA must be a whole number, got '{}'", quota)
+            })?);
+        }
+        Ok(policy)
+    }
+
+    pub fn weight(mut self, tenant: impl Into<String>, weight: u32) -> Result<Self, String> {
+        if weight == 0 {
+            return Err("Tenant weights must be at least 1".to_string());
+        }
+        let default = self.default;
+        self.tenants.entry(tenant.into()).or_insert(default).weight = weight;
+        Ok(self)
+    }
+
+    pub fn quota(mut self, tenant: impl Into<String>, max_running: usize) -> Self {
+        let default = self.default;
+        self.tenants.entry(tenant.into()).or_insert(default).max_running = Some(max_running);
+        self
+    }
+
+    pub fn tenant(&self, tenant: &str) -> TenantPolicy {
+        self.tenants.get(tenant).copied().unwrap_or(self.default)
+    }
+
+    /// Whether `tenant`, with `running` jobs leased, may start another
+    pub fn has_room(&self, tenant: &str, running: usize) -> bool {
+        self.tenant(tenant).max_running.is_none_or(|max| running < max)
+    }
+}
+
+/// `name=n` pairs separated by commas
+pub fn parse_pairs(what: &str, value: &str) -> Result<Vec<(String, usize)>, String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, n) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("{} expects name=number pairs, got '{}'", what, pair))?;
+            let n = n
+                .trim()
+                .parse()
+                .map_err(|_| format!("{} expects name=number pairs, got '{}'", what, pair))?;
+            Ok((name.trim().to_string(), n))
+        })
+        .collect()
+}
+
+/// `tenant=weight` pairs separated by commas
+pub fn parse_weights(what: &str, value: &str) -> Result<Vec<(String, u32)>, String> {
+    parse_pairs(what, value)?
+        .into_iter()
+        .map(|(tenant, weight)| {
+            match u32::try_from(weight) {
+                Ok(weight) => Ok((tenant, weight)),
+                Err(_) => Err(format!("{}: weight {} of '{}' is larger than {}", what, weight, tenant, u32::MAX)),
+            }
+        })
+        .collect()
+}
+
+/// Start-time fair queueing across tenants: every lease moves its tenant's
+/// clock forward by `1 / weight`, and the tenant furthest behind goes next.
+/// A tenant that was idle rejoins at the current clock instead of catching up
+/// on the time it missed.
+#[derive(Debug, Clone, Default)]
+pub struct FairShare {
+    clock: f64,
+    finish: HashMap<String, f64>,
+}
+
+impl FairShare {
+    /// Where `tenant`'s next job would start; lower goes first
+    pub fn start(&self, tenant: &str) -> f64 {
+        self.finish.get(tenant).copied().unwrap_or(0.0).max(self.clock)
+    }
+
+    /// Account for one job leased by `tenant`
+    pub fn charge(&mut self, tenant: &str, weight: u32) {
+        let start = self.start(tenant);
+        self.clock = start;
+        self.finish.insert(tenant.to_string(), start + 1.0 / weight.max(1) as f64);
+    }
+
+    /// The order `jobs` would be leased in if every worker could run them
+    /// all: by priority, then fairly across tenants, oldest first within a
+    /// tenant. Takes `(id, priority, tenant)` in id order.
+    pub fn order<'a>(
+        &self,
+        policy: &SchedulingPolicy,
+        jobs: impl IntoIterator<Item = (u64, Priority, &'a str)>,
+    ) -> Vec<u64> {
+        let mut classes: BTreeMap<Priority, BTreeMap<&str, VecDeque<u64>>> = BTreeMap::new();
+        for (id, priority, tenant) in jobs {
+            classes.entry(priority).or_default().entry(tenant).or_default().push_back(id);
+        }
+
+        let mut share = self.clone();
+        let mut order = Vec::new();
+        for (_, mut tenants) in classes.into_iter().rev() {
+            while let Some((&tenant, _)) = tenants
+                .iter()
+                .filter(|(_, ids)| !ids.is_empty())
+                .min_by(|(a, a_ids), (b, b_ids)| {
+                    share
+                        .start(a)
+                        .total_cmp(&share.start(b))
+                        .then(a_ids.front().cmp(&b_ids.front()))
+                })
+            {
+                order.extend(tenants.get_mut(tenant).and_then(VecDeque::pop_front));
+                share.charge(tenant, policy.tenant(tenant).weight);
+            }
+        }
+        order
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order(policy: &SchedulingPolicy, jobs: &[(u64, Priority, &str)]) -> Vec<u64> {
+        FairShare::default().order(policy, jobs.iter().copied())
+    }
+
+    #[test]
+    fn priority_comes_before_fairness() {
+        let jobs = [
+            (0, Priority::Low, "a"),
+            (1, Priority::Normal, "a"),
+            (2, Priority::Urgent, "b"),
+            (3, Priority::High, "a"),
+        ];
+        assert_eq!(order(&SchedulingPolicy::default(), &jobs), [2, 3, 1, 0]);
+    }
+
+    #[test]
+    fn tenants_with_equal_weights_take_turns() {
+        let jobs = ["a", "a", "a", "b", "b"].map(|tenant| (Priority::Normal, tenant));
+        let jobs: Vec<_> = (0..).zip(jobs).map(|(id, (priority, tenant))| (id, priority, tenant)).collect();
+        assert_eq!(order(&SchedulingPolicy::default(), &jobs), [0, 3, 1, 4, 2]);
+    }
+
+    #[test]
+    fn weights_set_each_tenants_share() {
+        let policy = SchedulingPolicy::default().weight("a", 2).unwrap();
+        let jobs = ["a", "a", "a", "a", "b", "b"].map(|tenant| (Priority::Normal, tenant));
+        let jobs: Vec<_> = (0..).zip(jobs).map(|(id, (priority, tenant))| (id, priority, tenant)).collect();
+        assert_eq!(order(&policy, &jobs), [0, 4, 1, 2, 5, 3]);
+    }
+
+    #[test]
+    fn idle_tenants_rejoin_at_the_current_clock() {
+        let mut share = FairShare::default();
+        for _ in 0..3 {
+            share.charge("a", 1);
+        }
+        assert_eq!(share.start("a"), 3.0);
+        assert_eq!(share.start("b"), 2.0, "b doesn't get credit for the time it was idle");
+    }
+
+    #[test]
+    fn quotas_limit_running_jobs() {
+        let policy = SchedulingPolicy::default().quota("a", 2);
+        assert!(policy.has_room("a", 1));
+        assert!(!policy.has_room("a", 2));
+        assert!(policy.has_room("b", 100), "tenants without a quota are unlimited");
+    }
+
+    #[test]
+    fn parses_weights() {
+        assert_eq!(
+            parse_weights("--tenant-weight", "a=3, b=1").unwrap(),
+            [("a".to_string(), 3), ("b".to_string(), 1)]
+        );
+        assert!(parse_weights("--tenant-weight", "a").is_err());
+        assert!(parse_weights("--tenant-weight", "a=4294967296").unwrap_err().contains("larger than"));
+        assert!(SchedulingPolicy::default().weight("a", 0).is_err());
+    }
+
+    #[test]
+    fn parses_priorities() {
+        assert_eq!(" High ".parse::<Priority>(), Ok(Priority::High));
+        assert!("asap".parse::<Priority>().is_err());
+        assert_eq!(Priority::Urgent.to_string(), "urgent");
+    }
+}
@@ -0,0 +1,493 @@
+use crate::observation_layer::dynamic_detection::ElementFingerprint;
+use crate::variables::{VariableError, Variables};
+use fantoccini::Locator;
+use serde::de::{self, MapAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+
+/// The element a step acts on.
+///
+/// In a workflow file a plain string is a CSS selector; anything else is a map
+/// naming one strategy, optionally narrowed with `nth` and `within`:
+///
+/// ```yaml
+/// - ClickElement: "#submit"
+/// - ClickElement: { role: button, name: Sign in }
+/// - FillForm: [{ label: Email, within: { test_id: login-form } }, "{{email}}"]
+/// - ClickElement: { text: Delete, nth: 1 }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Target {
+    pub by: By,
+    pub nth: Option<usize>,            // Pick the nth match (0-based) instead of the first
+    pub within: Option<Box<Target>>,   // Only search inside this element
+    pub fingerprint: Option<Box<ElementFingerprint>>, // Recorded look of the element, used to heal a broken target
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum By {
+    Css(String),
+    XPath(String),
+    LinkText(String),                              // Exact text of an `<a>`
+    Text(String),                                  // Any element whose own text contains this
+    Role { role: String, name: Option<String> },   // ARIA role, explicit or implied by the tag
+    Label(String),                                 // Form control by its `<label>` or `aria-label`
+    TestId(String),                                // `data-testid` attribute
+}
+
+/// What `By` turns into when it is handed to WebDriver
+#[derive(Debug, Clone, PartialEq)]
+pub enum Query {
+    Css(String),
+    XPath(String),
+    LinkText(String),
+}
+
+impl Query {
+    pub fn locator(&self) -> Locator<'_> {
+        match self {
+            Query::Css(selector) => Locator::Css(selector),
+            Query::XPath(path) => Locator::XPath(path),
+            Query::LinkText(text) => Locator::LinkText(text),
+        }
+    }
+}
+
+impl Target {
+    pub fn new(by: By) -> Self {
+        Target {
+            by,
+            nth: None,
+            within: None,
+            fingerprint: None,
+        }
+    }
+
+    pub fn css(selector: impl Into<String>) -> Self {
+        Target::new(By::Css(selector.into()))
+    }
+
+    pub fn nth(mut self, index: usize) -> Self {
+        self.nth = Some(index);
+        self
+    }
+
+    pub fn within(mut self, parent: Target) -> Self {
+        self.within = Some(Box::new(parent));
+        self
+    }
+
+    pub fn fingerprint(mut self, fingerprint: ElementFingerprint) -> Self {
+        self.fingerprint = Some(Box::new(fingerprint));
+        self
+    }
+
+    /// Strings that may contain `{{name}}` placeholders
+    pub fn templates(&self) -> Vec<&str> {
+        let mut templates = match &self.by {
+            By::Css(value)
+            | By::XPath(value)
+            | By::LinkText(value)
+            | By::Text(value)
+            | By::Label(value)
+            | By::TestId(value) => vec![value.as_str()],
+            By::Role { role, name } => std::iter::once(role.as_str()).chain(name.as_deref()).collect(),
+        };
+        if let Some(parent) = &self.within {
+            templates.extend(parent.templates());
+        }
+        templates
+    }
+
+    pub fn render(&self, vars: &Variables) -> Result<Target, VariableError> {
+        let by = match &self.by {
+            By::Css(selector) => By::Css(vars.render(selector)?),
+            By::XPath(path) => By::XPath(vars.render(path)?),
+            By::LinkText(text) => By::LinkText(vars.render(text)?),
+            By::Text(text) => By::Text(vars.render(text)?),
+            By::Role { role, name } => By::Role {
+                role: vars.render(role)?,
+                name: name.as_deref().map(|name| vars.render(name)).transpose()?,
+            },
+            By::Label(label) => By::Label(vars.render(label)?),
+            By::TestId(id) => By::TestId(vars.render(id)?),
+        };
+        Ok(Target {
+            by,
+            nth: self.nth,
+            within: self.within.as_deref().map(|parent| parent.render(vars).map(Box::new)).transpose()?,
+            fingerprint: self.fingerprint.clone(),
+        })
+    }
+
+    /// Why this target can never match anything, if it can't
+    pub fn problem(&self) -> Option<String> {
+        let empty = match &self.by {
+            By::Css(value)
+            | By::XPath(value)
+            | By::LinkText(value)
+            | By::Text(value)
+            | By::Label(value)
+            | By::TestId(value) => value.trim().is_empty(),
+            By::Role { role, name } => {
+                role.trim().is_empty() || name.as_deref().is_some_and(|name| name.trim().is_empty())
+            }
+        };
+        if empty {
+            return Some(format!("{} must not be empty", self.by.strategy()));
+        }
+        self.within.as_deref().and_then(Target::problem)
+    }
+}
+
+impl By {
+    pub fn strategy(&self) -> &'static str {
+        match self {
+            By::Css(_) => "css",
+            By::XPath(_) => "xpath",
+            By::LinkText(_) => "link_text",
+            By::Text(_) => "text",
+            By::Role { .. } => "role",
+            By::Label(_) => "label",
+            By::TestId(_) => "test_id",
+        }
+    }
+
+    /// The WebDriver query for this strategy. Generated XPath is made relative
+    /// (`.//`) when `scoped`, so it searches inside the parent element.
+    pub fn query(&self, scoped: bool) -> Query {
+        let prefix = if scoped { "." } else { "" };
+        match self {
+            By::Css(selector) => Query::Css(selector.clone()),
+            By::XPath(path) => Query::XPath(path.clone()),
+            By::LinkText(text) => Query::LinkText(text.clone()),
+            By::TestId(id) => Query::Css(format!("[data-testid={}]", css_string(id))),
+            By::Text(text) => Query::XPath(format!(
+                "{}//*[text()[contains(normalize-space(.), {})]]",
+                prefix,
+                xpath_literal(text.trim())
+            )),
+            By::Label(label) => {
+                let label = xpath_literal(label.trim());
+                Query::XPath(format!(
+                    "{prefix}//*[(self::input or self::select or self::textarea) and \
+                     (@id = //label[contains(normalize-space(.), {label})]/@for \
+                     or ancestor::label[contains(normalize-space(.), {label})] \
+                     or normalize-space(@aria-label) = {label})]"
+                ))
+            }
+            By::Role { role, name } => {
+                let role = role.trim();
+                let mut predicate = match implicit_role(role) {
+                    Some(tags) => format!("(@role = {} or (not(@role) and ({})))", xpath_literal(role), tags),
+                    None => format!("@role = {}", xpath_literal(role)),
+                };
+                if let Some(name) = name {
+                    let name = xpath_literal(name.trim());
+                    predicate.push_str(&format!(
+                        " and (normalize-space(@aria-label) = {name} or normalize-space(.) = {name} \
+                         or normalize-space(@value) = {name} or normalize-space(@title) = {name} \
+                         or normalize-space(@alt) = {name} or @id = //label[normalize-space(.) = {name}]/@for)"
+                    ));
+                }
+                Query::XPath(format!("{}//*[{}]", prefix, predicate))
+            }
+        }
+    }
+}
+
+/// XPath test for elements that carry `role` without an explicit attribute
+fn implicit_role(role: &str) -> Option<&'static str> {
+    Some(match role {
+        "button" => "self::button or (self::input and (@type = 'button' or @type = 'submit' or @type = 'reset'))",
+        "link" => "self::a and @href",
+        "textbox" => {
+            "self::textarea or (self::input and (not(@type) or @type = 'text' or @type = 'email' \
+             or @type = 'password' or @type = 'search' or @type = 'tel' or @type = 'url'))"
+        }
+        "checkbox" => "self::input and @type = 'checkbox'",
+        "radio" => "self::input and @type = 'radio'",
+        "combobox" => "self::select",
+        "heading" => "self::h1 or self::h2 or self::h3 or self::h4 or self::h5 or self::h6",
+        "img" => "self::img",
+        "list" => "self::ul or self::ol",
+        "listitem" => "self::li",
+        "table" => "self::table",
+        "row" => "self::tr",
+        "cell" => "self::td",
+        "form" => "self::form",
+        "navigation" => "self::nav",
+        _ => return None,
+    })
+}
+
+/// Quote `value` as an XPath 1.0 string literal, which has no escapes
+fn xpath_literal(value: &str) -> String {
+    if !value.contains('\'') {
+        format!("'{}'", value)
+    } else if !value.contains('"') {
+        format!("\"{}\"", value)
+    } else {
+        let parts: Vec<String> = value.split('\'').map(|part| format!("'{}'", part)).collect();
+        format!("concat({})", parts.join(", \"'\", "))
+    }
+}
+
+fn css_string(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(parent) = &self.within {
+            write!(f, "{} >> ", parent)?;
+        }
+        match &self.by {
+            By::Css(selector) => write!(f, "{}", selector)?,
+            By::XPath(path) => write!(f, "xpath={}", path)?,
+            By::LinkText(text) => write!(f, "link_text={:?}", text)?,
+            By::Text(text) => write!(f, "text={:?}", text)?,
+            By::Role { role, name: Some(name) } => write!(f, "role={}[name={:?}]", role, name)?,
+            By::Role { role, name: None } => write!(f, "role={}", role)?,
+            By::Label(label) => write!(f, "label={:?}", label)?,
+            By::TestId(id) => write!(f, "test_id={:?}", id)?,
+        }
+        if let Some(nth) = self.nth {
+            write!(f, " >> nth={}", nth)?;
+        }
+        Ok(())
+    }
+}
+
+impl From<&str> for Target {
+    fn from(selector: &str) -> Self {
+        Target::css(selector)
+    }
+}
+
+impl From<String> for Target {
+    fn from(selector: String) -> Self {
+        Target::css(selector)
+    }
+}
+
+/// The map form of a target in workflow files
+#[derive(Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TargetSpec {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    css: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    xpath: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    link_text: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    text: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    role: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    label: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    test_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    nth: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    within: Option<Box<Target>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    fingerprint: Option<Box<ElementFingerprint>>,
+}
+
+impl Serialize for Target {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if let (By::Css(selector), None, None, None) = (&self.by, self.nth, &self.within, &self.fingerprint) {
+            return serializer.serialize_str(selector);
+        }
+        let mut spec = TargetSpec {
+            nth: self.nth,
+            within: self.within.clone(),
+            fingerprint: self.fingerprint.clone(),
+            ..TargetSpec::default()
+        };
+        match &self.by {
+            By::Css(selector) => spec.css = Some(selector.clone()),
+            By::XPath(path) => spec.xpath = Some(path.clone()),
+            By::LinkText(text) => spec.link_text = Some(text.clone()),
+            By::Text(text) => spec.text = Some(text.clone()),
+            By::Role { role, name } => {
+                spec.role = Some(role.clone());
+                spec.name = name.clone();
+            }
+            By::Label(label) => spec.label = Some(label.clone()),
+            By::TestId(id) => spec.test_id = Some(id.clone()),
+        }
+        spec.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Target {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(TargetVisitor)
+    }
+}
+
+struct TargetVisitor;
+
+impl<'de> Visitor<'de> for TargetVisitor {
+    type Value = Target;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a CSS selector or a map with one of css, xpath, link_text, text, role, label or test_id")
+    }
+
+    fn visit_str<E: de::Error>(self, selector: &str) -> Result<Target, E> {
+        Ok(Target::css(selector))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Target, A::Error> {
+        let spec = TargetSpec::deserialize(de::value::MapAccessDeserializer::new(map))?;
+        let mut strategies = Vec::new();
+        if let Some(selector) = spec.css {
+            strategies.push(By::Css(selector));
+        }
+        if let Some(path) = spec.xpath {
+            strategies.push(By::XPath(path));
+        }
+        if let Some(text) = spec.link_text {
+            strategies.push(By::LinkText(text));
+        }
+        if let Some(text) = spec.text {
+            strategies.push(By::Text(text));
+        }
+        match (spec.role, spec.name) {
+            (Some(role), name) => strategies.push(By::Role { role, name }),
+            (None, Some(_)) => return Err(de::Error::custom("`name` is only allowed together with `role`")),
+            (None, None) => {}
+        }
+        if let Some(label) = spec.label {
+            strategies.push(By::Label(label));
+        }
+        if let Some(id) = spec.test_id {
+            strategies.push(By::TestId(id));
+        }
+
+        if strategies.len() > 1 {
+            let names: Vec<_> = strategies.iter().map(By::strategy).collect();
+            return Err(de::Error::custom(format!(
+                "a target uses exactly one strategy, got {}",
+                names.join(" and ")
+            )));
+        }
+        let by = strategies.pop().ok_or_else(|| {
+            de::Error::custom("a target needs one of css, xpath, link_text, text, role, label or test_id")
+        })?;
+        Ok(Target {
+            by,
+            nth: spec.nth,
+            within: spec.within,
+            fingerprint: spec.fingerprint,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn xpath(by: By, scoped: bool) -> String {
+        match by.query(scoped) {
+            Query::XPath(path) => path,
+            other => panic!("expected an XPath query, got {:?}", other),
+        }
+    }
+
+    fn parse(yaml: &str) -> Result<Target, String> {
+        serde_yaml::from_str(yaml).map_err(|err| err.to_string())
+    }
+
+    #[test]
+    fn native_strategies_pass_through() {
+        assert_eq!(By::Css("#a b".to_string()).query(true), Query::Css("#a b".to_string()));
+        assert_eq!(By::XPath("//a".to_string()).query(true), Query::XPath("//a".to_string()));
+        assert_eq!(By::LinkText("Home".to_string()).query(false), Query::LinkText("Home".to_string()));
+        assert_eq!(
+            By::TestId(r#"save "all""#.to_string()).query(false),
+            Query::Css(r#"[data-testid="save \"all\""]"#.to_string())
+        );
+    }
+
+    #[test]
+    fn generated_xpath_is_relative_when_scoped() {
+        let text = || By::Text(" Save ".to_string());
+        assert_eq!(xpath(text(), false), "//*[text()[contains(normalize-space(.), 'Save')]]");
+        assert_eq!(xpath(text(), true), ".//*[text()[contains(normalize-space(.), 'Save')]]");
+        assert!(xpath(By::Label("Email".to_string()), true).starts_with(".//*[(self::input or self::select"));
+        let tab = By::Role {
+            role: "tab".to_string(),
+            name: None,
+        };
+        assert_eq!(xpath(tab, true), ".//*[@role = 'tab']");
+    }
+
+    #[test]
+    fn roles_match_implicit_tags_and_names() {
+        let button = xpath(
+            By::Role {
+                role: "button".to_string(),
+                name: Some("Sign in".to_string()),
+            },
+            false,
+        );
+        assert!(button.starts_with("//*[(@role = 'button' or (not(@role) and (self::button or "), "{}", button);
+        assert!(button.contains("normalize-space(@aria-label) = 'Sign in'"), "{}", button);
+    }
+
+    #[test]
+    fn xpath_literals_survive_any_quotes() {
+        assert_eq!(xpath_literal("plain"), "'plain'");
+        assert_eq!(xpath_literal("it's"), r#""it's""#);
+        assert_eq!(xpath_literal(r#"say "it's""#), r#"concat('say "it', "'", 's"')"#);
+        assert_eq!(xpath_literal(r#"'""#), r#"concat('', "'", '"')"#);
+    }
+
+    #[test]
+    fn rejects_ambiguous_or_incomplete_maps() {
+        let err = parse("{ css: a, xpath: //a }").unwrap_err();
+        assert!(err.contains("exactly one strategy, got css and xpath"), "{}", err);
+        let err = parse("{ name: Save }").unwrap_err();
+        assert!(err.contains("`name` is only allowed together with `role`"), "{}", err);
+        let err = parse("{ nth: 1 }").unwrap_err();
+        assert!(err.contains("a target needs one of"), "{}", err);
+        assert!(parse("{ css: a, colour: red }").is_err());
+    }
+
+    #[test]
+    fn round_trips_through_serde() {
+        assert_eq!(serde_json::to_value(Target::css("#a")).unwrap(), json!("#a"));
+        assert_eq!(parse("'#a'").unwrap(), Target::css("#a"));
+
+        let target = Target::new(By::Role {
+            role: "button".to_string(),
+            name: Some("Sign in".to_string()),
+        })
+        .nth(1)
+        .within(Target::new(By::TestId("login".to_string())));
+        let value = serde_json::to_value(&target).unwrap();
+        assert_eq!(value, json!({ "role": "button", "name": "Sign in", "nth": 1, "within": { "test_id": "login" } }));
+        assert_eq!(serde_json::from_value::<Target>(value).unwrap(), target);
+
+        let fingerprint = ElementFingerprint {
+            tag: "input".to_string(),
+            ..ElementFingerprint::default()
+        };
+        let recorded = Target::css("#email").fingerprint(fingerprint);
+        let yaml = serde_yaml::to_string(&recorded).unwrap();
+        assert_eq!(parse(&yaml).unwrap(), recorded);
+        assert_eq!(
+            parse("{ label: Email, within: { test_id: login-form } }").unwrap(),
+            Target::new(By::Label("Email".to_string())).within(Target::new(By::TestId("login-form".to_string())))
+        );
+    }
+}
@@ -1,17 +1,613 @@
-use std::fs::{self, File};
-use std::io::{self, Write};
+use crate::task_model::AutomationTask;
+use crate::workflow::{load_workflow, WorkflowDocument, WorkflowError, WorkflowFormat};
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use parking_lot::RwLock;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::env;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+use thiserror::Error;
 
-pub fn save_task(name: String, steps: String) {
-    let file_name = format!("tasks/{}.txt", name);
-    fs::create_dir_all("tasks").unwrap();
-    let mut file = File::create(file_name).unwrap();
-    writeln!(file, "{}", steps).unwrap();
+/// Where the CLI keeps the task library unless told otherwise
+pub const DEFAULT_LIBRARY_DIR: &str = "tasks";
+
+/// Held while an entry is read, changed and written back
+const LOCK_FILE: &str = ".lock";
+
+/// How long to wait for another writer before giving up
+const LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A lock older than this was left behind by a writer that died
+const STALE_LOCK: Duration = Duration::from_secs(60);
+
+lazy_static! {
+    static ref LIBRARY_DIR: RwLock<Option<PathBuf>> = RwLock::new(None);
+}
+
+/// Use `dir` as the library from now on, e.g. from a `--library` flag
+pub fn set_library_dir(dir: impl Into<PathBuf>) {
+    *LIBRARY_DIR.write() = Some(dir.into());
+}
+
+/// The library tasks are saved to and looked up in: the one set with
+/// `set_library_dir`, else `SAE_LIBRARY_DIR`, else `DEFAULT_LIBRARY_DIR`
+pub fn library_dir() -> PathBuf {
+    if let Some(dir) = LIBRARY_DIR.read().clone() {
+        return dir;
+    }
+    match env::var("SAE_LIBRARY_DIR") {
+        Ok(dir) if !dir.trim().is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(DEFAULT_LIBRARY_DIR),
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum LibraryError {
+    #[error("Failed to access {path}: {source}")]
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    #[error("Library entry {path} is unreadable: {message}")]
+    Corrupt {
+        path: PathBuf,
+        message: String,
+    },
+    #[error("No task named or with id '{0}' in the library")]
+    NotFound(String),
+    #[error("A task named '{0}' is already in the library")]
+    NameTaken(String),
+    #[error("Timed out waiting for {0}; is another save stuck?")]
+    Locked(PathBuf),
+    #[error("Task '{task}' has no version {version}")]
+    VersionNotFound {
+        task: String,
+        version: u32,
+    },
+    #[error(transparent)]
+    Workflow(#[from] WorkflowError),
+}
+
+/// One saved revision of a task
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskVersion {
+    pub version: u32, // Starts at 1
+    pub saved_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>, // What changed, like a commit message
+    pub workflow: WorkflowDocument,
+}
+
+/// A task in the library with its metadata and every version saved so far
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LibraryEntry {
+    pub id: String, // Stable across renames; also the file name
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub tags: BTreeSet<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>, // Whoever saved the first version
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub versions: Vec<TaskVersion>, // Oldest first, never empty
+}
+
+impl LibraryEntry {
+    pub fn latest(&self) -> &TaskVersion {
+        self.versions.last().expect("library entries always have a version")
+    }
+
+    pub fn version(&self, version: u32) -> Result<&TaskVersion, LibraryError> {
+        self.versions
+            .iter()
+            .find(|v| v.version == version)
+            .ok_or_else(|| LibraryError::VersionNotFound {
+                task: self.name.clone(),
+                version,
+            })
+    }
+
+    /// Whether saving `task` under this entry would add nothing new
+    pub fn is_latest(&self, task: &AutomationTask) -> bool {
+        let mut task = task.clone();
+        task.name = self.latest().workflow.name.clone();
+        same_workflow(&self.latest().workflow, &WorkflowDocument::from_task(&task))
+    }
+
+    /// The task as of `version` (the latest if `None`), under the entry's
+    /// current name
+    pub fn task(&self, version: Option<u32>) -> Result<AutomationTask, LibraryError> {
+        let saved = match version {
+            Some(version) => self.version(version)?,
+            None => self.latest(),
+        };
+        let mut task = saved.workflow.clone().into_task();
+        task.name = self.name.clone();
+        Ok(task)
+    }
+}
+
+/// Filter for `TaskLibrary::search`; unset fields match everything
+#[derive(Debug, Clone, Default)]
+pub struct LibrarySearch {
+    pub text: Option<String>, // Case-insensitive, in name, description or tags
+    pub tag: Option<String>,
+    pub author: Option<String>,
+}
+
+impl LibrarySearch {
+    fn matches(&self, entry: &LibraryEntry) -> bool {
+        let text_matches = self.text.as_ref().is_none_or(|text| {
+            let text = text.to_lowercase();
+            entry.name.to_lowercase().contains(&text)
+                || entry
+                    .description
+                    .as_ref()
+                    .is_some_and(|description| description.to_lowercase().contains(&text))
+                || entry.tags.iter().any(|tag| tag.to_lowercase().contains(&text))
+        });
+        text_matches
+            && self.tag.as_ref().is_none_or(|tag| entry.tags.contains(tag))
+            && self
+                .author
+                .as_ref()
+                .is_none_or(|author| entry.versions.iter().any(|v| v.author.as_ref() == Some(author)))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffKind {
+    Same,
+    Added,
+    Removed,
+}
+
+/// One line of a diff between two versions' YAML
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiffLine {
+    pub kind: DiffKind,
+    pub text: String,
+}
+
+impl fmt::Display for DiffLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let marker = match self.kind {
+            DiffKind::Same => ' ',
+            DiffKind::Added => '+',
+            DiffKind::Removed => '-',
+        };
+        write!(f, "{} {}", marker, self.text)
+    }
+}
+
+/// Saved tasks, one JSON file per task in a directory
+pub struct TaskLibrary {
+    dir: PathBuf,
+}
+
+impl TaskLibrary {
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self, LibraryError> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir).map_err(|source| LibraryError::Io {
+            path: dir.clone(),
+            source,
+        })?;
+        Ok(TaskLibrary { dir })
+    }
+
+    /// Save `task` as a new library entry, or as the next version of the
+    /// entry with the same name. Saving an unchanged task adds no version.
+    pub fn save(
+        &self,
+        task: &AutomationTask,
+        author: Option<&str>,
+        note: Option<&str>,
+    ) -> Result<LibraryEntry, LibraryError> {
+        let workflow = WorkflowDocument::from_task(task);
+        workflow.validate()?;
+        let _lock = self.lock()?;
+        let now = Utc::now();
+
+        let mut entry = match self.find_by_name(&task.name)? {
+            Some(mut entry) => {
+                if same_workflow(&entry.latest().workflow, &workflow) {
+                    println!("📚 '{}' is unchanged at version {}", entry.name, entry.latest().version);
+                    return Ok(entry);
+                }
+                entry.description = task.description.clone();
+                entry
+            }
+            None => LibraryEntry {
+                id: self.new_id(),
+                name: task.name.clone(),
+                description: task.description.clone(),
+                tags: BTreeSet::new(),
+                author: author.map(str::to_string),
+                created_at: now,
+                updated_at: now,
+                versions: Vec::new(),
+            },
+        };
+
+        let version = entry.versions.last().map_or(1, |latest| latest.version + 1);
+        entry.versions.push(TaskVersion {
+            version,
+            saved_at: now,
+            author: author.map(str::to_string),
+            note: note.map(str::to_string),
+            workflow,
+        });
+        entry.updated_at = now;
+        self.write(&entry)?;
+        println!("📚 Saved '{}' as version {}", entry.name, version);
+        Ok(entry)
+    }
+
+    /// Look a task up by id or, failing that, by name
+    pub fn get(&self, key: &str) -> Result<LibraryEntry, LibraryError> {
+        let path = self.entry_path(key);
+        if is_id(key) && path.exists() {
+            return self.read(&path);
+        }
+        self.find_by_name(key)?.ok_or_else(|| LibraryError::NotFound(key.to_string()))
+    }
+
+    /// The task as of `version`, or its latest version
+    pub fn load_task(&self, key: &str, version: Option<u32>) -> Result<AutomationTask, LibraryError> {
+        self.get(key)?.task(version)
+    }
+
+    /// Every entry, sorted by name. Unreadable entries are skipped with a warning.
+    pub fn list(&self) -> Result<Vec<LibraryEntry>, LibraryError> {
+        let read_dir = fs::read_dir(&self.dir).map_err(|source| LibraryError::Io {
+            path: self.dir.clone(),
+            source,
+        })?;
+        let mut entries = Vec::new();
+        for file in read_dir {
+            let path = file
+                .map_err(|source| LibraryError::Io {
+                    path: self.dir.clone(),
+                    source,
+                })?
+                .path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+                continue;
+            }
+            match self.read(&path) {
+                Ok(entry) => entries.push(entry),
+                Err(err) => eprintln!("⚠️ {}", err),
+            }
+        }
+        entries.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.id.cmp(&b.id)));
+        Ok(entries)
+    }
+
+    pub fn search(&self, search: &LibrarySearch) -> Result<Vec<LibraryEntry>, LibraryError> {
+        Ok(self.list()?.into_iter().filter(|entry| search.matches(entry)).collect())
+    }
+
+    pub fn delete(&self, key: &str) -> Result<LibraryEntry, LibraryError> {
+        let _lock = self.lock()?;
+        let entry = self.get(key)?;
+        let path = self.entry_path(&entry.id);
+        fs::remove_file(&path).map_err(|source| LibraryError::Io { path, source })?;
+        println!("🗑️ Deleted '{}' from the library", entry.name);
+        Ok(entry)
+    }
+
+    /// Rename a task, keeping its id and history
+    pub fn rename(&self, key: &str, new_name: &str) -> Result<LibraryEntry, LibraryError> {
+        let _lock = self.lock()?;
+        let mut entry = self.get(key)?;
+        if entry.name == new_name {
+            return Ok(entry);
+        }
+        if self.find_by_name(new_name)?.is_some() {
+            return Err(LibraryError::NameTaken(new_name.to_string()));
+        }
+        println!("📚 Renamed '{}' to '{}'", entry.name, new_name);
+        entry.name = new_name.to_string();
+        entry.updated_at = Utc::now();
+        self.write(&entry)?;
+        Ok(entry)
+    }
+
+    pub fn set_tags(&self, key: &str, tags: impl IntoIterator<Item = String>) -> Result<LibraryEntry, LibraryError> {
+        let _lock = self.lock()?;
+        let mut entry = self.get(key)?;
+        entry.tags = tags.into_iter().collect();
+        entry.updated_at = Utc::now();
+        self.write(&entry)?;
+        Ok(entry)
+    }
+
+    /// Line diff of two versions rendered as YAML
+    pub fn diff(&self, key: &str, from: u32, to: u32) -> Result<Vec<DiffLine>, LibraryError> {
+        let entry = self.get(key)?;
+        let from = entry.version(from)?.workflow.render(WorkflowFormat::Yaml)?;
+        let to = entry.version(to)?.workflow.render(WorkflowFormat::Yaml)?;
+        Ok(diff_lines(&from, &to))
+    }
+
+    /// Take the library's lock file, so saves from other threads or
+    /// processes can't both pick the same version or name
+    fn lock(&self) -> Result<LibraryLock, LibraryError> {
+        let path = self.dir.join(LOCK_FILE);
+        let started = SystemTime::now();
+        loop {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(_) => return Ok(LibraryLock { path }),
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {}
+                Err(source) => return Err(LibraryError::Io { path, source }),
+            }
+            let age = fs::metadata(&path)
+                .and_then(|metadata| metadata.modified())
+                .ok()
+                .and_then(|modified| modified.elapsed().ok());
+            if age.is_some_and(|age| age > STALE_LOCK) {
+                eprintln!("⚠️ Removing stale library lock {}", path.display());
+                let _ = fs::remove_file(&path);
+                continue;
+            }
+            if started.elapsed().unwrap_or_default() > LOCK_TIMEOUT {
+                return Err(LibraryError::Locked(path));
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    fn find_by_name(&self, name: &str) -> Result<Option<LibraryEntry>, LibraryError> {
+        Ok(self.list()?.into_iter().find(|entry| entry.name == name))
+    }
+
+    fn new_id(&self) -> String {
+        loop {
+            let id = format!("{:012x}", rand::thread_rng().gen_range(0..1u64 << 48));
+            if !self.entry_path(&id).exists() {
+                return id;
+            }
+        }
+    }
+
+    fn entry_path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", id))
+    }
+
+    fn read(&self, path: &Path) -> Result<LibraryEntry, LibraryError> {
+        let text = fs::read_to_string(path).map_err(|source| LibraryError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let entry: LibraryEntry = serde_json::from_str(&text).map_err(|err| LibraryError::Corrupt {
+            path: path.to_path_buf(),
+            message: err.to_string(),
+        })?;
+        if entry.versions.is_empty() {
+            return Err(LibraryError::Corrupt {
+                path: path.to_path_buf(),
+                message: "no versions".to_string(),
+            });
+        }
+        Ok(entry)
+    }
+
+    /// Write through a temporary file so a crash never leaves half an entry
+    fn write(&self, entry: &LibraryEntry) -> Result<(), LibraryError> {
+        let path = self.entry_path(&entry.id);
+        let temp = path.with_extension("json.tmp");
+        let io_error = |source| LibraryError::Io {
+            path: path.clone(),
+            source,
+        };
+        let text = serde_json::to_string_pretty(entry).map_err(|err| io_error(err.into()))?;
+        fs::write(&temp, text).map_err(io_error)?;
+        fs::rename(&temp, &path).map_err(io_error)
+    }
+}
+
+/// Removes the lock file when dropped
+struct LibraryLock {
+    path: PathBuf,
+}
+
+impl Drop for LibraryLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Load the workflow `reference` points at: a workflow file if it is
+/// spelled as one (see `is_file_reference`), otherwise a task in the library
+/// at `library_dir()`
+pub fn resolve_workflow(reference: &str) -> Result<AutomationTask, String> {
+    if is_file_reference(reference) {
+        return load_workflow(reference).map_err(|err| err.to_string());
+    }
+    TaskLibrary::open(library_dir())
+        .and_then(|library| library.load_task(reference, None))
+        .map_err(|err| err.to_string())
+}
+
+/// A reference starting with `./`, `../` or `/`, or ending in a workflow
+/// extension, is a file; anything else is a library task, even if a file
+/// of that name happens to exist
+pub fn is_file_reference(reference: &str) -> bool {
+    let path = Path::new(reference);
+    reference.starts_with("./")
+        || reference.starts_with("../")
+        || reference.starts_with(".\\")
+        || reference.starts_with("..\\")
+        || path.is_absolute()
+        || WorkflowFormat::from_path(path).is_some()
 }
 
-pub fn load_task(name: String) -> Option<String> {
-    let file_name = format!("tasks/{}.txt", name);
-    match fs::read_to_string(file_name) {
-        Ok(content) => Some(content),
-        Err(_) => None,
+/// Ids are generated hex strings, so any other key must be a name
+fn is_id(key: &str) -> bool {
+    key.len() == 12 && key.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+fn same_workflow(a: &WorkflowDocument, b: &WorkflowDocument) -> bool {
+    match (serde_json::to_value(a), serde_json::to_value(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Longest-common-subsequence line diff; workflows are small enough for the
+/// quadratic table
+fn diff_lines(from: &str, to: &str) -> Vec<DiffLine> {
+    let a: Vec<&str> = from.lines().collect();
+    let b: Vec<&str> = to.lines().collect();
+    let mut lcs = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let line = |kind, text: &str| DiffLine {
+        kind,
+        text: text.to_string(),
+    };
+    let (mut i, mut j) = (0, 0);
+    let mut lines = Vec::new();
+    while i < a.len() && j < b.len() {
+        if a[i] == b[j] {
+            lines.push(line(DiffKind::Same, a[i]));
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            lines.push(line(DiffKind::Removed, a[i]));
+            i += 1;
+        } else {
+            lines.push(line(DiffKind::Added, b[j]));
+            j += 1;
+        }
+    }
+    lines.extend(a[i..].iter().map(|text| line(DiffKind::Removed, text)));
+    lines.extend(b[j..].iter().map(|text| line(DiffKind::Added, text)));
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task_model::TaskStep;
+    use std::sync::Arc;
+
+    fn temp_library(name: &str) -> (TaskLibrary, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("sae-library-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        (TaskLibrary::open(&dir).unwrap(), dir)
+    }
+
+    fn task(name: &str, url: &str) -> AutomationTask {
+        let mut task = AutomationTask::new();
+        task.name = name.to_string();
+        task.add_step(TaskStep::OpenWebsite(url.to_string()));
+        task
+    }
+
+    #[test]
+    fn saving_adds_a_version_only_when_the_task_changed() {
+        let (library, dir) = temp_library("versions");
+        let first = library.save(&task("login", "https://a.test"), Some("alice"), None).unwrap();
+        let unchanged = library.save(&task("login", "https://a.test"), Some("bob"), None).unwrap();
+        let second = library.save(&task("login", "https://b.test"), Some("bob"), Some("new host")).unwrap();
+
+        assert_eq!((first.id.as_str(), unchanged.versions.len()), (second.id.as_str(), 1));
+        assert_eq!(second.versions.iter().map(|v| v.version).collect::<Vec<_>>(), [1, 2]);
+        assert_eq!(second.author.as_deref(), Some("alice"), "the entry keeps its first author");
+        assert_eq!(second.latest().note.as_deref(), Some("new host"));
+
+        let steps = |version| library.load_task("login", version).unwrap().steps[0].action.clone();
+        assert_eq!(steps(Some(1)), TaskStep::OpenWebsite("https://a.test".to_string()));
+        assert_eq!(steps(None), TaskStep::OpenWebsite("https://b.test".to_string()));
+        assert!(matches!(
+            library.load_task("login", Some(3)),
+            Err(LibraryError::VersionNotFound { version: 3, .. })
+        ));
+        assert_eq!(library.get(&first.id).unwrap().name, "login");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rename_keeps_the_id_and_history() {
+        let (library, dir) = temp_library("rename");
+        library.save(&task("login", "https://a.test"), None, None).unwrap();
+        let entry = library.save(&task("login", "https://b.test"), None, None).unwrap();
+        library.save(&task("signup", "https://a.test"), None, None).unwrap();
+
+        assert!(matches!(library.rename("login", "signup"), Err(LibraryError::NameTaken(name)) if name == "signup"));
+        let renamed = library.rename("login", "sign-in").unwrap();
+        assert_eq!((renamed.id, renamed.versions.len()), (entry.id, 2));
+        assert!(matches!(library.get("login"), Err(LibraryError::NotFound(_))));
+        assert_eq!(library.load_task("sign-in", Some(1)).unwrap().name, "sign-in");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn diff_shows_changed_lines() {
+        let (library, dir) = temp_library("diff");
+        library.save(&task("login", "https://a.test"), None, None).unwrap();
+        library.save(&task("login", "https://b.test"), None, None).unwrap();
+
+        let changed: Vec<String> = library
+            .diff("login", 1, 2)
+            .unwrap()
+            .iter()
+            .filter(|line| line.kind != DiffKind::Same)
+            .map(|line| line.to_string())
+            .collect();
+        assert_eq!(changed, ["- - OpenWebsite: https://a.test", "+ - OpenWebsite: https://b.test"]);
+        assert!(library.diff("login", 2, 2).unwrap().iter().all(|line| line.kind == DiffKind::Same));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn concurrent_saves_get_distinct_versions() {
+        let (library, dir) = temp_library("concurrent");
+        let library = Arc::new(library);
+        let saves: Vec<_> = (0..8)
+            .map(|n| {
+                let library = Arc::clone(&library);
+                thread::spawn(move || library.save(&task("login", &format!("https://{}.test", n)), None, None))
+            })
+            .collect();
+        for save in saves {
+            save.join().unwrap().unwrap();
+        }
+
+        let entries = library.list().unwrap();
+        assert_eq!(entries.len(), 1, "one entry however the saves interleaved");
+        assert_eq!(entries[0].versions.iter().map(|v| v.version).collect::<Vec<_>>(), (1..=8).collect::<Vec<_>>());
+        assert!(!dir.join(LOCK_FILE).exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn only_spelled_out_paths_are_files() {
+        for reference in ["./login", "../shared/login", "/srv/login", "login.yaml", "flows/login.TOML"] {
+            assert!(is_file_reference(reference), "{}", reference);
+        }
+        for reference in ["login", "flows/login", "0a1b2c3d4e5f", "login.v2"] {
+            assert!(!is_file_reference(reference), "{}", reference);
+        }
     }
 }
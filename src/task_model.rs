@@ -1,36 +1,667 @@
+use crate::capabilities::{BrowserNeed, Requirements};
+use crate::scheduling::Priority;
+use crate::run_report::Artifact;
+use crate::target::Target;
+use crate::variables::{placeholders, root_name, VariableError, Variables};
+use serde::de::{self, DeserializeSeed, IntoDeserializer, MapAccess, Visitor};
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct AutomationTask {
-    pub steps: Vec<TaskStep>,
+    pub name: String,
+    pub description: Option<String>,
+    pub inputs: Vec<TaskInput>,
+    pub steps: Vec<Step>,
+    pub metadata: BTreeMap<String, String>, // Free-form key/value annotations (owner, ticket, ...)
+}
+
+/// An input the caller is expected to supply when running a task.
+/// Inputs without a default are required.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TaskInput {
+    pub name: String,
+    #[serde(default, rename = "type")]
+    pub kind: InputKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InputKind {
+    #[default]
+    String,
+    Number,
+    Boolean,
+}
+
+impl InputKind {
+    /// Convert a raw argument into a typed value, `None` if it doesn't fit
+    pub fn parse(&self, raw: &str) -> Option<serde_json::Value> {
+        match self {
+            InputKind::String => Some(serde_json::Value::String(raw.to_string())),
+            // Keep integers as integers so `{{count}}` renders as `3`, not `3.0`
+            InputKind::Number => match raw.trim().parse::<i64>() {
+                Ok(n) => Some(n.into()),
+                Err(_) => raw
+                    .trim()
+                    .parse::<f64>()
+                    .ok()
+                    .and_then(serde_json::Number::from_f64)
+                    .map(serde_json::Value::Number),
+            },
+            InputKind::Boolean => raw.trim().parse::<bool>().ok().map(serde_json::Value::Bool),
+        }
+    }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Subtask {
+    #[serde(default)]
     pub id: usize,
     pub description: String,
+    pub step: Step, // Associate a specific task step with the subtask
+    #[serde(default, skip_serializing_if = "Requirements::is_empty")]
+    pub requires: Requirements, // What the worker running it must offer
 }
 
-#[derive(Debug)]
-pub struct TaskResult {
+/// Subtasks that share one browser session. A job goes to a single worker,
+/// which runs its subtasks in order; different jobs run in parallel.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Job {
+    #[serde(default)]
     pub id: usize,
-    pub status: String,
+    pub description: String,
+    pub subtasks: Vec<Subtask>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub inputs: BTreeMap<String, serde_json::Value>, // Variables set before the first subtask
+    #[serde(default, skip_serializing_if = "Priority::is_normal")]
+    pub priority: Priority,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>, // Who submitted it, for fair sharing and quotas
+}
+
+impl Job {
+    pub fn new(description: impl Into<String>) -> Self {
+        Self {
+            description: description.into(),
+            ..Self::default()
+        }
+    }
+
+    /// Append a subtask, run after the ones already in the job
+    pub fn then(mut self, subtask: Subtask) -> Self {
+        self.subtasks.push(subtask);
+        self
+    }
+
+    pub fn priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn tenant(mut self, tenant: impl Into<String>) -> Self {
+        self.tenant = Some(tenant.into());
+        self
+    }
+
+    /// What one worker needs to run every subtask; `None` if they contradict
+    /// each other
+    pub fn requirements(&self) -> Option<Requirements> {
+        self.subtasks
+            .iter()
+            .try_fold(Requirements::default().browser(BrowserNeed::None), |merged, subtask| {
+                merged.merge(&subtask.requires)
+            })
+    }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskStatus {
+    Completed,
+    Failed,
+    Cancelled, // Stopped before or while it ran
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TaskResult {
+    pub id: usize,
+    #[serde(default)]
+    pub job_id: usize, // Job the subtask belongs to
+    pub worker_id: usize,
+    pub status: TaskStatus,
+    pub details: Option<String>, // Optional details or result data
+    #[serde(default)]
+    pub duration_ms: u64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub artifacts: Vec<Artifact>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub outputs: BTreeMap<String, serde_json::Value>, // Run variables written by extraction steps
+}
 
 impl AutomationTask {
-    // Add a constructor for creating tasks
+    /// Create a new automation task
     pub fn new() -> Self {
-        AutomationTask { steps: Vec::new() }
+        Self::default()
+    }
+
+    /// Add a step to the automation task
+    pub fn add_step(&mut self, step: impl Into<Step>) {
+        self.steps.push(step.into());
     }
 
-    // Add a method to append steps
-    pub fn add_step(&mut self, step: TaskStep) {
-        self.steps.push(step);
+    /// Make sure every placeholder used by the task (fallbacks included) is
+    /// bound in `vars`, before anything touches the browser
+    pub fn check_variables(&self, vars: &Variables) -> Result<(), Vec<VariableError>> {
+        let mut bound: HashSet<String> = vars.names().map(str::to_string).collect();
+        let mut unbound = Vec::new();
+        unbound_variables(&self.steps, "steps", &mut bound, &mut unbound);
+        if unbound.is_empty() {
+            Ok(())
+        } else {
+            Err(unbound.into_iter().map(|(_, err)| err).collect())
+        }
     }
 }
 
+/// Walk `steps` in execution order and collect every placeholder naming a
+/// variable that isn't bound at that point, together with the step's path
+/// (e.g. `steps[2].then[0]`).
+///
+/// Variables written by a step count as bound for the steps after it. Those
+/// written inside a nested body or a fallback stay local to it, matching the
+/// scoping the executor applies at run time.
+pub fn unbound_variables(
+    steps: &[Step],
+    path: &str,
+    bound: &mut HashSet<String>,
+    unbound: &mut Vec<(String, VariableError)>,
+) {
+    for (i, step) in steps.iter().enumerate() {
+        let step_path = format!("{}[{}]", path, i);
+        for template in step.action.templates() {
+            match placeholders(template) {
+                Ok(names) => unbound.extend(
+                    names
+                        .into_iter()
+                        .filter(|name| !bound.contains(root_name(name)))
+                        .map(|name| (step_path.clone(), VariableError::Unbound(name.to_string()))),
+                ),
+                Err(err) => unbound.push((step_path.clone(), err)),
+            }
+        }
+        if let TaskStep::ForEach { items: LoopSource::Variable(name), .. } = &step.action {
+            if !bound.contains(root_name(name)) {
+                unbound.push((step_path.clone(), VariableError::Unbound(name.clone())));
+            }
+        }
+        for (label, body, local) in step.action.bodies() {
+            let mut scope = bound.clone();
+            scope.extend(local.map(str::to_string));
+            unbound_variables(body, &format!("{}.{}", step_path, label), &mut scope, unbound);
+        }
+        if let ErrorPolicy::Fallback(fallback) = &step.on_error {
+            let fallback_path = format!("{}.on_error.fallback", step_path);
+            unbound_variables(fallback, &fallback_path, &mut bound.clone(), unbound);
+        }
+        if let Some(variable) = step.action.produces() {
+            bound.insert(variable.to_string());
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum TaskStep {
     OpenWebsite(String),         // Open a URL
-    ClickElement(String),        // Click an element by selector
-    FillForm(String, String),    // Fill a form field (selector, value)
-    WaitForElement(String),      // Wait for an element to appear
+    ClickElement(Target),        // Click an element
+    FillForm(Target, String),    // Fill a form field (target, value)
+    SelectOption(Target, String), // Choose a `<select>` option by value or visible text
+    SubmitForm(Target),          // Submit the form, or the form containing the field
+    WaitForElement(Target),      // Wait for an element to appear
     TakeScreenshot(String),      // Take a screenshot and save to path
+    CategorizeElements,          // New: Categorize all elements on the page
+    // Extraction steps store what they read in the run variable named last
+    ExtractText(Target, String),               // (target, variable)
+    ExtractAttribute(Target, String, String),  // (target, attribute, variable)
+    ExtractProperty(Target, String, String),   // (target, DOM property, variable)
+    ExtractTable(Target, String),              // (table target, variable) as a list of rows
+    EvaluateScript(String, String),            // (JavaScript with a `return`, variable)
+    // Control flow; nested steps see the enclosing variables, and variables
+    // they create go away when the block ends
+    If {
+        condition: Condition,
+        then: Vec<Step>,
+        #[serde(rename = "else", default, skip_serializing_if = "Vec::is_empty")]
+        otherwise: Vec<Step>,
+    },
+    ForEach {
+        items: LoopSource,
+        #[serde(rename = "as")]
+        item: String, // Variable holding the current item inside `steps`
+        steps: Vec<Step>,
+    },
+    While {
+        condition: Condition,
+        max_iterations: u32, // The step fails if the condition still holds after this many rounds
+        steps: Vec<Step>,
+    },
+    CallWorkflow {
+        workflow: String, // Path of a saved workflow document, or the name of a library task
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        args: BTreeMap<String, String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        into: Option<String>, // Variable receiving the called workflow's variables as an object
+    },
+}
+
+/// Test used by `If` and `While`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Condition {
+    ElementExists(Target),
+    TextContains { selector: Target, text: String },
+    Compare { left: String, op: CompareOp, right: String }, // both sides may use placeholders
+    Not(Box<Condition>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Contains,
+}
+
+/// Where `ForEach` gets its items from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LoopSource {
+    Variable(String), // A list variable, e.g. rows from `ExtractTable`
+    Csv(String),      // A CSV file with a header row; each row becomes an object
+}
+
+impl Condition {
+    pub fn templates(&self) -> Vec<&str> {
+        match self {
+            Condition::ElementExists(target) => target.templates(),
+            Condition::TextContains { selector, text } => {
+                let mut templates = selector.templates();
+                templates.push(text);
+                templates
+            }
+            Condition::Compare { left, right, .. } => vec![left, right],
+            Condition::Not(inner) => inner.templates(),
+        }
+    }
+}
+
+impl TaskStep {
+    /// Utility to create a subtask directly from a TaskStep
+    pub fn to_subtask(self, id: usize) -> Subtask {
+        Subtask {
+            id,
+            description: format!("{:?}", self),
+            step: self.into(),
+            requires: Requirements::default(),
+        }
+    }
+
+    /// Variant name, as written in workflow documents
+    pub fn name(&self) -> &'static str {
+        match self {
+            TaskStep::OpenWebsite(_) => "OpenWebsite",
+            TaskStep::ClickElement(_) => "ClickElement",
+            TaskStep::FillForm(_, _) => "FillForm",
+            TaskStep::SelectOption(_, _) => "SelectOption",
+            TaskStep::SubmitForm(_) => "SubmitForm",
+            TaskStep::WaitForElement(_) => "WaitForElement",
+            TaskStep::TakeScreenshot(_) => "TakeScreenshot",
+            TaskStep::CategorizeElements => "CategorizeElements",
+            TaskStep::ExtractText(_, _) => "ExtractText",
+            TaskStep::ExtractAttribute(_, _, _) => "ExtractAttribute",
+            TaskStep::ExtractProperty(_, _, _) => "ExtractProperty",
+            TaskStep::ExtractTable(_, _) => "ExtractTable",
+            TaskStep::EvaluateScript(_, _) => "EvaluateScript",
+            TaskStep::If { .. } => "If",
+            TaskStep::ForEach { .. } => "ForEach",
+            TaskStep::While { .. } => "While",
+            TaskStep::CallWorkflow { .. } => "CallWorkflow",
+        }
+    }
+
+    fn is_unit(&self) -> bool {
+        matches!(self, TaskStep::CategorizeElements)
+    }
+
+    /// Arguments that may contain `{{name}}` placeholders
+    pub fn templates(&self) -> Vec<&str> {
+        match self {
+            TaskStep::OpenWebsite(url) => vec![url],
+            TaskStep::ClickElement(target) | TaskStep::WaitForElement(target) | TaskStep::SubmitForm(target) => {
+                target.templates()
+            }
+            TaskStep::FillForm(target, value) | TaskStep::SelectOption(target, value) => {
+                let mut templates = target.templates();
+                templates.push(value);
+                templates
+            }
+            TaskStep::TakeScreenshot(file_path) => vec![file_path],
+            TaskStep::CategorizeElements => vec![],
+            TaskStep::ExtractText(target, _)
+            | TaskStep::ExtractTable(target, _)
+            | TaskStep::ExtractAttribute(target, _, _)
+            | TaskStep::ExtractProperty(target, _, _) => target.templates(),
+            TaskStep::EvaluateScript(script, _) => vec![script],
+            TaskStep::If { condition, .. } | TaskStep::While { condition, .. } => condition.templates(),
+            TaskStep::ForEach { items, .. } => match items {
+                LoopSource::Variable(_) => vec![],
+                LoopSource::Csv(path) => vec![path],
+            },
+            TaskStep::CallWorkflow { workflow, args, .. } => std::iter::once(workflow.as_str())
+                .chain(args.values().map(String::as_str))
+                .collect(),
+        }
+    }
+
+    /// Nested step lists as `(label, steps, variable local to them)`
+    pub fn bodies(&self) -> Vec<(&'static str, &[Step], Option<&str>)> {
+        match self {
+            TaskStep::If { then, otherwise, .. } => vec![("then", then, None), ("else", otherwise, None)],
+            TaskStep::ForEach { item, steps, .. } => vec![("steps", steps, Some(item))],
+            TaskStep::While { steps, .. } => vec![("steps", steps, None)],
+            _ => vec![],
+        }
+    }
+
+    /// Mutable counterpart of `bodies`, without labels
+    pub fn bodies_mut(&mut self) -> Vec<&mut Vec<Step>> {
+        match self {
+            TaskStep::If { then, otherwise, .. } => vec![then, otherwise],
+            TaskStep::ForEach { steps, .. } | TaskStep::While { steps, .. } => vec![steps],
+            _ => vec![],
+        }
+    }
+
+    /// Run variable this step writes, if any
+    pub fn produces(&self) -> Option<&str> {
+        match self {
+            TaskStep::ExtractText(_, variable)
+            | TaskStep::ExtractTable(_, variable)
+            | TaskStep::EvaluateScript(_, variable)
+            | TaskStep::ExtractAttribute(_, _, variable)
+            | TaskStep::ExtractProperty(_, _, variable) => Some(variable),
+            TaskStep::CallWorkflow { into, .. } => into.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Element this step acts on, if any
+    pub fn target_mut(&mut self) -> Option<&mut Target> {
+        match self {
+            TaskStep::ClickElement(target)
+            | TaskStep::WaitForElement(target)
+            | TaskStep::FillForm(target, _)
+            | TaskStep::SelectOption(target, _)
+            | TaskStep::SubmitForm(target)
+            | TaskStep::ExtractText(target, _)
+            | TaskStep::ExtractTable(target, _)
+            | TaskStep::ExtractAttribute(target, _, _)
+            | TaskStep::ExtractProperty(target, _, _) => Some(target),
+            _ => None,
+        }
+    }
+
+    /// Copy of the step with every placeholder filled in from `vars`.
+    /// Control-flow steps are returned unchanged: their conditions and bodies
+    /// are rendered as they run, when loop variables are bound.
+    pub fn render(&self, vars: &Variables) -> Result<TaskStep, VariableError> {
+        Ok(match self {
+            TaskStep::OpenWebsite(url) => TaskStep::OpenWebsite(vars.render(url)?),
+            TaskStep::ClickElement(target) => TaskStep::ClickElement(target.render(vars)?),
+            TaskStep::FillForm(target, value) => {
+                TaskStep::FillForm(target.render(vars)?, vars.render(value)?)
+            }
+            TaskStep::SelectOption(target, value) => {
+                TaskStep::SelectOption(target.render(vars)?, vars.render(value)?)
+            }
+            TaskStep::SubmitForm(target) => TaskStep::SubmitForm(target.render(vars)?),
+            TaskStep::WaitForElement(target) => TaskStep::WaitForElement(target.render(vars)?),
+            TaskStep::TakeScreenshot(file_path) => TaskStep::TakeScreenshot(vars.render(file_path)?),
+            TaskStep::CategorizeElements => TaskStep::CategorizeElements,
+            TaskStep::ExtractText(target, variable) => {
+                TaskStep::ExtractText(target.render(vars)?, variable.clone())
+            }
+            TaskStep::ExtractAttribute(target, attribute, variable) => {
+                TaskStep::ExtractAttribute(target.render(vars)?, attribute.clone(), variable.clone())
+            }
+            TaskStep::ExtractProperty(target, property, variable) => {
+                TaskStep::ExtractProperty(target.render(vars)?, property.clone(), variable.clone())
+            }
+            TaskStep::ExtractTable(target, variable) => {
+                TaskStep::ExtractTable(target.render(vars)?, variable.clone())
+            }
+            TaskStep::EvaluateScript(script, variable) => {
+                TaskStep::EvaluateScript(vars.render(script)?, variable.clone())
+            }
+            TaskStep::If { .. }
+            | TaskStep::ForEach { .. }
+            | TaskStep::While { .. }
+            | TaskStep::CallWorkflow { .. } => self.clone(),
+        })
+    }
+}
+
+/// What to do when a step fails
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorPolicy {
+    /// Stop the task; nothing after this step runs
+    #[default]
+    Abort,
+    /// Record the failure and move on to the next step
+    Continue,
+    /// Re-run the step up to `attempts` more times, doubling the delay each time,
+    /// then abort
+    Retry {
+        attempts: u32,
+        #[serde(default = "default_backoff_ms")]
+        backoff_ms: u64,
+    },
+    /// Run these steps instead, then carry on with the task
+    Fallback(Vec<Step>),
+}
+
+fn default_backoff_ms() -> u64 {
+    500
+}
+
+/// A `TaskStep` together with its failure policy.
+///
+/// Steps using the default policy serialize exactly like a bare `TaskStep`
+/// (`OpenWebsite: https://...`); otherwise `on_error` sits next to the action:
+///
+/// ```yaml
+/// - ClickElement: "#accept-cookies"
+///   on_error: continue
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+    pub action: TaskStep,
+    pub on_error: ErrorPolicy,
+}
+
+impl Step {
+    pub fn new(action: TaskStep) -> Self {
+        Step {
+            action,
+            on_error: ErrorPolicy::default(),
+        }
+    }
+
+    pub fn on_error(mut self, policy: ErrorPolicy) -> Self {
+        self.on_error = policy;
+        self
+    }
+}
+
+impl From<TaskStep> for Step {
+    fn from(action: TaskStep) -> Self {
+        Step::new(action)
+    }
+}
+
+impl Serialize for Step {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.on_error == ErrorPolicy::default() {
+            return self.action.serialize(serializer);
+        }
+        if self.action.is_unit() {
+            // Unit variants have no payload to flatten, so write `Name: {}`
+            let mut map = serializer.serialize_map(Some(2))?;
+            map.serialize_entry(self.action.name(), &BTreeMap::<String, String>::new())?;
+            map.serialize_entry("on_error", &self.on_error)?;
+            return map.end();
+        }
+
+        #[derive(Serialize)]
+        struct WithPolicy<'a> {
+            #[serde(flatten)]
+            action: &'a TaskStep,
+            on_error: &'a ErrorPolicy,
+        }
+        WithPolicy {
+            action: &self.action,
+            on_error: &self.on_error,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Step {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(StepVisitor)
+    }
+}
+
+struct StepVisitor;
+
+impl<'de> Visitor<'de> for StepVisitor {
+    type Value = Step;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a task step such as `OpenWebsite: <url>`")
+    }
+
+    fn visit_str<E: de::Error>(self, name: &str) -> Result<Step, E> {
+        TaskStep::deserialize(name.into_deserializer()).map(Step::from)
+    }
+
+    // Read the action straight from the map entry rather than buffering the
+    // whole step, so errors keep pointing at the offending line
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Step, A::Error> {
+        let mut action = None;
+        let mut on_error = None;
+        while let Some(key) = map.next_key::<String>()? {
+            if key == "on_error" {
+                if on_error.is_some() {
+                    return Err(de::Error::duplicate_field("on_error"));
+                }
+                on_error = Some(map.next_value()?);
+            } else if action.is_some() {
+                return Err(de::Error::custom(format!(
+                    "unexpected key `{}`, a step has one action and an optional `on_error`",
+                    key
+                )));
+            } else {
+                action = Some(TaskStep::deserialize(ActionEntry {
+                    name: key,
+                    map: &mut map,
+                })?);
+            }
+        }
+
+        Ok(Step {
+            action: action.ok_or_else(|| de::Error::custom("step is missing an action"))?,
+            on_error: on_error.unwrap_or_default(),
+        })
+    }
+}
+
+/// Presents one `Name: payload` map entry to serde as an externally tagged enum
+struct ActionEntry<'a, A> {
+    name: String,
+    map: &'a mut A,
+}
+
+impl<'de, A: MapAccess<'de>> Deserializer<'de> for ActionEntry<'_, A> {
+    type Error = A::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, A::Error> {
+        visitor.visit_enum(self)
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+impl<'de, A: MapAccess<'de>> de::EnumAccess<'de> for ActionEntry<'_, A> {
+    type Error = A::Error;
+    type Variant = Self;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self), A::Error> {
+        let variant = seed.deserialize(self.name.as_str().into_deserializer())?;
+        Ok((variant, self))
+    }
+}
+
+impl<'de, A: MapAccess<'de>> de::VariantAccess<'de> for ActionEntry<'_, A> {
+    type Error = A::Error;
+
+    fn unit_variant(self) -> Result<(), A::Error> {
+        // Accept `Name: null` as well as `Name: {}`
+        self.map.next_value::<de::IgnoredAny>().map(|_| ())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, A::Error> {
+        self.map.next_value_seed(seed)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, A::Error> {
+        struct TupleSeed<V>(usize, V);
+        impl<'de, V: Visitor<'de>> DeserializeSeed<'de> for TupleSeed<V> {
+            type Value = V::Value;
+            fn deserialize<D: Deserializer<'de>>(self, d: D) -> Result<V::Value, D::Error> {
+                d.deserialize_tuple(self.0, self.1)
+            }
+        }
+        self.map.next_value_seed(TupleSeed(len, visitor))
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, A::Error> {
+        struct StructSeed<V>(&'static [&'static str], V);
+        impl<'de, V: Visitor<'de>> DeserializeSeed<'de> for StructSeed<V> {
+            type Value = V::Value;
+            fn deserialize<D: Deserializer<'de>>(self, d: D) -> Result<V::Value, D::Error> {
+                d.deserialize_struct("", self.0, self.1)
+            }
+        }
+        self.map.next_value_seed(StructSeed(fields, visitor))
+    }
 }
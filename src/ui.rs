@@ -1,4 +1,4 @@
-use crate::{automation_engine, task_library, rewards};
+use crate::{automation_engine, rewards};
 use std::io;
 
 pub async fn start_ui() {
@@ -0,0 +1,261 @@
+use crate::task_model::{InputKind, TaskInput};
+use serde_json::Value;
+use std::collections::HashMap;
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum VariableError {
+    #[error("Variable '{0}' is used but never bound")]
+    Unbound(String),
+    #[error("Missing value for required input '{0}'")]
+    MissingInput(String),
+    #[error("Input '{name}' expects a {kind:?}, got '{value}'")]
+    InvalidInput {
+        name: String,
+        kind: InputKind,
+        value: String,
+    },
+    #[error("Unknown input '{0}'")]
+    UnknownInput(String),
+    #[error("Unterminated placeholder in '{0}'")]
+    Unterminated(String),
+}
+
+/// Run-scoped variable store used to fill `{{name}}` placeholders.
+///
+/// Control-flow blocks push a scope: variables defined inside it disappear
+/// when it is popped, while assignments to variables that already exist in an
+/// outer scope update them in place.
+#[derive(Debug, Clone)]
+pub struct Variables {
+    scopes: Vec<HashMap<String, Value>>,
+}
+
+impl Default for Variables {
+    fn default() -> Self {
+        Variables {
+            scopes: vec![HashMap::new()],
+        }
+    }
+}
+
+impl Variables {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bind the task's declared inputs from caller-supplied string arguments,
+    /// falling back to defaults and converting to each input's type
+    pub fn from_inputs(
+        inputs: &[TaskInput],
+        args: &HashMap<String, String>,
+    ) -> Result<Self, Vec<VariableError>> {
+        let mut vars = Variables::new();
+        let mut errors = Vec::new();
+
+        for name in args.keys() {
+            if !inputs.iter().any(|input| &input.name == name) {
+                errors.push(VariableError::UnknownInput(name.clone()));
+            }
+        }
+        for input in inputs {
+            let Some(raw) = args.get(&input.name).or(input.default.as_ref()) else {
+                errors.push(VariableError::MissingInput(input.name.clone()));
+                continue;
+            };
+            match input.kind.parse(raw) {
+                Some(value) => vars.set(&input.name, value),
+                None => errors.push(VariableError::InvalidInput {
+                    name: input.name.clone(),
+                    kind: input.kind,
+                    value: raw.clone(),
+                }),
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(vars)
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Look up a variable; `row.email` or `rows.0` reach into objects and lists
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        let mut parts = name.split('.');
+        let root = parts.next()?;
+        let mut value = self.scopes.iter().rev().find_map(|scope| scope.get(root))?;
+        for part in parts {
+            value = match value {
+                Value::Object(map) => map.get(part)?,
+                Value::Array(items) => items.get(part.parse::<usize>().ok()?)?,
+                _ => return None,
+            };
+        }
+        Some(value)
+    }
+
+    /// Assign to the innermost scope that already has `name`, or define it in
+    /// the current scope
+    pub fn set(&mut self, name: &str, value: Value) {
+        match self.scopes.iter_mut().rev().find(|scope| scope.contains_key(name)) {
+            Some(scope) => {
+                scope.insert(name.to_string(), value);
+            }
+            None => self.define(name, value),
+        }
+    }
+
+    /// Define `name` in the current scope, shadowing any outer variable
+    pub fn define(&mut self, name: &str, value: Value) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), value);
+        }
+    }
+
+    pub fn push_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    pub fn pop_scope(&mut self) {
+        if self.scopes.len() > 1 {
+            self.scopes.pop();
+        }
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.scopes.iter().flat_map(|scope| scope.keys().map(String::as_str))
+    }
+
+    /// Visible variables, inner scopes shadowing outer ones
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Value)> {
+        let mut visible = HashMap::new();
+        for scope in &self.scopes {
+            for (name, value) in scope {
+                visible.insert(name.as_str(), value);
+            }
+        }
+        visible.into_iter()
+    }
+
+    /// Replace every `{{name}}` in `template` with the bound value
+    pub fn render(&self, template: &str) -> Result<String, VariableError> {
+        let mut rendered = String::with_capacity(template.len());
+        let mut rest = template;
+        while let Some(start) = rest.find("{{") {
+            rendered.push_str(&rest[..start]);
+            let after = &rest[start + 2..];
+            let end = after
+                .find("}}")
+                .ok_or_else(|| VariableError::Unterminated(template.to_string()))?;
+            let name = after[..end].trim();
+            match self.get(name) {
+                Some(Value::String(s)) => rendered.push_str(s),
+                Some(value) => rendered.push_str(&value.to_string()),
+                None => return Err(VariableError::Unbound(name.to_string())),
+            }
+            rest = &after[end + 2..];
+        }
+        rendered.push_str(rest);
+        Ok(rendered)
+    }
+}
+
+/// The variable a possibly dotted reference starts from (`row` for `row.email`)
+pub fn root_name(name: &str) -> &str {
+    name.split('.').next().unwrap_or(name)
+}
+
+/// Names referenced by `{{name}}` placeholders in `template`
+pub fn placeholders(template: &str) -> Result<Vec<&str>, VariableError> {
+    let mut names = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let after = &rest[start + 2..];
+        let end = after
+            .find("}}")
+            .ok_or_else(|| VariableError::Unterminated(template.to_string()))?;
+        names.push(after[..end].trim());
+        rest = &after[end + 2..];
+    }
+    Ok(names)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn input(name: &str, kind: InputKind, default: Option<&str>) -> TaskInput {
+        TaskInput {
+            name: name.to_string(),
+            kind,
+            description: None,
+            default: default.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn renders_placeholders() {
+        let mut vars = Variables::new();
+        vars.set("user", json!("ada"));
+        vars.set("count", json!(3));
+        vars.set("row", json!({"email": "ada@example.com", "tags": ["a", "b"]}));
+        assert_eq!(vars.render("Hi {{user}}, {{ count }} new").unwrap(), "Hi ada, 3 new");
+        assert_eq!(vars.render("{{row.email}} {{row.tags.1}}").unwrap(), "ada@example.com b");
+        assert_eq!(vars.render("{{row.tags}}").unwrap(), r#"["a","b"]"#);
+        assert_eq!(vars.render("no placeholders").unwrap(), "no placeholders");
+    }
+
+    #[test]
+    fn render_rejects_unbound_and_unterminated_placeholders() {
+        let vars = Variables::new();
+        assert_eq!(vars.render("{{missing}}"), Err(VariableError::Unbound("missing".to_string())));
+        assert_eq!(vars.render("{{oops"), Err(VariableError::Unterminated("{{oops".to_string())));
+    }
+
+    #[test]
+    fn inner_scopes_shadow_and_update_outer_ones() {
+        let mut vars = Variables::new();
+        vars.set("total", json!(0));
+        vars.push_scope();
+        vars.define("item", json!("x"));
+        vars.set("total", json!(1));
+        assert_eq!(vars.render("{{item}}{{total}}").unwrap(), "x1");
+        vars.pop_scope();
+        assert!(!vars.contains("item"));
+        assert_eq!(vars.get("total"), Some(&json!(1)));
+    }
+
+    #[test]
+    fn binds_typed_inputs() {
+        let inputs = [
+            input("name", InputKind::String, None),
+            input("count", InputKind::Number, Some("2")),
+            input("dry_run", InputKind::Boolean, Some("false")),
+        ];
+        let args = HashMap::from([("name".to_string(), "ada".to_string()), ("count".to_string(), "5".to_string())]);
+        let vars = Variables::from_inputs(&inputs, &args).unwrap();
+        assert_eq!(vars.render("{{name}} {{count}} {{dry_run}}").unwrap(), "ada 5 false");
+    }
+
+    #[test]
+    fn reports_every_input_problem() {
+        let inputs = [input("name", InputKind::String, None), input("count", InputKind::Number, None)];
+        let args = HashMap::from([("count".to_string(), "many".to_string()), ("extra".to_string(), "1".to_string())]);
+        let errors = Variables::from_inputs(&inputs, &args).unwrap_err();
+        assert!(errors.contains(&VariableError::UnknownInput("extra".to_string())));
+        assert!(errors.contains(&VariableError::MissingInput("name".to_string())));
+        assert!(errors.iter().any(|err| matches!(err, VariableError::InvalidInput { name, .. } if name == "count")));
+    }
+
+    #[test]
+    fn lists_placeholders() {
+        assert_eq!(placeholders("{{a}} and {{ row.b }}").unwrap(), ["a", "row.b"]);
+        assert_eq!(root_name("row.b"), "row");
+    }
+}
@@ -1,81 +1,382 @@
-use fantoccini::{Client, Locator};
+use crate::browser_config::BrowserConfig;
+use crate::observation_layer::interaction_log::{logged, ActionKind};
+use crate::target::Target;
+use fantoccini::elements::Element;
+use fantoccini::{Client, ClientBuilder, Locator};
 use std::error::Error;
 use std::fs;
 use anyhow::{Result, anyhow};
 use thiserror::Error;
 use fantoccini::error::CmdError;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use webdriver::error::{ErrorStatus, WebDriverError};
 
 #[derive(Error, Debug)]
 pub enum WebInteractionError {
     #[error("Element did not appear in time: {0}")]
     ElementNotFound(String),
+    #[error("{0}")]
+    Rejected(String), // The element was there but refused the action, e.g. a missing option
     #[error("Fantoccini error: {0}")]
     FantocciniError(#[from] CmdError),
 }
 
-pub async fn start_browser() -> Result<Client, fantoccini::error::NewSessionError> {
-    println!("Starting browser for automation...");
-    let client = Client::new("http://localhost:4444").await?;
-    Ok(client)
+pub async fn start_browser(config: &BrowserConfig) -> Result<Client, fantoccini::error::NewSessionError> {
+    println!("Starting {} for automation via {}...", config.browser, config.webdriver_url);
+    logged(ActionKind::StartBrowser, &config.browser, None, async {
+        ClientBuilder::native()
+            .capabilities(config.capabilities())
+            .connect(&config.webdriver_url)
+            .await
+    })
+    .await
+}
+
+/// How long closing the browser may take, e.g. behind a page load that a
+/// cancelled step left running
+const CLOSE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// End the browser session, giving up with `WaitTimeout` after
+/// `CLOSE_TIMEOUT` instead of waiting out a command nobody needs anymore
+pub async fn close_browser(mut client: Client) -> Result<(), CmdError> {
+    tokio::time::timeout(CLOSE_TIMEOUT, client.close())
+        .await
+        .unwrap_or(Err(CmdError::WaitTimeout))
 }
 
 pub async fn open_website(client: &mut Client, url: &str) -> Result<(), fantoccini::error::CmdError> {
     println!("🌐 Navigating to: {}", url);
-    client.goto(url).await
+    logged(ActionKind::Navigate, url, None, client.goto(url)).await
+}
+
+/// Find the element `target` points at, honouring `within` and `nth`
+pub fn locate<'a>(
+    client: &'a mut Client,
+    target: &'a Target,
+) -> Pin<Box<dyn Future<Output = Result<Element, CmdError>> + Send + 'a>> {
+    Box::pin(async move {
+        let query = target.by.query(target.within.is_some());
+        let mut matches = match &target.within {
+            Some(parent) => locate(client, parent).await?.find_all(query.locator()).await?,
+            None => client.find_all(query.locator()).await?,
+        };
+        let index = target.nth.unwrap_or(0);
+        if index < matches.len() {
+            Ok(matches.swap_remove(index))
+        } else {
+            Err(CmdError::NoSuchElement(WebDriverError::new(
+                ErrorStatus::NoSuchElement,
+                format!("{} matched {} element(s)", target, matches.len()),
+            )))
+        }
+    })
 }
 
-pub async fn click_element(client: &mut Client, selector: &str) -> Result<(), fantoccini::error::CmdError> {
-    println!("🖱️ Clicking element: {}", selector);
-    let element = client.find(Locator::Css(selector)).await?;
-    element.click().await.map(|_| ()) // Convert the Result<Client, CmdError> to Result<(), CmdError>
+pub async fn click_element(client: &mut Client, target: &Target) -> Result<(), fantoccini::error::CmdError> {
+    println!("🖱️ Clicking element: {}", target);
+    logged(ActionKind::Click, target, None, async {
+        let element = locate(client, target).await?;
+        element.click().await.map(|_| ()) // Convert the Result<Client, CmdError> to Result<(), CmdError>
+    })
+    .await
 }
 
 
-pub async fn fill_form_field(client: &mut Client, selector: &str, value: &str) -> Result<(), fantoccini::error::CmdError> {
-    println!("⌨️ Filling form field: {} with {}", selector, value);
-    let mut element = client.find(Locator::Css(selector)).await?; // Declare as mutable
-    element.send_keys(value).await
+pub async fn fill_form_field(client: &mut Client, target: &Target, value: &str) -> Result<(), fantoccini::error::CmdError> {
+    println!("⌨️ Filling form field: {} with {}", target, value);
+    logged(ActionKind::Fill, target, None, async {
+        let mut element = locate(client, target).await?; // Declare as mutable
+        element.send_keys(value).await
+    })
+    .await
+}
+
+
+/// Pick the option whose value, or else visible text, is `value`
+pub async fn select_option(client: &mut Client, target: &Target, value: &str) -> Result<(), WebInteractionError> {
+    println!("🔽 Selecting '{}' in: {}", value, target);
+    logged(ActionKind::Select, target, None, async {
+        let element = serde_json::to_value(locate(client, target).await?).map_err(CmdError::from)?;
+        let selected = client
+            .execute(
+                "const [select, wanted] = arguments;
+                const options = Array.from(select.options || []);
+                const option = options.find(o => o.value === wanted) || options.find(o => o.text.trim() === wanted);
+                if (!option) return false;
+                select.value = option.value;
+                select.dispatchEvent(new Event('input', { bubbles: true }));
+                select.dispatchEvent(new Event('change', { bubbles: true }));
+                return true;",
+                vec![element, serde_json::Value::String(value.to_string())],
+            )
+            .await?;
+        match selected.as_bool() {
+            Some(true) => Ok(()),
+            _ => Err(WebInteractionError::Rejected(format!("{} has no option '{}'", target, value))),
+        }
+    })
+    .await
+}
+
+/// Submit `target` if it is a form, otherwise the form it belongs to
+pub async fn submit_form(client: &mut Client, target: &Target) -> Result<(), WebInteractionError> {
+    println!("📨 Submitting form: {}", target);
+    logged(ActionKind::Submit, target, None, async {
+        let element = serde_json::to_value(locate(client, target).await?).map_err(CmdError::from)?;
+        let submitted = client
+            .execute(
+                "const el = arguments[0];
+                const form = el.tagName === 'FORM' ? el : (el.form || el.closest('form'));
+                if (!form) return false;
+                if (form.requestSubmit) form.requestSubmit(); else form.submit();
+                return true;",
+                vec![element],
+            )
+            .await?;
+        match submitted.as_bool() {
+            Some(true) => Ok(()),
+            _ => Err(WebInteractionError::Rejected(format!("{} is not in a form", target))),
+        }
+    })
+    .await
+}
+
+pub async fn extract_text(client: &mut Client, target: &Target) -> Result<String, fantoccini::error::CmdError> {
+    println!("📝 Extracting text from: {}", target);
+    logged(ActionKind::Extract, target, None, async {
+        let mut element = locate(client, target).await?;
+        element.text().await
+    })
+    .await
+}
+
+pub async fn extract_attribute(
+    client: &mut Client,
+    target: &Target,
+    attribute: &str,
+) -> Result<Option<String>, fantoccini::error::CmdError> {
+    println!("📝 Extracting attribute '{}' from: {}", attribute, target);
+    logged(ActionKind::Extract, target, None, async {
+        let mut element = locate(client, target).await?;
+        element.attr(attribute).await
+    })
+    .await
+}
+
+pub async fn extract_property(
+    client: &mut Client,
+    target: &Target,
+    property: &str,
+) -> Result<Option<String>, fantoccini::error::CmdError> {
+    println!("📝 Extracting property '{}' from: {}", property, target);
+    logged(ActionKind::Extract, target, None, async {
+        let mut element = locate(client, target).await?;
+        element.prop(property).await
+    })
+    .await
+}
+
+/// Read a `<table>` into rows of cell text. When the table has a header row
+/// each row becomes an object keyed by header, otherwise an array of cells.
+pub async fn extract_table(client: &mut Client, target: &Target) -> Result<serde_json::Value, fantoccini::error::CmdError> {
+    println!("📝 Extracting table rows from: {}", target);
+    logged(ActionKind::Extract, target, None, async {
+        let element = locate(client, target).await?;
+        let element = serde_json::to_value(element)?;
+        client
+            .execute(
+                "const table = arguments[0];
+                const rows = Array.from(table.rows).map(r => Array.from(r.cells).map(c => c.innerText.trim()));
+                const hasHeader = table.rows.length > 0 &&
+                    ((table.tHead && table.tHead.rows.length > 0) ||
+                     Array.from(table.rows[0].cells).every(c => c.tagName === 'TH'));
+                if (!hasHeader) return rows;
+                const header = rows.shift();
+                return rows.map(r => Object.fromEntries(header.map((h, i) => [h || String(i), r[i] ?? ''])));",
+                vec![element],
+            )
+            .await
+    })
+    .await
 }
 
+pub async fn evaluate_script(client: &mut Client, script: &str) -> Result<serde_json::Value, fantoccini::error::CmdError> {
+    println!("📜 Evaluating script: {}", script);
+    logged(ActionKind::Script, script, None, client.execute(script, vec![])).await
+}
 
 pub async fn take_screenshot(client: &mut Client, file_path: &str) -> Result<(), Box<dyn Error>> {
     println!("📸 Taking screenshot and saving to: {}", file_path);
-    let png_data = client.screenshot().await?;
-    fs::write(file_path, png_data)?;
-    Ok(())
+    logged(ActionKind::Screenshot, file_path, Some(file_path), async {
+        let png_data = client.screenshot().await?;
+        fs::write(file_path, png_data)?;
+        Ok::<(), Box<dyn Error>>(())
+    })
+    .await
 }
 
 pub async fn wait_for_element(
     client: &mut Client,
-    selector: &str,
+    target: &Target,
 ) -> Result<()> {
-    println!("⏳ Waiting for element to appear: {}", selector);
-    for _ in 0..30 {
-        if client.find(Locator::Css(selector)).await.is_ok() {
-            println!("✅ Element appeared: {}", selector);
-            return Ok(());
+    println!("⏳ Waiting for element to appear: {}", target);
+    logged(ActionKind::Wait, target, None, async {
+        for _ in 0..30 {
+            if locate(client, target).await.is_ok() {
+                println!("✅ Element appeared: {}", target);
+                return Ok(());
+            }
+            tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
         }
-        tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-    }
-    Err(anyhow!("Element '{}' did not appear in time", selector))
+        Err(anyhow!("Element '{}' did not appear in time", target))
+    })
+    .await
 }
 
 pub async fn wait_for_element_with_timeout(
     client: &mut Client,
-    selector: &str,
+    target: &Target,
     timeout_secs: u64,
 ) -> Result<()> {
-    println!("⏳ Waiting for element to appear: {}", selector);
-    for _ in 0..timeout_secs {
-        if client.find(Locator::Css(selector)).await.is_ok() {
-            println!("✅ Element appeared: {}", selector);
-            return Ok(());
+    println!("⏳ Waiting for element to appear: {}", target);
+    logged(ActionKind::Wait, target, None, async {
+        for _ in 0..timeout_secs {
+            if locate(client, target).await.is_ok() {
+                println!("✅ Element appeared: {}", target);
+                return Ok(());
+            }
+            tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+        }
+        Err(anyhow!(
+            "Element '{}' did not appear in {} seconds",
+            target,
+            timeout_secs
+        ))
+    })
+    .await
+}
+
+
+pub async fn universal_locator(client: &mut Client) -> Result<HashMap<String, Vec<HashMap<String, String>>>, fantoccini::error::CmdError> {
+    logged(ActionKind::Categorize, "all elements", None, categorize_elements(client)).await
+}
+
+async fn categorize_elements(client: &mut Client) -> Result<HashMap<String, Vec<HashMap<String, String>>>, fantoccini::error::CmdError> {
+    println!("🔍 Locating and categorizing all elements...");
+
+    let categories = vec![
+        ("Buttons", "button"),
+        ("Inputs", "input"),
+        ("Text Areas", "textarea"),
+        ("Links", "a"),
+        ("Divs", "div"),
+        ("Images", "img"),
+        ("Headers", "h1, h2, h3, h4, h5, h6"),
+        ("Paragraphs", "p"),
+        ("Spans", "span"),
+    ];
+
+    let mut categorized_elements: HashMap<String, Vec<HashMap<String, String>>> = HashMap::new();
+
+    for (category, selector) in categories {
+        println!("🔍 Searching for {} using selector '{}'", category, selector);
+        // sleep(Duration::from_secs(2)); // Pause for observation
+
+        let mut elements_data = vec![];
+
+        match client.find_all(Locator::Css(selector)).await {
+            Ok(elements) => {
+                println!("🔍 Found {} elements for category '{}'", elements.len(), category);
+                for mut element in elements {
+                    // sleep(Duration::from_secs(1)); // Pause before processing each element
+                    let mut element_data = HashMap::new();
+
+                    if let Ok(Some(id)) = element.attr("id").await {
+                        element_data.insert("id".to_string(), id);
+                    } else {
+                        element_data.insert("id".to_string(), "N/A".to_string());
+                    }
+
+                    if let Ok(Some(class)) = element.attr("class").await {
+                        element_data.insert("class".to_string(), class);
+                    } else {
+                        element_data.insert("class".to_string(), "N/A".to_string());
+                    }
+
+                    if let Ok(Some(name)) = element.attr("name").await {
+                        element_data.insert("name".to_string(), name);
+                    } else {
+                        element_data.insert("name".to_string(), "N/A".to_string());
+                    }
+
+                    // Execute JavaScript for additional properties
+                    if let Ok(tag_name) = client
+                        .execute(
+                            &format!(
+                                "return (function() {{
+                                    let el = document.querySelector('{}');
+                                    return el ? el.tagName : null;
+                                }})();",
+                                selector
+                            ),
+                            vec![],
+                        )
+                        .await
+                    {
+                        if let Some(tag_name_str) = tag_name.as_str() {
+                            element_data.insert("tag".to_string(), tag_name_str.to_string());
+                        }
+                    }
+
+                    if let Ok(is_displayed) = client
+                        .execute(
+                            &format!(
+                                "return (function() {{
+                                    let el = document.querySelector('{}');
+                                    return el ? (el.offsetWidth > 0 && el.offsetHeight > 0) : false;
+                                }})();",
+                                selector
+                            ),
+                            vec![],
+                        )
+                        .await
+                    {
+                        if let Some(displayed) = is_displayed.as_bool() {
+                            element_data.insert("visible".to_string(), displayed.to_string());
+                        }
+                    }
+
+                    if let Ok(is_enabled) = client
+                        .execute(
+                            &format!(
+                                "return (function() {{
+                                    let el = document.querySelector('{}');
+                                    return el ? !el.disabled : false;
+                                }})();",
+                                selector
+                            ),
+                            vec![],
+                        )
+                        .await
+                    {
+                        if let Some(enabled) = is_enabled.as_bool() {
+                            element_data.insert("enabled".to_string(), enabled.to_string());
+                        }
+                    }
+
+                    elements_data.push(element_data);
+                }
+                categorized_elements.insert(category.to_string(), elements_data);
+            }
+            Err(e) => {
+                println!("❌ Failed to locate elements for category '{}': {:?}", category, e);
+            }
         }
-        tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
     }
-    Err(anyhow!(
-        "Element '{}' did not appear in {} seconds",
-        selector,
-        timeout_secs
-    ))
-}
\ No newline at end of file
+
+    println!("✅ Finished locating and categorizing elements.");
+    Ok(categorized_elements)
+}
+
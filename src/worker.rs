@@ -1,28 +1,406 @@
 use tokio::sync::mpsc;
-use tokio::sync::Mutex;
+use tokio::time::{interval_at, sleep, Duration};
+use crate::task_model::{Job, Subtask, TaskResult, TaskStatus};
+use fantoccini::Client;
+use parking_lot::Mutex;
+use std::collections::BTreeMap;
+use std::pin::pin;
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
 use std::sync::Arc;
-use crate::task_model::{Subtask, TaskResult};
+use std::time::Instant;
+use chrono::Utc;
+use crate::executor::{run_with_policy, Flow};
+use crate::job_queue::{SharedQueue, DEFAULT_VISIBILITY_TIMEOUT_SECS};
+use crate::run_report::RunReport;
+use crate::variables::Variables;
+use crate::browser_config::BrowserConfig;
+use crate::cancellation::{Cancelled, RunControl, RunState};
+use crate::capabilities::Capabilities;
+use crate::web_interaction::{close_browser, start_browser};
+use crate::observation_layer::interaction_log::{LogContext, LOG_CONTEXT};
 
+/// How long an idle worker waits before asking the queue again
+const IDLE_POLL: Duration = Duration::from_millis(200);
+
+/// How often a paused worker shows it is still alive
+const PAUSE_POLL: Duration = Duration::from_secs(1);
+
+/// How often a worker busy with a job beats and extends its lease, however
+/// long the current subtask takes
+const JOB_BEAT: Duration = Duration::from_secs(10);
+
+/// Shared between a worker and whoever supervises it
+#[derive(Debug, Default)]
+pub struct WorkerControl {
+    drain: AtomicBool,
+    started: AtomicBool, // Its browser came up, or it runs without one
+    last_beat_ms: AtomicI64,
+    client: Mutex<Option<Client>>, // Its browser, so the supervisor can close it after an abort
+}
+
+impl WorkerControl {
+    pub fn new() -> Self {
+        let control = Self::default();
+        control.beat();
+        control
+    }
+
+    /// The worker is alive and making progress
+    pub fn beat(&self) {
+        self.last_beat_ms.store(Utc::now().timestamp_millis(), Ordering::Relaxed);
+    }
+
+    pub fn since_beat(&self) -> chrono::Duration {
+        chrono::Duration::milliseconds(Utc::now().timestamp_millis() - self.last_beat_ms.load(Ordering::Relaxed))
+    }
+
+    /// Ask the worker to stop after its current job and close its browser
+    pub fn drain(&self) {
+        self.drain.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.drain.load(Ordering::Relaxed)
+    }
+
+    pub fn is_started(&self) -> bool {
+        self.started.load(Ordering::Relaxed)
+    }
+
+    /// The browser of a worker that was stopped without closing it
+    pub fn take_client(&self) -> Option<Client> {
+        self.client.lock().take()
+    }
+
+    fn hold(&self, client: &Client) {
+        *self.client.lock() = Some(client.clone());
+    }
+}
+
+/// How a job ended for the worker that ran it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobEnd {
+    Finished,     // Every subtask has a result
+    SessionLost,  // The browser went away mid-job; the job should go to another worker
+    Disconnected, // Nobody listens for results anymore
+    Cancelled,    // Every subtask has a result, but some were cut short
+}
+
+/// Lease and run jobs until the queue is finished, the worker is drained or
+/// `run` is cancelled. While `run` is paused no new subtask starts. A worker
+/// whose `capabilities` list no browser runs without one.
 pub async fn worker_node(
     worker_id: usize,
-    task_rx: Arc<Mutex<mpsc::Receiver<Subtask>>>,
+    browser: BrowserConfig,
+    capabilities: Capabilities,
+    queue: SharedQueue,
     result_tx: mpsc::Sender<TaskResult>,
+    control: Arc<WorkerControl>,
+    run: RunControl,
 ) {
     println!("Worker {} started", worker_id);
 
-    while let Some(task) = task_rx.lock().await.recv().await {
-        println!("Worker {} processing task: {:?}", worker_id, task);
+    let context = LogContext {
+        worker_id: Some(worker_id),
+        ..LogContext::default()
+    };
+    let browser = (!capabilities.browsers.is_empty()).then_some(browser);
+    let start = || async {
+        let Some(browser) = &browser else {
+            return Some(None);
+        };
+        match LOG_CONTEXT.scope(context.clone(), start_browser(browser)).await {
+            Ok(client) => {
+                control.hold(&client);
+                Some(Some(client))
+            }
+            Err(err) => {
+                eprintln!("Worker {}: Failed to start browser client: {:?}", worker_id, err);
+                None
+            }
+        }
+    };
+    let Some(mut client) = start().await else {
+        return;
+    };
+    control.started.store(true, Ordering::Relaxed);
 
-        // Simulate task execution
-        tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+    let visibility = chrono::Duration::seconds(DEFAULT_VISIBILITY_TIMEOUT_SECS);
+    loop {
+        control.beat();
+        // Asked to stop: the current job is done, so leave between jobs
+        if control.is_draining() {
+            println!("Worker {} draining", worker_id);
+            break;
+        }
+        if wait_while_paused(&run, || control.beat()).await.is_err() {
+            println!("Worker {} cancelled", worker_id);
+            break;
+        }
+        let leased = queue.lock().lease(worker_id, &capabilities, visibility);
+        let lease = match leased {
+            Ok(Some(lease)) => lease,
+            Ok(None) => {
+                let finished = queue.lock().is_finished();
+                if finished {
+                    break;
+                }
+                sleep(IDLE_POLL).await;
+                continue;
+            }
+            Err(err) => {
+                eprintln!("Worker {}: {}", worker_id, err);
+                sleep(IDLE_POLL).await;
+                continue;
+            }
+        };
+        let job = lease.job.clone();
+        println!(
+            "Worker {} processing job {}: {} ({} subtasks, attempt {})",
+            worker_id,
+            job.id,
+            job.description,
+            job.subtasks.len(),
+            lease.attempt
+        );
 
-        // Send result back to the coordinator
-        let result = TaskResult {
-            id: task.id,
-            status: format!("Completed by Worker {}", worker_id),
+        let job_run = run.child();
+        let id = lease.id;
+        // Keep the job from being handed out again while we're on it, beating
+        // after every subtask and on a timer so a long one doesn't look hung
+        let lease = Mutex::new(lease);
+        let progress = || {
+            control.beat();
+            let extended = queue.lock().extend(&mut lease.lock(), visibility);
+            if let Err(err) = extended {
+                eprintln!("Worker {}: {}", worker_id, err);
+            }
         };
-        result_tx.send(result).await.unwrap();
+        // Both timers live in this task, so they stop with it
+        let end = {
+            let job_done = run_job(worker_id, client.as_mut(), &job, &context, &result_tx, &job_run, &progress);
+            let mut job_done = pin!(job_done);
+            let mut beat = interval_at(tokio::time::Instant::now() + JOB_BEAT, JOB_BEAT);
+            let mut watch = interval_at(tokio::time::Instant::now() + IDLE_POLL, IDLE_POLL);
+            loop {
+                tokio::select! {
+                    end = &mut job_done => break end,
+                    _ = beat.tick() => progress(),
+                    // Stop mid-subtask if the job gets cancelled while we're on it
+                    _ = watch.tick() => {
+                        if queue.lock().is_cancelling(id) {
+                            job_run.cancel();
+                        }
+                    }
+                }
+            }
+        };
+        let lease = lease.into_inner();
+        match end {
+            JobEnd::Finished => {}
+            JobEnd::Cancelled => {
+                // A WebDriver call may have been cut off halfway, so don't
+                // trust the session with the next job
+                println!("🛑 Worker {} cancelled job {}; restarting its browser", worker_id, job.id);
+                let acked = queue.lock().ack(&lease);
+                if let Err(err) = acked {
+                    eprintln!("Worker {}: {}", worker_id, err);
+                }
+                close(worker_id, &control, client.take()).await;
+                if run.is_cancelled() {
+                    println!("Worker {} exiting", worker_id);
+                    return;
+                }
+                client = match start().await {
+                    Some(client) => client,
+                    None => return,
+                };
+                continue;
+            }
+            JobEnd::SessionLost => {
+                // Nothing this worker does will work anymore; let a healthy
+                // one have the job and leave the supervisor to replace us
+                eprintln!("💥 Worker {} lost its browser session; requeueing job {}", worker_id, job.id);
+                let released = queue.lock().nack(&lease, chrono::Duration::zero());
+                if let Err(err) = released {
+                    eprintln!("Worker {}: {}", worker_id, err);
+                }
+                break;
+            }
+            JobEnd::Disconnected => {
+                eprintln!("Worker {}: coordinator stopped listening for results", worker_id);
+                break;
+            }
+        }
+
+        // Only once every result is out; if we die before this the job is redelivered
+        let acked = queue.lock().ack(&lease);
+        if let Err(err) = acked {
+            eprintln!("Worker {}: {}", worker_id, err);
+        }
     }
 
     println!("Worker {} exiting", worker_id);
+    close(worker_id, &control, client).await;
+}
+
+async fn close(worker_id: usize, control: &WorkerControl, client: Option<Client>) {
+    control.take_client();
+    if let Some(client) = client {
+        if let Err(err) = close_browser(client).await {
+            eprintln!("Worker {}: Failed to close browser: {:?}", worker_id, err);
+        }
+    }
+}
+
+/// Wait out a pause, calling `progress` now and then so the worker doesn't
+/// look hung and keeps its lease. Fails once cancelled.
+pub async fn wait_while_paused(run: &RunControl, mut progress: impl FnMut()) -> Result<(), Cancelled> {
+    if run.state() == RunState::Paused {
+        println!("⏸️ Paused");
+        while run.state() == RunState::Paused {
+            progress();
+            let _ = tokio::time::timeout(PAUSE_POLL, run.changed(RunState::Paused)).await;
+        }
+    }
+    match run.state() {
+        RunState::Cancelled => Err(Cancelled),
+        _ => Ok(()),
+    }
+}
+
+/// Run `job`'s subtasks in order on `client` (`None` on a worker without a
+/// browser), sending each result to
+/// `result_tx` and calling `progress` after each one. A failure with the
+/// browser gone ends the job early without reporting it, so it can be retried
+/// elsewhere. Cancelling `run` abandons the running subtask; it and the rest
+/// are reported cancelled.
+pub async fn run_job(
+    worker_id: usize,
+    mut client: Option<&mut Client>,
+    job: &Job,
+    context: &LogContext,
+    result_tx: &mpsc::Sender<TaskResult>,
+    run: &RunControl,
+    mut progress: impl FnMut(),
+) -> JobEnd {
+    // Subtasks share the browser and run in order; after a failure the
+    // rest of the job would start from the wrong page, so they are skipped
+    let mut vars = Variables::new();
+    for (name, value) in &job.inputs {
+        vars.set(name, value.clone());
+    }
+    let mut failed: Option<usize> = None;
+    let mut cancelled = false;
+    for task in &job.subtasks {
+        cancelled = cancelled || wait_while_paused(run, &mut progress).await.is_err();
+        let result = match failed {
+            _ if cancelled => skipped(worker_id, job.id, task, TaskStatus::Cancelled, "Cancelled before it started"),
+            Some(previous) => skipped(
+                worker_id,
+                job.id,
+                task,
+                TaskStatus::Failed,
+                &format!("Skipped: subtask {} of the same job failed", previous),
+            ),
+            None => {
+                let started = Instant::now();
+                let subtask = run_subtask(worker_id, client.as_deref_mut(), job.id, task, &mut vars, context, run);
+                match run.run(subtask).await {
+                    Ok(result) => result,
+                    Err(Cancelled) => {
+                        cancelled = true;
+                        eprintln!("🛑 Worker {} cancelled task {}", worker_id, task.id);
+                        TaskResult {
+                            duration_ms: started.elapsed().as_millis() as u64,
+                            ..skipped(worker_id, job.id, task, TaskStatus::Cancelled, "Cancelled while running")
+                        }
+                    }
+                }
+            }
+        };
+        if result.status == TaskStatus::Failed && failed.is_none() {
+            if let Some(client) = client.as_deref_mut() {
+                if client.current_url().await.is_err() {
+                    return JobEnd::SessionLost;
+                }
+            }
+            failed = Some(task.id);
+        }
+        if result_tx.send(result).await.is_err() {
+            return JobEnd::Disconnected;
+        }
+        progress();
+    }
+    if cancelled {
+        JobEnd::Cancelled
+    } else {
+        JobEnd::Finished
+    }
+}
+
+/// The result of a subtask that didn't get to run to the end
+fn skipped(worker_id: usize, job_id: usize, task: &Subtask, status: TaskStatus, details: &str) -> TaskResult {
+    TaskResult {
+        id: task.id,
+        job_id,
+        worker_id,
+        status,
+        details: Some(details.to_string()),
+        duration_ms: 0,
+        artifacts: Vec::new(),
+        outputs: BTreeMap::new(),
+    }
+}
+
+/// Run one subtask with the same policy handling (retry, fallback, ...) as
+/// `executor::execute_task`. `vars` carries over between the job's subtasks.
+async fn run_subtask(
+    worker_id: usize,
+    client: Option<&mut Client>,
+    job_id: usize,
+    task: &Subtask,
+    vars: &mut Variables,
+    context: &LogContext,
+    run: &RunControl,
+) -> TaskResult {
+    println!("Worker {} processing task: {:?}", worker_id, task);
+    let started = Instant::now();
+    let mut report = RunReport::start(&task.description);
+    let context = LogContext {
+        run_id: Some(report.run_id.clone()),
+        task_id: Some(task.id.to_string()),
+        step_index: Some(0),
+        ..context.clone()
+    };
+    let flow = LOG_CONTEXT
+        .scope(context, run_with_policy(client, &task.step, "step", vars, &mut report, run))
+        .await;
+    match flow {
+        Flow::Continue => {}
+        Flow::Abort => report.abort(),
+        Flow::Cancelled => report.cancel(),
+    }
+    let report = report.finish();
+
+    let status = if report.passed() {
+        println!("Worker {} completed task {}", worker_id, task.id);
+        TaskStatus::Completed
+    } else {
+        eprintln!("Worker {} failed task {}", worker_id, task.id);
+        TaskStatus::Failed
+    };
+    TaskResult {
+        id: task.id,
+        job_id,
+        worker_id,
+        status,
+        details: report.first_error().map(|err| err.to_string()),
+        duration_ms: started.elapsed().as_millis() as u64,
+        artifacts: report.artifacts(),
+        outputs: vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.clone()))
+            .collect(),
+    }
 }
@@ -0,0 +1,420 @@
+use crate::browser_config::BrowserConfig;
+use crate::cancellation::RunControl;
+use crate::capabilities::{Capabilities, Requirements};
+use crate::job_queue::SharedQueue;
+use crate::scheduling::SchedulingPolicy;
+use crate::task_model::TaskResult;
+use crate::web_interaction::close_browser;
+use crate::worker::{worker_node, WorkerControl};
+use parking_lot::Mutex;
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+/// How many workers to run and when to add or remove them
+#[derive(Debug, Clone, PartialEq)]
+pub struct PoolConfig {
+    pub workers: usize, // Started up front
+    pub min_workers: usize,
+    pub max_workers: usize,
+    pub autoscale: bool,
+    pub scale_interval: Duration,
+    pub target_backlog: Duration, // Grow while waiting jobs would take longer than this to clear
+    pub heartbeat_timeout: Duration, // A worker silent for this long is considered hung
+    pub listen: Option<String>,   // Address remote workers connect to
+    pub scheduling: SchedulingPolicy, // How tenants share the workers
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        PoolConfig {
+            workers: 3,
+            min_workers: 1,
+            max_workers: 8,
+            autoscale: true,
+            scale_interval: Duration::from_secs(5),
+            target_backlog: Duration::from_secs(30),
+            heartbeat_timeout: Duration::from_secs(180),
+            listen: None,
+            scheduling: SchedulingPolicy::default(),
+        }
+    }
+}
+
+impl PoolConfig {
+    /// Defaults overridden by `SAE_WORKERS`, `SAE_MIN_WORKERS`,
+    /// `SAE_MAX_WORKERS`, `SAE_AUTOSCALE` (`false` keeps the pool at its
+    /// starting size), `SAE_TARGET_BACKLOG_SECS`, `SAE_HEARTBEAT_TIMEOUT_SECS`
+    /// and `SAE_LISTEN`, with tenant weights and quotas from
+    /// `SchedulingPolicy::from_env`
+    pub fn from_env() -> Result<Self, String> {
+        let mut config = PoolConfig {
+            scheduling: SchedulingPolicy::from_env()?,
+            ..PoolConfig::default()
+        };
+        let var = |name: &str| env::var(name).ok().filter(|v| !v.trim().is_empty());
+        let number = |name: &str, value: String| {
+            value
+                .trim()
+                .parse::<usize>()
+                .map_err(|_| format!("{} must be a whole number, got '{}'", name, value))
+        };
+
+        if let Some(value) = var("SAE_WORKERS") {
+            config.workers = number("SAE_WORKERS", value)?;
+        }
+        if let Some(value) = var("SAE_MIN_WORKERS") {
+            config.min_workers = number("SAE_MIN_WORKERS", value)?;
+        }
+        if let Some(value) = var("SAE_MAX_WORKERS") {
+            config.max_workers = number("SAE_MAX_WORKERS", value)?;
+        }
+        if let Some(value) = var("SAE_AUTOSCALE") {
+            config.autoscale = matches!(value.trim().to_ascii_lowercase().as_str(), "1" | "true" | "yes" | "on");
+        }
+        if let Some(value) = var("SAE_TARGET_BACKLOG_SECS") {
+            config.target_backlog = Duration::from_secs(number("SAE_TARGET_BACKLOG_SECS", value)? as u64);
+        }
+        if let Some(value) = var("SAE_HEARTBEAT_TIMEOUT_SECS") {
+            config.heartbeat_timeout = Duration::from_secs(number("SAE_HEARTBEAT_TIMEOUT_SECS", value)? as u64);
+        }
+        if let Some(addr) = var("SAE_LISTEN") {
+            config.listen = Some(addr);
+        }
+        config.check()?;
+        Ok(config)
+    }
+
+    /// Without remote workers there has to be room for a local one
+    pub fn check(&self) -> Result<(), String> {
+        if (self.max_workers == 0 && self.listen.is_none()) || self.min_workers > self.max_workers {
+            return Err(format!(
+                "Invalid worker bounds: min {} / max {}",
+                self.min_workers, self.max_workers
+            ));
+        }
+        Ok(())
+    }
+
+    /// Starting size, kept within the bounds
+    pub fn initial_size(&self) -> usize {
+        self.workers.clamp(self.min_workers, self.max_workers)
+    }
+}
+
+/// Every worker the coordinator knows, local or remote, with what it can do.
+/// Shared with the remote worker server so ids never collide.
+#[derive(Debug, Default)]
+pub struct WorkerRegistry {
+    next_id: AtomicUsize,
+    workers: Mutex<BTreeMap<usize, Capabilities>>,
+}
+
+impl WorkerRegistry {
+    /// Add a worker; returns its id
+    pub fn register(&self, capabilities: Capabilities) -> usize {
+        let worker_id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.workers.lock().insert(worker_id, capabilities);
+        worker_id
+    }
+
+    pub fn unregister(&self, worker_id: usize) {
+        self.workers.lock().remove(&worker_id);
+    }
+
+    /// Some registered worker could run a job with `requirements`
+    pub fn can_run(&self, requirements: &Requirements) -> bool {
+        self.workers.lock().values().any(|capabilities| capabilities.satisfies(requirements))
+    }
+}
+
+pub type SharedRegistry = Arc<WorkerRegistry>;
+
+struct PoolWorker {
+    control: Arc<WorkerControl>,
+    handle: JoinHandle<()>,
+}
+
+/// Worker tasks pulling from one queue, each with its own browser session
+pub struct WorkerPool {
+    config: PoolConfig,
+    browser: BrowserConfig,
+    queue: SharedQueue,
+    result_tx: mpsc::Sender<TaskResult>,
+    workers: BTreeMap<usize, PoolWorker>,
+    capabilities: Capabilities, // Of every local worker
+    run: RunControl,            // Pauses or cancels every worker
+    registry: SharedRegistry,
+    latency_ms: HashMap<usize, f64>, // Moving average of subtask time, per worker
+}
+
+impl WorkerPool {
+    /// Start `config.initial_size()` workers, each offering `capabilities`
+    /// and following `run`
+    pub fn start(
+        config: PoolConfig,
+        browser: BrowserConfig,
+        capabilities: Capabilities,
+        queue: SharedQueue,
+        result_tx: mpsc::Sender<TaskResult>,
+        run: RunControl,
+    ) -> Self {
+        let mut pool = WorkerPool {
+            config,
+            browser,
+            capabilities,
+            run,
+            queue,
+            result_tx,
+            workers: BTreeMap::new(),
+            registry: SharedRegistry::default(),
+            latency_ms: HashMap::new(),
+        };
+        for _ in 0..pool.config.initial_size() {
+            pool.spawn();
+        }
+        pool
+    }
+
+    /// Launch one more worker; returns its id
+    pub fn spawn(&mut self) -> usize {
+        let worker_id = self.registry.register(self.capabilities.clone());
+        let control = Arc::new(WorkerControl::new());
+        let handle = tokio::spawn(worker_node(
+            worker_id,
+            self.browser.clone(),
+            self.capabilities.clone(),
+            Arc::clone(&self.queue),
+            self.result_tx.clone(),
+            Arc::clone(&control),
+            self.run.clone(),
+        ));
+        self.workers.insert(worker_id, PoolWorker { control, handle });
+        worker_id
+    }
+
+    /// Ask a worker to stop after its current job and close its browser
+    pub fn drain(&mut self, worker_id: usize) -> bool {
+        match self.workers.get(&worker_id) {
+            Some(worker) => {
+                worker.control.drain();
+                self.latency_ms.remove(&worker_id);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Drain every worker and wait until their browsers are closed
+    pub async fn shutdown(mut self) {
+        let ids: Vec<usize> = self.workers.keys().copied().collect();
+        for worker_id in ids {
+            self.drain(worker_id);
+        }
+        for (worker_id, worker) in self.workers {
+            if let Err(err) = worker.handle.await {
+                eprintln!("❌ Worker {} ended abnormally: {}", worker_id, err);
+            }
+            if let Some(client) = worker.control.take_client() {
+                let _ = close_browser(client).await;
+            }
+        }
+    }
+
+    /// Workers not asked to drain
+    pub fn size(&self) -> usize {
+        self.workers.values().filter(|worker| !worker.control.is_draining()).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.workers.is_empty()
+    }
+
+    /// Feed a result into the latency estimate. Only this pool's running
+    /// workers count: remote and drained ones are never evicted from the
+    /// estimate, so they would skew it.
+    pub fn record(&mut self, result: &TaskResult) {
+        let worker = self.workers.get(&result.worker_id);
+        if worker.is_none_or(|worker| worker.control.is_draining()) {
+            return;
+        }
+        let sample = result.duration_ms as f64;
+        self.latency_ms
+            .entry(result.worker_id)
+            .and_modify(|average| *average = 0.8 * *average + 0.2 * sample)
+            .or_insert(sample);
+    }
+
+    /// Deal with workers that exited or stopped sending heartbeats. Hung
+    /// workers are aborted; either way whatever they held goes back on the
+    /// queue, and a worker that crashed mid-work is replaced.
+    pub async fn reap(&mut self) {
+        let ids: Vec<usize> = self.workers.keys().copied().collect();
+        for worker_id in ids {
+            let worker = &self.workers[&worker_id];
+            let silent = worker.control.since_beat().to_std().unwrap_or_default();
+            if !worker.handle.is_finished() {
+                if silent < self.config.heartbeat_timeout {
+                    continue;
+                }
+                eprintln!("💔 Worker {} sent no heartbeat for {}s; stopping it", worker_id, silent.as_secs());
+                worker.handle.abort();
+            }
+
+            let worker = self.workers.remove(&worker_id).expect("listed above");
+            self.latency_ms.remove(&worker_id);
+            self.registry.unregister(worker_id);
+            let crashed = match worker.handle.await {
+                Ok(()) => false,
+                Err(err) if err.is_panic() => {
+                    eprintln!("💥 Worker {} crashed: {}", worker_id, err);
+                    true
+                }
+                Err(_) => true, // Aborted above
+            };
+            // An aborted worker never got to close its browser
+            if let Some(client) = worker.control.take_client() {
+                if let Err(err) = close_browser(client).await {
+                    eprintln!("Worker {}: Failed to close browser: {:?}", worker_id, err);
+                }
+            }
+            let released = self.queue.lock().release_worker(worker_id);
+            match released {
+                Ok(ids) if !ids.is_empty() => println!("🔁 Requeued job(s) {:?} from worker {}", ids, worker_id),
+                Ok(_) => {}
+                Err(err) => eprintln!("❌ Failed to requeue jobs of worker {}: {}", worker_id, err),
+            }
+            if worker.control.is_draining() || self.run.is_cancelled() {
+                continue;
+            }
+
+            // A worker that never got a browser would fail again the same way
+            let finished = self.queue.lock().is_finished();
+            if (crashed || worker.control.is_started()) && !finished {
+                let replacement = self.spawn();
+                println!("🩹 Replacing worker {} with worker {}", worker_id, replacement);
+            }
+        }
+    }
+
+    /// Size the pool for the current backlog: enough workers to clear the
+    /// waiting jobs' subtasks within `target_backlog` at the observed
+    /// latency, within the configured bounds. Grows at once, shrinks one idle
+    /// worker at a time.
+    pub fn autoscale(&mut self) {
+        if !self.config.autoscale {
+            return;
+        }
+        let (waiting, busy) = {
+            let queue = self.queue.lock();
+            if queue.is_finished() {
+                return; // Workers are on their way out
+            }
+            (queue.ready_subtasks_for(&self.capabilities), queue.busy_workers())
+        };
+        let active: Vec<usize> = self
+            .workers
+            .iter()
+            .filter(|(_, worker)| !worker.control.is_draining())
+            .map(|(&worker_id, _)| worker_id)
+            .collect();
+
+        // Latency is per subtask, and so is the backlog. Until something has
+        // finished, assume a subtask per worker per target.
+        let target_ms = self.config.target_backlog.as_millis().max(1) as f64;
+        let latency_ms = match self.latency_ms.len() {
+            0 => target_ms,
+            n => self.latency_ms.values().sum::<f64>() / n as f64,
+        };
+        let running = active.iter().filter(|worker_id| busy.contains(worker_id)).count();
+        let needed = running + (waiting as f64 * latency_ms / target_ms).ceil() as usize;
+        let desired = needed.clamp(self.config.min_workers, self.config.max_workers);
+
+        if desired > active.len() {
+            println!(
+                "📈 Scaling workers {} -> {} ({} subtasks waiting, {:.0} ms per task)",
+                active.len(),
+                desired,
+                waiting,
+                latency_ms
+            );
+            for _ in active.len()..desired {
+                self.spawn();
+            }
+        } else if desired < active.len() {
+            // Newest idle worker first, so long-lived sessions stay warm
+            if let Some(&worker_id) = active.iter().rev().find(|worker_id| !busy.contains(worker_id)) {
+                println!("📉 Draining worker {} ({} -> {})", worker_id, active.len(), active.len() - 1);
+                self.drain(worker_id);
+            }
+        }
+    }
+
+    pub fn registry(&self) -> SharedRegistry {
+        Arc::clone(&self.registry)
+    }
+
+    /// A job with `requirements` can run here now, or once the pool grows
+    pub fn can_run(&self, requirements: &Requirements) -> bool {
+        self.registry.can_run(requirements)
+            || (self.config.max_workers > 0 && self.capabilities.satisfies(requirements))
+    }
+
+    pub fn scale_interval(&self) -> Duration {
+        self.config.scale_interval
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::job_queue::JobQueue;
+    use crate::task_model::TaskStatus;
+
+    fn result(worker_id: usize, duration_ms: u64) -> TaskResult {
+        TaskResult {
+            id: 0,
+            job_id: 0,
+            worker_id,
+            status: TaskStatus::Completed,
+            details: None,
+            duration_ms,
+            artifacts: Vec::new(),
+            outputs: BTreeMap::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn latency_counts_running_local_workers_only() {
+        let config = PoolConfig {
+            workers: 2,
+            ..PoolConfig::default()
+        };
+        let (result_tx, _results) = mpsc::channel(1);
+        let queue = Arc::new(Mutex::new(JobQueue::new()));
+        // Without a browser the workers start no WebDriver session
+        let mut pool = WorkerPool::start(
+            config,
+            BrowserConfig::default(),
+            Capabilities::default(),
+            queue,
+            result_tx,
+            RunControl::new(),
+        );
+        let remote = pool.registry().register(Capabilities::default());
+
+        pool.record(&result(0, 100));
+        pool.record(&result(0, 200));
+        pool.record(&result(remote, 10_000));
+        assert_eq!(pool.latency_ms, HashMap::from([(0, 120.0)]));
+
+        pool.drain(1);
+        pool.record(&result(1, 10_000));
+        assert_eq!(pool.latency_ms.len(), 1);
+        pool.shutdown().await;
+    }
+}
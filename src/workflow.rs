@@ -0,0 +1,666 @@
+use crate::target::Target;
+use crate::task_model::{unbound_variables, AutomationTask, Condition, ErrorPolicy, Step, TaskInput, TaskStep};
+use serde::de::{self, DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Highest workflow document version this build understands
+pub const WORKFLOW_FORMAT_VERSION: u32 = 1;
+
+/// On-disk representation of an `AutomationTask`.
+///
+/// Workflow documents are meant to be written by hand and checked into git, so
+/// unknown keys are rejected instead of being silently dropped.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WorkflowDocument {
+    pub version: u32,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub inputs: Vec<TaskInput>,
+    // Steps are written as `- OpenWebsite: https://...` in every format rather
+    // than with YAML's `!Tag` enum syntax
+    #[serde(with = "serde_yaml::with::singleton_map_recursive")]
+    pub steps: Vec<Step>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorkflowFormat {
+    Yaml,
+    Json,
+    Toml,
+}
+
+impl WorkflowFormat {
+    /// Pick a format from a file extension (`.yaml`/`.yml`, `.json`, `.toml`)
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "yaml" | "yml" => Some(WorkflowFormat::Yaml),
+            "json" => Some(WorkflowFormat::Json),
+            "toml" => Some(WorkflowFormat::Toml),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for WorkflowFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WorkflowFormat::Yaml => write!(f, "YAML"),
+            WorkflowFormat::Json => write!(f, "JSON"),
+            WorkflowFormat::Toml => write!(f, "TOML"),
+        }
+    }
+}
+
+/// A single schema violation found after the document parsed successfully
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationIssue {
+    pub path: String, // e.g. `steps[3]` or `inputs[0].name`
+    pub message: String,
+    pub location: Option<(usize, usize)>, // Line and column in the document, when parsed from text
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.location {
+            Some((line, column)) => write!(f, "{} (line {}, column {}): {}", self.path, line, column, self.message),
+            None => write!(f, "{}: {}", self.path, self.message),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum WorkflowError {
+    #[error("Failed to access workflow file {path}: {source}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Cannot infer workflow format from file name: {0}")]
+    UnknownFormat(PathBuf),
+    #[error("{format} error at line {line}, column {column}: {message}")]
+    Parse {
+        format: WorkflowFormat,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    #[error("Invalid workflow:\n{}", format_issues(.0))]
+    Invalid(Vec<ValidationIssue>),
+    #[error("Failed to serialize workflow as {format}: {message}")]
+    Serialize {
+        format: WorkflowFormat,
+        message: String,
+    },
+}
+
+fn format_issues(issues: &[ValidationIssue]) -> String {
+    issues
+        .iter()
+        .map(|issue| format!("  - {}", issue))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+impl WorkflowDocument {
+    /// Parse and validate a workflow document
+    pub fn parse(text: &str, format: WorkflowFormat) -> Result<Self, WorkflowError> {
+        let document: WorkflowDocument = match format {
+            WorkflowFormat::Yaml => serde_yaml::from_str(text).map_err(|e| {
+                let (line, column) = e
+                    .location()
+                    .map(|loc| (loc.line(), loc.column()))
+                    .unwrap_or((0, 0));
+                parse_error(format, line, column, e.to_string())
+            })?,
+            WorkflowFormat::Json => serde_json::from_str(text)
+                .map_err(|e| parse_error(format, e.line(), e.column(), e.to_string()))?,
+            WorkflowFormat::Toml => toml::from_str(text).map_err(|e| {
+                let (line, column) = e
+                    .span()
+                    .map(|span| line_column(text, span.start))
+                    .unwrap_or((0, 0));
+                parse_error(format, line, column, e.message().to_string())
+            })?,
+        };
+
+        match document.validate() {
+            Err(WorkflowError::Invalid(mut issues)) => {
+                for issue in &mut issues {
+                    issue.location = locate(text, format, &issue.path);
+                }
+                Err(WorkflowError::Invalid(issues))
+            }
+            validated => validated.map(|()| document),
+        }
+    }
+
+    /// Render the document in the requested format
+    pub fn render(&self, format: WorkflowFormat) -> Result<String, WorkflowError> {
+        let rendered = match format {
+            WorkflowFormat::Yaml => serde_yaml::to_string(self).map_err(|e| e.to_string()),
+            WorkflowFormat::Json => serde_json::to_string_pretty(self).map_err(|e| e.to_string()),
+            WorkflowFormat::Toml => toml::to_string_pretty(self).map_err(|e| e.to_string()),
+        };
+        rendered.map_err(|message| WorkflowError::Serialize { format, message })
+    }
+
+    /// Check the parts of the schema that serde cannot express
+    pub fn validate(&self) -> Result<(), WorkflowError> {
+        let mut issues = Vec::new();
+        let mut issue = |path: String, message: &str| {
+            issues.push(ValidationIssue {
+                path,
+                message: message.to_string(),
+                location: None,
+            })
+        };
+
+        if self.version == 0 || self.version > WORKFLOW_FORMAT_VERSION {
+            issue(
+                "version".to_string(),
+                &format!(
+                    "unsupported version {} (this engine supports up to {})",
+                    self.version, WORKFLOW_FORMAT_VERSION
+                ),
+            );
+        }
+        if self.name.trim().is_empty() {
+            issue("name".to_string(), "must not be empty");
+        }
+
+        let mut seen_inputs = HashSet::new();
+        for (i, input) in self.inputs.iter().enumerate() {
+            if input.name.trim().is_empty() {
+                issue(format!("inputs[{}].name", i), "must not be empty");
+            } else if !seen_inputs.insert(input.name.as_str()) {
+                issue(
+                    format!("inputs[{}].name", i),
+                    &format!("duplicate input '{}'", input.name),
+                );
+            }
+            if let Some(default) = &input.default {
+                if input.kind.parse(default).is_none() {
+                    issue(
+                        format!("inputs[{}].default", i),
+                        &format!("'{}' is not a valid {:?}", default, input.kind),
+                    );
+                }
+            }
+        }
+
+        if self.steps.is_empty() {
+            issue("steps".to_string(), "a workflow needs at least one step");
+        }
+        validate_steps(&self.steps, "steps", &mut issue);
+
+        // Every placeholder must name a declared input or a variable written
+        // by an earlier step
+        let mut declared = seen_inputs.iter().map(|name| name.to_string()).collect();
+        let mut unbound = Vec::new();
+        unbound_variables(&self.steps, "steps", &mut declared, &mut unbound);
+        for (path, err) in unbound {
+            issue(path, &err.to_string());
+        }
+
+        if issues.is_empty() {
+            Ok(())
+        } else {
+            Err(WorkflowError::Invalid(issues))
+        }
+    }
+
+    pub fn into_task(self) -> AutomationTask {
+        AutomationTask {
+            name: self.name,
+            description: self.description,
+            inputs: self.inputs,
+            steps: self.steps,
+            metadata: self.metadata,
+        }
+    }
+
+    pub fn from_task(task: &AutomationTask) -> Self {
+        WorkflowDocument {
+            version: WORKFLOW_FORMAT_VERSION,
+            name: task.name.clone(),
+            description: task.description.clone(),
+            inputs: task.inputs.clone(),
+            steps: task.steps.clone(),
+            metadata: task.metadata.clone(),
+        }
+    }
+}
+
+fn validate_steps(steps: &[Step], path: &str, issue: &mut impl FnMut(String, &str)) {
+    for (i, step) in steps.iter().enumerate() {
+        let step_path = format!("{}[{}]", path, i);
+        if let Err(message) = validate_step(&step.action) {
+            issue(step_path.clone(), &message);
+        }
+        for (label, body, _) in step.action.bodies() {
+            validate_steps(body, &format!("{}.{}", step_path, label), issue);
+        }
+        match &step.on_error {
+            ErrorPolicy::Retry { attempts: 0, .. } => {
+                issue(format!("{}.on_error", step_path), "retry needs at least one attempt");
+            }
+            ErrorPolicy::Fallback(fallback) => {
+                if fallback.is_empty() {
+                    issue(format!("{}.on_error", step_path), "fallback needs at least one step");
+                }
+                validate_steps(fallback, &format!("{}.on_error.fallback", step_path), issue);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn validate_step(step: &TaskStep) -> Result<(), String> {
+    match step {
+        TaskStep::OpenWebsite(url) => {
+            if url.trim().is_empty() {
+                return Err("OpenWebsite needs a URL".to_string());
+            }
+            if !url.contains("://") && !url.starts_with("{{") {
+                return Err(format!("'{}' is not an absolute URL", url));
+            }
+        }
+        TaskStep::ClickElement(target)
+        | TaskStep::WaitForElement(target)
+        | TaskStep::FillForm(target, _)
+        | TaskStep::SelectOption(target, _)
+        | TaskStep::SubmitForm(target) => {
+            validate_target(target)?;
+        }
+        TaskStep::TakeScreenshot(file_path) => {
+            if file_path.trim().is_empty() {
+                return Err("screenshot path must not be empty".to_string());
+            }
+        }
+        TaskStep::CategorizeElements => {}
+        TaskStep::ExtractText(target, variable)
+        | TaskStep::ExtractTable(target, variable)
+        | TaskStep::ExtractAttribute(target, _, variable)
+        | TaskStep::ExtractProperty(target, _, variable) => {
+            validate_target(target)?;
+            validate_variable_name(variable)?;
+        }
+        TaskStep::EvaluateScript(script, variable) => {
+            if script.trim().is_empty() {
+                return Err("script must not be empty".to_string());
+            }
+            validate_variable_name(variable)?;
+        }
+        TaskStep::If { condition, then, .. } => {
+            validate_condition(condition)?;
+            if then.is_empty() {
+                return Err("If needs at least one step under `then`".to_string());
+            }
+        }
+        TaskStep::ForEach { item, steps, .. } => {
+            validate_variable_name(item)?;
+            if steps.is_empty() {
+                return Err("ForEach needs at least one step".to_string());
+            }
+        }
+        TaskStep::While { condition, max_iterations, steps } => {
+            validate_condition(condition)?;
+            if *max_iterations == 0 {
+                return Err("While needs max_iterations of at least 1".to_string());
+            }
+            if steps.is_empty() {
+                return Err("While needs at least one step".to_string());
+            }
+        }
+        TaskStep::CallWorkflow { workflow, into, .. } => {
+            if workflow.trim().is_empty() {
+                return Err("CallWorkflow needs a workflow path".to_string());
+            }
+            if let Some(variable) = into {
+                validate_variable_name(variable)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn validate_target(target: &Target) -> Result<(), String> {
+    match target.problem() {
+        Some(problem) => Err(format!("target {}", problem)),
+        None => Ok(()),
+    }
+}
+
+fn validate_condition(condition: &Condition) -> Result<(), String> {
+    match condition {
+        Condition::ElementExists(target) | Condition::TextContains { selector: target, .. } => {
+            validate_target(target)
+        }
+        Condition::Compare { .. } => Ok(()),
+        Condition::Not(inner) => validate_condition(inner),
+    }
+}
+
+fn validate_variable_name(name: &str) -> Result<(), String> {
+    let valid = !name.is_empty()
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !name.starts_with(|c: char| c.is_ascii_digit());
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("'{}' is not a valid variable name", name))
+    }
+}
+
+fn parse_error(format: WorkflowFormat, line: usize, column: usize, message: String) -> WorkflowError {
+    WorkflowError::Parse {
+        format,
+        line,
+        column,
+        message,
+    }
+}
+
+/// Convert a byte offset into a 1-based (line, column) pair
+fn line_column(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset.min(text.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.rsplit('\n').next().map_or(0, |l| l.chars().count()) + 1;
+    (line, column)
+}
+
+/// One step of a `ValidationIssue` path
+#[derive(Debug, Clone, PartialEq)]
+enum PathPart {
+    Key(String),
+    Index(usize),
+}
+
+/// Split `steps[3].then[0]` into its keys and indices
+fn path_parts(path: &str) -> Vec<PathPart> {
+    let mut parts = Vec::new();
+    for segment in path.split('.') {
+        let (key, mut rest) = segment.split_once('[').map_or((segment, ""), |(key, rest)| (key, rest));
+        if !key.is_empty() {
+            parts.push(PathPart::Key(key.to_string()));
+        }
+        while let Some((index, after)) = rest.split_once(']') {
+            parts.extend(index.parse().ok().map(PathPart::Index));
+            rest = after.trim_start_matches('[');
+        }
+    }
+    parts
+}
+
+/// Marks the error a `Locate` raises once it reaches its node, so the
+/// deserializer tags it with that node's position
+const FOUND: &str = "workflow locator: found";
+
+/// Line and column of the node at `path` in `text`, found by deserializing
+/// the document again and stopping at that node
+fn locate(text: &str, format: WorkflowFormat, path: &str) -> Option<(usize, usize)> {
+    let seed = Locate {
+        path: &path_parts(path),
+        in_list: false,
+    };
+    match format {
+        WorkflowFormat::Yaml => {
+            let err = seed.deserialize(serde_yaml::Deserializer::from_str(text)).err()?;
+            let location = err.location().filter(|_| err.to_string().contains(FOUND))?;
+            Some((location.line(), location.column()))
+        }
+        WorkflowFormat::Json => {
+            let err = seed.deserialize(&mut serde_json::Deserializer::from_str(text)).err()?;
+            (err.to_string().contains(FOUND) && err.line() > 0).then(|| (err.line(), err.column()))
+        }
+        WorkflowFormat::Toml => {
+            let err = seed.deserialize(toml::Deserializer::new(text)).err()?;
+            let span = err.span().filter(|_| err.message().contains(FOUND))?;
+            Some(line_column(text, span.start))
+        }
+    }
+}
+
+/// Walks a document towards `path`, failing with `FOUND` when it gets there.
+/// Issue paths leave out the action name of a step (`steps[3].then`, written
+/// `- If: {then: ...}`), so inside a list item one map level may be skipped.
+struct Locate<'a> {
+    path: &'a [PathPart],
+    in_list: bool, // The current node is a list item
+}
+
+impl<'de> DeserializeSeed<'de> for Locate<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for Locate<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("any value")
+    }
+
+    fn visit_bool<E: de::Error>(self, _: bool) -> Result<(), E> {
+        self.scalar()
+    }
+
+    fn visit_i64<E: de::Error>(self, _: i64) -> Result<(), E> {
+        self.scalar()
+    }
+
+    fn visit_u64<E: de::Error>(self, _: u64) -> Result<(), E> {
+        self.scalar()
+    }
+
+    fn visit_f64<E: de::Error>(self, _: f64) -> Result<(), E> {
+        self.scalar()
+    }
+
+    fn visit_str<E: de::Error>(self, _: &str) -> Result<(), E> {
+        self.scalar()
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<(), E> {
+        self.scalar()
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        let Some((first, rest)) = self.path.split_first() else {
+            return Err(de::Error::custom(FOUND));
+        };
+        let mut i = 0;
+        loop {
+            let found = if *first == PathPart::Index(i) {
+                seq.next_element_seed(Locate { path: rest, in_list: true })?
+            } else {
+                seq.next_element::<IgnoredAny>()?.map(|_| ())
+            };
+            if found.is_none() {
+                return Ok(());
+            }
+            i += 1;
+        }
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        let Some(first) = self.path.first() else {
+            return Err(de::Error::custom(FOUND));
+        };
+        while let Some(key) = map.next_key::<String>()? {
+            if *first == PathPart::Key(key.clone()) {
+                map.next_value_seed(Locate {
+                    path: &self.path[1..],
+                    in_list: false,
+                })?;
+            } else if self.in_list && key != "on_error" {
+                map.next_value_seed(Locate {
+                    path: self.path,
+                    in_list: false,
+                })?;
+            } else {
+                map.next_value::<IgnoredAny>()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Locate<'_> {
+    fn scalar<E: de::Error>(&self) -> Result<(), E> {
+        if self.path.is_empty() {
+            Err(de::Error::custom(FOUND))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Load a workflow file, inferring the format from its extension
+pub fn load_workflow(path: impl AsRef<Path>) -> Result<AutomationTask, WorkflowError> {
+    let path = path.as_ref();
+    let format =
+        WorkflowFormat::from_path(path).ok_or_else(|| WorkflowError::UnknownFormat(path.to_path_buf()))?;
+    let text = fs::read_to_string(path).map_err(|source| WorkflowError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    Ok(WorkflowDocument::parse(&text, format)?.into_task())
+}
+
+/// Save a task as a workflow file, inferring the format from its extension
+pub fn save_workflow(task: &AutomationTask, path: impl AsRef<Path>) -> Result<(), WorkflowError> {
+    let path = path.as_ref();
+    let format =
+        WorkflowFormat::from_path(path).ok_or_else(|| WorkflowError::UnknownFormat(path.to_path_buf()))?;
+    let document = WorkflowDocument::from_task(task);
+    document.validate()?;
+    let text = document.render(format)?;
+    fs::write(path, text).map_err(|source| WorkflowError::Io {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOGIN: &str = "\
+version: 1
+name: login
+inputs:
+  - name: user
+steps:
+  - OpenWebsite: https://example.com/login
+  - FillForm: ['#user', '{{user}}']
+  - ClickElement: '#accept-cookies'
+    on_error: continue
+";
+
+    fn issues(text: &str, format: WorkflowFormat) -> Vec<ValidationIssue> {
+        match WorkflowDocument::parse(text, format) {
+            Err(WorkflowError::Invalid(issues)) => issues,
+            other => panic!("expected validation issues, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parses_yaml() {
+        let document = WorkflowDocument::parse(LOGIN, WorkflowFormat::Yaml).unwrap();
+        assert_eq!(document.name, "login");
+        assert_eq!(document.inputs[0].name, "user");
+        assert_eq!(document.steps.len(), 3);
+        assert_eq!(document.steps[0].action, TaskStep::OpenWebsite("https://example.com/login".to_string()));
+        assert_eq!(document.steps[2].on_error, ErrorPolicy::Continue);
+    }
+
+    #[test]
+    fn round_trips_through_every_format() {
+        let document = WorkflowDocument::parse(LOGIN, WorkflowFormat::Yaml).unwrap();
+        for format in [WorkflowFormat::Yaml, WorkflowFormat::Json, WorkflowFormat::Toml] {
+            let text = document.render(format).unwrap();
+            let parsed = WorkflowDocument::parse(&text, format).unwrap();
+            assert_eq!(parsed.steps, document.steps, "{}", format);
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_keys_with_their_position() {
+        let text = "version: 1\nname: x\nstepz: []\n";
+        match WorkflowDocument::parse(text, WorkflowFormat::Yaml) {
+            Err(WorkflowError::Parse { line, .. }) => assert_eq!(line, 3),
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn reports_every_issue() {
+        let text = "\
+version: 2
+name: ' '
+inputs:
+  - name: a
+  - name: a
+steps:
+  - OpenWebsite: example.com
+  - ExtractText: ['#total', '{{missing}}']
+";
+        let paths: Vec<_> = issues(text, WorkflowFormat::Yaml).into_iter().map(|issue| issue.path).collect();
+        assert!(paths.contains(&"version".to_string()), "{:?}", paths);
+        assert!(paths.contains(&"name".to_string()), "{:?}", paths);
+        assert!(paths.contains(&"inputs[1].name".to_string()), "{:?}", paths);
+        assert!(paths.contains(&"steps[0]".to_string()), "{:?}", paths);
+    }
+
+    #[test]
+    fn rejects_unbound_placeholders() {
+        let text = "version: 1\nname: x\nsteps:\n  - OpenWebsite: '{{site}}'\n";
+        let issues = issues(text, WorkflowFormat::Yaml);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].path, "steps[0]");
+        assert!(issues[0].message.contains("site"), "{}", issues[0].message);
+    }
+
+    #[test]
+    fn locates_issues_in_every_format() {
+        let yaml = "version: 1\nname: x\nsteps:\n  - OpenWebsite: https://a.example\n  - OpenWebsite: nowhere\n";
+        assert_eq!(issues(yaml, WorkflowFormat::Yaml)[0].location, Some((5, 5)));
+
+        let json = r#"{
+  "version": 1,
+  "name": "x",
+  "steps": [
+    {"OpenWebsite": "nowhere"}
+  ]
+}"#;
+        let located = issues(json, WorkflowFormat::Json);
+        assert_eq!(located[0].location.map(|(line, _)| line), Some(5));
+
+        let toml = "version = 1\nname = \"x\"\n\n[[steps]]\nOpenWebsite = \"nowhere\"\n";
+        let located = issues(toml, WorkflowFormat::Toml);
+        assert_eq!(located[0].location.map(|(line, _)| line), Some(4));
+        assert!(located[0].to_string().starts_with("steps[0] (line 4, column"), "{}", located[0]);
+    }
+
+    #[test]
+    fn picks_the_format_from_the_extension() {
+        assert_eq!(WorkflowFormat::from_path(Path::new("a.YML")), Some(WorkflowFormat::Yaml));
+        assert_eq!(WorkflowFormat::from_path(Path::new("a.toml")), Some(WorkflowFormat::Toml));
+        assert_eq!(WorkflowFormat::from_path(Path::new("a.txt")), None);
+    }
+}